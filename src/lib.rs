@@ -15,10 +15,37 @@
 pub mod utils;
 
 pub use utils::cli;
+pub use utils::compression_utils;
+pub use utils::crawler;
+pub use utils::css_utils;
+pub use utils::dir_listing;
+pub use utils::download_log;
+pub use utils::downloader;
 pub use utils::enums;
 pub use utils::error;
 pub use utils::file_utils;
+pub use utils::ftp;
+pub use utils::html_utils;
 pub use utils::http;
+pub use utils::http3;
+pub use utils::json_output;
+pub use utils::link_converter;
+pub use utils::local_url;
+pub use utils::metalink;
+pub use utils::mirrors;
+pub use utils::progress;
 pub use utils::proxy_utils;
+pub use utils::report;
 pub use utils::resolver_utils;
+pub use utils::resume_utils;
+pub use utils::robots;
+pub use utils::scheduler;
+pub use utils::sitemap;
+pub use utils::tls_utils;
+pub use utils::transport;
+pub use utils::url_filter;
 pub use utils::url_utils;
+pub use utils::webdav;
+
+pub use utils::downloader::{DownloadOutcome, DownloadRequest, Downloader};
+pub use utils::progress::{DownloadEvent, ProgressObserver};
@@ -12,98 +12,736 @@
 // MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
 // GNU General Public License for more details.
 
+use nget::crawler::{CrawlEntry, Crawler, SpiderEntry};
+use nget::download_log::DownloadLog;
+use nget::downloader::parse_header;
+use nget::enums::OutputFormat;
 use nget::error::NgetError;
-use nget::http;
+use nget::ftp::{expand_glob, has_wildcards, is_ftp_url};
+use nget::json_output::{JsonObserver, JsonWriter};
+use nget::link_converter::convert_links;
+use nget::metalink::{download_files, load_metalink};
+use nget::mirrors::parse_url_list;
+use nget::report::{Report, ReportFormat};
+use nget::scheduler::Scheduler;
+use nget::sitemap::{collect_urls, discover_sitemaps};
+use nget::tls_utils::TlsConfig;
+use nget::url_filter::UrlFilter;
+use nget::url_utils::mirror_path;
+use nget::webdav::{is_dav_url, to_http_url};
 use nget::{cli, proxy_utils::ProxyConfig};
+use nget::{DownloadEvent, DownloadOutcome, Downloader, ProgressObserver};
 
 use clap::Parser;
-use futures::future;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+
+use reqwest::StatusCode;
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing_subscriber::EnvFilter;
+
+use url::Url;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = cli::Args::parse();
-    let urls = args.urls;
+    let mut urls = args.urls;
     let output_dir = args.output_dir;
-    let retries = args.retries;
-    let delay = args.delay;
     let verbose = args.verbose;
     let quiet = args.quiet;
+    let json = args.output_format == OutputFormat::Json;
+
+    // Verbose output shows the library's attempt and retry logs unless RUST_LOG says otherwise
+    let filter = match std::env::var(EnvFilter::DEFAULT_ENV) {
+        Err(_) if verbose => EnvFilter::new("nget=info"),
+        _ => EnvFilter::from_default_env(),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+
+    // Each line of an input file gives a URL, followed by its mirrors
+    let mut mirrors: HashMap<String, Vec<String>> = HashMap::new();
+    if let Some(path) = &args.input_file {
+        let content = match path.as_str() {
+            "-" => std::io::read_to_string(std::io::stdin())?,
+            path => std::fs::read_to_string(path)
+                .map_err(|e| NgetError::FileError(format!("Failed to read {}: {}", path, e)))?,
+        };
+        for mut line in parse_url_list(&content) {
+            let url = line.remove(0);
+            if !line.is_empty() {
+                mirrors.insert(url.clone(), line);
+            }
+            urls.push(url);
+        }
+    }
+    if !args.mirrors.is_empty() {
+        if urls.len() != 1 {
+            return Err(NgetError::ConfigurationError(
+                "--mirror needs exactly one URL".to_string(),
+            )
+            .into());
+        }
+        mirrors
+            .entry(urls[0].clone())
+            .or_default()
+            .extend(args.mirrors.iter().cloned());
+    }
+
+    let tls_config = TlsConfig {
+        ca_certificate: args.ca_certificate.clone(),
+        ca_directory: args.ca_directory.clone(),
+        certificate: args.certificate.clone(),
+        private_key: args.private_key.clone(),
+        no_check_certificate: args.no_check_certificate,
+        pinned_pubkeys: args.pinned_pubkeys.clone(),
+    };
+
+    if tls_config.no_check_certificate {
+        eprintln!(
+            "WARNING: --no-check-certificate is set. Server certificates will NOT be validated,"
+        );
+        eprintln!("WARNING: so downloads are open to interception and tampering.");
+    }
+
+    let config = ProxyConfig {
+        proxy_url: args.proxy_url.clone(),
+        proxy_user: args.proxy_user.clone(),
+        proxy_password: args.proxy_pass.clone(),
+    };
+
+    // --retries counts attempts, so one attempt is always made
+    let mut downloader = Downloader::new()
+        .http_version(args.http_version.clone())
+        .compression(args.compression)
+        .proxy(config)
+        .tls(tls_config)
+        .retries(args.retries.max(1) as u32 - 1)
+        .retry_delay(Duration::from_secs(args.delay))
+        .ftp_active(args.ftp_active)
+        .mirror_order(args.mirror_order)
+        .mirror_segments(args.mirror_segments);
+    if let Some(timeout) = args.connect_timeout {
+        downloader = downloader.connect_timeout(Duration::from_secs(timeout));
+    }
+    if let Some(timeout) = args.read_timeout {
+        downloader = downloader.read_timeout(Duration::from_secs(timeout));
+    }
+    for line in &args.headers {
+        let (name, value) = parse_header(line)?;
+        downloader = downloader.header(name, value);
+    }
+
+    // JSON events replace the progress bars, and report each file's hash on completion
+    let json_writer = match json {
+        true => {
+            downloader = downloader.checksum(true);
+            Some(JsonWriter::new(json_output(args.json_fd)?))
+        }
+        false => None,
+    };
+    let progress_interval = Duration::from_millis(args.progress_interval);
+
+    let log = match (&args.output_file, &args.append_output) {
+        (Some(path), _) => Some(DownloadLog::open(Path::new(path), false)?),
+        (None, Some(path)) => Some(DownloadLog::open(Path::new(path), true)?),
+        (None, None) => None,
+    };
+
+    // Check the report's format now rather than after every download has finished
+    let report = match &args.report {
+        Some(path) => {
+            ReportFormat::from_path(Path::new(path))?;
+            Some(Report::new())
+        }
+        None => None,
+    };
 
-    tracing_subscriber::fmt::init();
+    let mut filter = UrlFilter::new()
+        .accept(args.accept.clone())
+        .reject(args.reject.clone())
+        .domains(args.domains.clone())
+        .exclude_domains(args.exclude_domains.clone())
+        .include_directories(args.include_directories.clone())
+        .exclude_directories(args.exclude_directories.clone())
+        .no_parent(args.no_parent);
+    if let Some(pattern) = &args.accept_regex {
+        filter = filter.accept_regex(pattern)?;
+    }
+    if let Some(pattern) = &args.reject_regex {
+        filter = filter.reject_regex(pattern)?;
+    }
+
+    let scheduler = Scheduler::new()
+        .wait(Duration::from_secs_f64(args.wait))
+        .random_wait(args.random_wait);
+    let robots = robots_enabled(&args.execute)?;
 
     // Create MultiProgress instance
     let multi_progress = MultiProgress::new();
 
+    // The first Ctrl-C or SIGTERM stops downloads cleanly, a second one exits immediately
+    let signal_downloader = downloader.clone();
+    let signal_progress = multi_progress.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = signal_progress.println(
+            "Stopping downloads and saving their progress. Press Ctrl-C again to exit immediately.",
+        );
+        signal_downloader.cancel();
+
+        shutdown_signal().await;
+        std::process::exit(130);
+    });
+
+    // dav:// and davs:// URLs are WebDAV collections served over HTTP(S)
+    let webdav = args.webdav || urls.iter().any(|url| is_dav_url(url));
+    let urls: Vec<String> = urls.iter().map(|url| to_http_url(url)).collect();
+
+    // FTP has no pages to follow links in, so recursive FTP downloads walk its listings
+    let ftp_listing = args.recursive && urls.iter().all(|url| is_ftp_url(url));
+    let listing = args.recursive_listing || webdav || ftp_listing;
+
+    // Page requisites alone don't follow any hyperlinks
+    let level = match args.recursive || args.recursive_listing || webdav {
+        true => (args.level > 0).then_some(args.level),
+        false => Some(0),
+    };
+
+    // Pages listed in sitemaps are fetched along with the given URLs
+    let use_sitemaps = !args.sitemaps.is_empty() || args.discover_sitemaps;
+    let mut urls = urls;
+    if use_sitemaps {
+        let pages = sitemap_pages(
+            &args.sitemaps,
+            args.discover_sitemaps,
+            &urls,
+            &downloader,
+            (args.sitemap_lastmod && !args.spider).then_some(Path::new(&output_dir)),
+        )
+        .await;
+        urls.extend(pages);
+    }
+
+    // The spider only checks URLs, so it never needs the output directory
+    if args.spider {
+        let crawler = Crawler::new(downloader.clone())
+            .level(level)
+            .page_requisites(args.page_requisites)
+            .filter(filter)
+            .robots(robots && args.recursive)
+            .scheduler(scheduler);
+        return spider(&crawler, &urls, &downloader, json, log.as_ref()).await;
+    }
+
     // Ensure the output directory exists
     tokio::fs::create_dir_all(&output_dir)
         .await
         .map_err(|e| NgetError::FileError(format!("Failed to create directory: {}", e)))?;
 
-    // Download tasks
-    let download_tasks: Vec<_> = urls.into_iter().map(|url| {
-        let output_dir = output_dir.clone();
-        let url = url.clone();
-        let http_version = args.http_version.clone();
-        let output_file_name = args.output_file_name.clone();
-
-        let config = ProxyConfig {
-            proxy_url: args.proxy_url.clone(),
-            proxy_user: args.proxy_user.clone(),
-            proxy_password: args.proxy_pass.clone(),
-        };
+    // Each download reports to the display, and to the log and report if enabled
+    let observers = {
+        let multi_progress = multi_progress.clone();
+        let log = log.clone();
+        let report = report.clone();
+        Arc::new(move |url: &str| -> Arc<dyn ProgressObserver> {
+            let display: Arc<dyn ProgressObserver> = match &json_writer {
+                Some(writer) => Arc::new(JsonObserver::new(writer.clone(), url, progress_interval)),
+                None => {
+                    let pb = if quiet {
+                        ProgressBar::hidden()
+                    } else {
+                        let pb = multi_progress.add(ProgressBar::new_spinner());
+                        pb.set_style(
+                            ProgressStyle::default_spinner()
+                                .template("{msg} [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}) eta: ({eta})")
+                                .unwrap()
+                                .progress_chars("=> "),
+                        );
+                        pb
+                    };
+                    Arc::new(ProgressBarObserver {
+                        progress_bar: pb,
+                        url: url.to_string(),
+                    })
+                }
+            };
+
+            let mut observers = vec![display];
+            if let Some(log) = &log {
+                observers.push(Arc::new(log.observer(url)));
+            }
+            if let Some(report) = &report {
+                observers.push(Arc::new(report.observer(url)));
+            }
+            Arc::new(observers)
+        })
+    };
+
+    let crawl =
+        args.recursive || args.recursive_listing || webdav || args.page_requisites || use_sitemaps;
+    let entries: Vec<CrawlEntry> = if let Some(source) = &args.metalink {
+        // A Metalink that can't be read is reported like a failed download
+        match load_metalink(&downloader, source).await {
+            Ok(metalink) => {
+                download_files(
+                    &downloader,
+                    &metalink,
+                    Path::new(&output_dir),
+                    &scheduler,
+                    args.preferred_location.as_deref(),
+                    &*observers,
+                )
+                .await
+            }
+            Err(e) => {
+                observers(source).on_event(&DownloadEvent::Failed {
+                    error: e.to_string(),
+                    kind: e.kind(),
+                });
+                vec![CrawlEntry {
+                    url: source.clone(),
+                    referrer: None,
+                    depth: 0,
+                    result: Err(e),
+                }]
+            }
+        }
+    } else if crawl {
+        let crawler = Crawler::new(downloader.clone())
+            .output_dir(&output_dir)
+            .level(level)
+            .page_requisites(args.page_requisites)
+            .filter(filter)
+            .robots(robots && !webdav)
+            .timestamping(args.timestamping)
+            .webdav(webdav)
+            .scheduler(scheduler)
+            .observers(move |url| observers(url));
+
+        match listing {
+            true => crawler.crawl_listing(&urls).await,
+            false => crawler.crawl(&urls).await,
+        }
+    } else {
+        // FTP wildcards stand for every file they match in the directory
+        let mut unmatched = Vec::new();
+        let mut expanded = Vec::new();
+        for url in urls {
+            if !has_wildcards(&url) {
+                expanded.push(url);
+                continue;
+            }
+            match expand_glob(&downloader, &url).await {
+                Ok(matches) => expanded.extend(matches),
+                Err(e) => {
+                    observers(&url).on_event(&DownloadEvent::Failed {
+                        error: e.to_string(),
+                        kind: e.kind(),
+                    });
+                    unmatched.push(CrawlEntry {
+                        url,
+                        referrer: None,
+                        depth: 0,
+                        result: Err(e),
+                    });
+                }
+            }
+        }
+
+        // Filters apply to a list of URLs too
+        let urls: Vec<String> = expanded
+            .into_iter()
+            .filter(|url| {
+                let allowed = Url::parse(url).map_or(true, |parsed| {
+                    filter.allows(&parsed, &[]) && filter.accepts_file_name(&parsed)
+                });
+                if !allowed {
+                    log::info!("Skipping {} as it is rejected by the filters", url);
+                }
+                allowed
+            })
+            .collect();
+
+        let requests: Vec<_> = urls
+            .iter()
+            .map(|url| {
+                // The observer finishes or abandons the progress bar, or reports the result
+                let mut request = downloader
+                    .request(url)
+                    .output_dir(&output_dir)
+                    .observer(observers(url));
+                if let Some(name) = &args.output_file_name {
+                    request = request.file_name(name);
+                }
+                if let Some(mirrors) = mirrors.get(url) {
+                    request = request.mirrors(mirrors.clone());
+                }
+                request
+            })
+            .collect();
+
+        let results = scheduler.run(requests).await;
+        unmatched
+            .into_iter()
+            .chain(urls.iter().zip(results).map(|(url, result)| CrawlEntry {
+                url: url.clone(),
+                referrer: None,
+                depth: 0,
+                result,
+            }))
+            .collect()
+    };
+
+    // Rewrite links in the saved pages once everything they might point to is downloaded
+    if args.convert_links && !downloader.is_cancelled() {
+        let converted = convert_links(&entries, args.backup_converted).await;
+        log::info!("Converted links in {} files", converted);
+    }
+
+    // Handle task results
+    let (mut completed, mut cancelled, mut failed) = (0, 0, 0);
+    for entry in &entries {
+        match &entry.result {
+            Ok(_) => completed += 1,
+            Err(NgetError::Cancelled(_)) => cancelled += 1,
+            Err(_) => failed += 1,
+        }
+    }
+
+    if let Some(log) = &log {
+        log.write(&format!(
+            "Finished: {} completed, {} cancelled, {} failed",
+            completed, cancelled, failed
+        ));
+    }
 
-        let pb = if quiet {
-            ProgressBar::hidden()
-        } else {
-            let pb = multi_progress.add(ProgressBar::new_spinner());
-            pb.set_style(
-                ProgressStyle::default_spinner()
-                    .template("{msg} [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}) eta: ({eta})")
-                    .unwrap()
-                    .progress_chars("=> "),
-            );
-            pb
+    // The report covers failed and cancelled downloads too
+    if let (Some(report), Some(path)) = (&report, &args.report) {
+        report.write(Path::new(path)).await?;
+    }
+
+    if downloader.is_cancelled() {
+        eprintln!(
+            "Interrupted: {} completed, {} cancelled, {} failed. Run the same command again to resume.",
+            completed, cancelled, failed
+        );
+        std::process::exit(130);
+    }
+    if failed > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Gets the pages listed in the given sitemaps, and in those found in robots.txt if
+/// `discover` is set. With a mirror directory, pages whose `<lastmod>` is no newer than
+/// their local copy are left out.
+async fn sitemap_pages(
+    sitemaps: &[String],
+    discover: bool,
+    urls: &[String],
+    downloader: &Downloader,
+    mirror_dir: Option<&Path>,
+) -> Vec<String> {
+    let mut sitemaps = sitemaps.to_vec();
+    if discover {
+        let sites: Vec<Url> = urls.iter().filter_map(|url| Url::parse(url).ok()).collect();
+        for sitemap in discover_sitemaps(downloader, &sites).await {
+            if !sitemaps.contains(&sitemap) {
+                sitemaps.push(sitemap);
+            }
+        }
+    }
+
+    let mut pages = Vec::new();
+    for sitemap in &sitemaps {
+        let entries = match collect_urls(downloader, sitemap).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Failed to read sitemap {}: {}", sitemap, e);
+                continue;
+            }
         };
+        log::info!("Found {} pages in sitemap {}", entries.len(), sitemap);
+
+        for entry in entries {
+            let unmodified = match (mirror_dir, Url::parse(&entry.url)) {
+                (Some(dir), Ok(url)) => entry.is_unmodified(&dir.join(mirror_path(&url))),
+                _ => false,
+            };
+            if unmodified {
+                log::info!(
+                    "Skipping {} as it hasn't changed since it was saved",
+                    entry.url
+                );
+            } else {
+                pages.push(entry.url);
+            }
+        }
+    }
+    pages
+}
+
+/// Checks the URLs, or every page the crawl reaches with -r, without saving anything.
+/// Each result is also written to the log, if there is one.
+/// Exits with status 8 if any links are broken, like wget.
+async fn spider(
+    crawler: &Crawler,
+    urls: &[String],
+    downloader: &Downloader,
+    json: bool,
+    log: Option<&DownloadLog>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let entries = crawler.spider(urls).await;
 
-        tokio::spawn(async move {
-            let mut attempt = 0;
-            loop {
-                attempt += 1;
-                if verbose {
-                    eprintln!("Attempt {} for URL: {}", attempt, url);
+    let mut broken = Vec::new();
+    for entry in &entries {
+        let line = match &entry.result {
+            Ok(probe) => {
+                let mut line = format!("{} {}", probe.status.as_u16(), entry.url);
+                if probe.final_url != entry.url {
+                    line.push_str(&format!(" -> {}", probe.final_url));
                 }
+                if let Some(content_type) = &probe.content_type {
+                    line.push_str(&format!(" [{}]", content_type));
+                }
+                if let Some(size) = probe.size {
+                    line.push_str(&format!(" {}", HumanBytes(size)));
+                }
+                line
+            }
+            Err(e) => format!("ERR {}: {}", entry.url, e),
+        };
+        match json {
+            true => println!("{}", spider_json(entry)),
+            false => println!("{}", line),
+        }
+        if let Some(log) = log {
+            log.write(&format!("Checked {}", line));
+        }
 
-                match http::download_file(&url, &output_dir, &output_file_name.as_deref(), &pb, &http_version, &config).await {
-                    Ok(_) => {
-                        // There is no use for this Ok match case, but you need it for compliation.
-                        // utils/http.rs handles all of the previous progress bar messages etc.
-                        break;
-                    }
-                    Err(e) if attempt < retries => {
-                        if verbose {
-                            eprintln!("Attempt {} failed for URL {}: {:?}. Retrying...", attempt, url, e);
-                        }
-                        tokio::time::sleep(std::time::Duration::from_secs(delay)).await;
-                    }
-                    Err(e) => {
-                        pb.abandon_with_message(format!("Failed to download {}: {:?}", url, e));
-                        break;
+        let is_broken = match &entry.result {
+            Ok(probe) => probe.is_broken(),
+            Err(NgetError::Cancelled(_)) => false,
+            Err(_) => true,
+        };
+        if is_broken {
+            broken.push(entry);
+        }
+    }
+
+    if !json && !broken.is_empty() {
+        println!();
+        println!("Found {} broken links:", broken.len());
+        for entry in &broken {
+            let problem = match &entry.result {
+                Ok(probe) => probe.status.to_string(),
+                Err(e) => e.to_string(),
+            };
+            println!("{} ({})", entry.url, problem);
+            match &entry.referrer {
+                Some(referrer) => println!("    referred by {}", referrer),
+                None => println!("    given on the command line"),
+            }
+        }
+    }
+
+    if let Some(log) = log {
+        log.write(&format!(
+            "Finished: {} checked, {} broken",
+            entries.len(),
+            broken.len()
+        ));
+    }
+
+    if downloader.is_cancelled() {
+        std::process::exit(130);
+    }
+    if !broken.is_empty() {
+        std::process::exit(8);
+    }
+
+    Ok(())
+}
+
+/// Describes a spider result as a JSON line.
+fn spider_json(entry: &SpiderEntry) -> serde_json::Value {
+    let mut value = serde_json::json!({
+        "event": "spider",
+        "url": entry.url,
+        "referrer": entry.referrer,
+        "depth": entry.depth,
+    });
+    match &entry.result {
+        Ok(probe) => {
+            value["status"] = probe.status.as_u16().into();
+            value["final_url"] = probe.final_url.clone().into();
+            value["content_type"] = probe.content_type.clone().into();
+            value["size"] = probe.size.into();
+            value["broken"] = probe.is_broken().into();
+        }
+        Err(e) => {
+            value["kind"] = e.kind().into();
+            value["error"] = e.to_string().into();
+            value["broken"] = true.into();
+        }
+    }
+    value
+}
+
+/// Reads the `robots` setting from `-e` commands, the only one supported so far.
+fn robots_enabled(commands: &[String]) -> Result<bool, NgetError> {
+    let mut robots = true;
+
+    for command in commands {
+        let (name, value) = command.split_once('=').ok_or_else(|| {
+            NgetError::ConfigurationError(format!(
+                "Command must be given as 'name=value': {}",
+                command
+            ))
+        })?;
+        match name.trim().to_ascii_lowercase().as_str() {
+            "robots" => {
+                robots = match value.trim().to_ascii_lowercase().as_str() {
+                    "on" | "yes" | "1" => true,
+                    "off" | "no" | "0" => false,
+                    _ => {
+                        return Err(NgetError::ConfigurationError(format!(
+                            "robots must be 'on' or 'off': {}",
+                            command
+                        )))
                     }
+                };
+            }
+            _ => {
+                return Err(NgetError::ConfigurationError(format!(
+                    "Unknown command: {}",
+                    command
+                )))
+            }
+        }
+    }
+
+    Ok(robots)
+}
+
+/// Opens the output for JSON events: stdout, or a file descriptor given by the caller.
+fn json_output(fd: Option<i32>) -> Result<Box<dyn Write + Send>, NgetError> {
+    let Some(fd) = fd else {
+        return Ok(Box::new(std::io::stdout()));
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::FromRawFd;
+
+        // SAFETY: the descriptor was handed to nget for writing, and nothing else in
+        // the process owns it. It is checked to be open before use.
+        let file = unsafe { std::fs::File::from_raw_fd(fd) };
+        if let Err(e) = file.metadata() {
+            std::mem::forget(file);
+            return Err(NgetError::ConfigurationError(format!(
+                "File descriptor {} is not open: {}",
+                fd, e
+            )));
+        }
+        Ok(Box::new(file))
+    }
+
+    #[cfg(not(unix))]
+    Err(NgetError::ConfigurationError(format!(
+        "--json-fd {} is only supported on Unix",
+        fd
+    )))
+}
+
+/// Waits for Ctrl-C, or SIGTERM on Unix.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
                 }
             }
-        })
-    }).collect();
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
 
-    // Wait for all tasks to complete
-    let results = future::join_all(download_tasks).await;
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
 
-    // Handle task results
-    for (i, result) in results.into_iter().enumerate() {
-        if let Err(err) = result {
-            eprintln!("Task {} panicked: {:?}", i, err);
+/// Draws the progress of a download on an indicatif progress bar.
+struct ProgressBarObserver {
+    progress_bar: ProgressBar,
+    url: String,
+}
+
+impl ProgressObserver for ProgressBarObserver {
+    fn on_event(&self, event: &DownloadEvent) {
+        let pb = &self.progress_bar;
+
+        match event {
+            DownloadEvent::Started { path, .. } => {
+                let file_name = path.file_name().unwrap_or_default();
+                pb.set_message(file_name.to_string_lossy().to_string());
+            }
+            DownloadEvent::HeadersReceived {
+                content_length,
+                resume_from,
+                ..
+            } => {
+                if let Some(total_length) = content_length {
+                    pb.set_length(total_length + resume_from);
+                }
+                pb.set_position(*resume_from);
+            }
+            DownloadEvent::Bytes { received } => pb.inc(*received),
+            DownloadEvent::Retrying { .. } => {}
+            DownloadEvent::Finished { outcome } => {
+                pb.finish_with_message(finished_message(outcome))
+            }
+            DownloadEvent::Failed { error, .. } => {
+                pb.abandon_with_message(format!("Failed to download {}: {}", self.url, error))
+            }
+            DownloadEvent::Cancelled => {
+                pb.abandon_with_message(format!("Cancelled: {}", pb.message()))
+            }
         }
     }
+}
 
-    Ok(())
+/// Creates the completion message, including the decoded size if the body was compressed.
+fn finished_message(outcome: &DownloadOutcome) -> String {
+    let path = outcome.path.display();
+
+    match &outcome.content_encoding {
+        _ if outcome.status == StatusCode::RANGE_NOT_SATISFIABLE => {
+            format!("Already downloaded: {}", path)
+        }
+        Some(encoding) => format!(
+            "Saved to: {} ({} decoded from {} {})",
+            path,
+            HumanBytes(outcome.bytes),
+            HumanBytes(outcome.received),
+            encoding
+        ),
+        None => format!("Saved to: {}", path),
+    }
 }
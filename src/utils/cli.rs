@@ -12,7 +12,7 @@
 // MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
 // GNU General Public License for more details.
 
-use crate::enums::HttpVersion;
+use crate::enums::{Compression, HttpVersion, MirrorOrder, OutputFormat};
 
 use clap::Parser;
 
@@ -27,14 +27,155 @@ pub struct Args {
     #[arg(short, long, value_delimiter = ' ', num_args = 1..)]
     pub urls: Vec<String>,
 
+    /// Read URLs from a file, one per line, or '-' for standard input. Tab-separated URLs
+    /// on a line are mirrors of the same file
+    #[arg(short = 'i', long, value_name = "FILE")]
+    pub input_file: Option<String>,
+
+    /// Another URL of the file, tried if a download fails and resuming what was saved.
+    /// Needs a single URL, and can be given several times
+    #[arg(long = "mirror", value_name = "URL", conflicts_with_all = ["recursive", "recursive_listing", "webdav", "page_requisites", "spider", "sitemaps", "discover_sitemaps", "metalink"])]
+    pub mirrors: Vec<String>,
+
+    /// Order to try a file's mirrors in
+    #[arg(long, default_value_t = MirrorOrder::Listed, value_enum)]
+    pub mirror_order: MirrorOrder,
+
+    /// Split new downloads that have HTTP(S) mirrors into segments, downloaded from all of
+    /// them at once
+    #[arg(long)]
+    pub mirror_segments: bool,
+
     /// File Path - Output Directory
     #[arg(short = 'P', long, default_value = "./")]
     pub output_dir: String,
 
     /// File Path - File Name
-    #[arg(short = 'O', long, default_value = None)]
+    #[arg(short = 'O', long, default_value = None, conflicts_with_all = ["recursive", "recursive_listing", "webdav", "page_requisites", "sitemaps", "discover_sitemaps"])]
     pub output_file_name: Option<String>,
 
+    /// Download pages recursively, following their links on the same hosts and saving
+    /// each host's files under a directory named after it. FTP directories are walked
+    /// through their listings
+    #[arg(short = 'r', long)]
+    pub recursive: bool,
+
+    /// Mirror the trees of directory listings (Apache, nginx, lighttpd or Python autoindex
+    /// pages, or nginx's JSON autoindex), walking into each subdirectory
+    #[arg(long, conflicts_with_all = ["recursive", "page_requisites", "spider", "sitemaps", "discover_sitemaps"])]
+    pub recursive_listing: bool,
+
+    /// Download WebDAV collections recursively, listing them with PROPFIND. Implied by
+    /// dav:// and davs:// URLs
+    #[arg(long, conflicts_with_all = ["recursive", "recursive_listing", "page_requisites", "spider", "sitemaps", "discover_sitemaps"])]
+    pub webdav: bool,
+
+    /// With --recursive-listing, WebDAV or recursive FTP, skip files the listing shows are
+    /// no newer than the local copy, and give saved files the listed modification time
+    #[arg(short = 'N', long)]
+    pub timestamping: bool,
+
+    /// Use active FTP, where the server connects back for each transfer, instead of passive
+    #[arg(long)]
+    pub ftp_active: bool,
+
+    /// Maximum recursion depth, or 0 or 'inf' for no limit
+    #[arg(short = 'l', long, value_name = "DEPTH", default_value = "5", value_parser = parse_level)]
+    pub level: u32,
+
+    /// Also download the images, stylesheets, scripts and fonts needed to display each page,
+    /// from any host
+    #[arg(short = 'p', long)]
+    pub page_requisites: bool,
+
+    /// After downloading, rewrite links in the saved HTML and CSS to point at the local
+    /// copies, or at the full URL of anything that wasn't downloaded
+    #[arg(short = 'k', long)]
+    pub convert_links: bool,
+
+    /// Keep each file's original as <file>.orig before converting its links
+    #[arg(short = 'K', long, requires = "convert_links")]
+    pub backup_converted: bool,
+
+    /// Check that each URL exists without saving anything, reporting its status, type and
+    /// size, and listing broken links with the page they were found on. With -r, checks
+    /// every page the crawl reaches
+    #[arg(long, conflicts_with_all = ["convert_links", "output_file_name"])]
+    pub spider: bool,
+
+    /// Also download the pages listed in a sitemap or sitemap index, which may be gzipped.
+    /// Can be given several times
+    #[arg(long = "sitemap", value_name = "URL")]
+    pub sitemaps: Vec<String>,
+
+    /// Also download the pages of the sitemaps listed in the robots.txt of each URL's site
+    #[arg(long)]
+    pub discover_sitemaps: bool,
+
+    /// Skip sitemap pages whose <lastmod> is no newer than the local copy
+    #[arg(long)]
+    pub sitemap_lastmod: bool,
+
+    /// Download the files described by a Metalink 4 or Metalink 3 file or URL, trying each
+    /// file's mirrors in order of priority and checking its size and hashes
+    #[arg(long, value_name = "FILE|URL", conflicts_with_all = ["urls", "input_file", "recursive", "recursive_listing", "webdav", "page_requisites", "spider", "sitemaps", "discover_sitemaps", "output_file_name"])]
+    pub metalink: Option<String>,
+
+    /// With --metalink, prefer mirrors in this country (an ISO 3166 code such as 'de') among
+    /// those of equal priority
+    #[arg(long, value_name = "LOCATION", requires = "metalink")]
+    pub preferred_location: Option<String>,
+
+    /// Comma-separated file name globs to accept, e.g. '*.pdf,*.zip'. A pattern without
+    /// wildcards matches the end of the name
+    #[arg(short = 'A', long, value_name = "LIST", value_delimiter = ',')]
+    pub accept: Vec<String>,
+
+    /// Comma-separated file name globs to reject
+    #[arg(short = 'R', long, value_name = "LIST", value_delimiter = ',')]
+    pub reject: Vec<String>,
+
+    /// Only download URLs matching this regular expression
+    #[arg(long, value_name = "REGEX", default_value = None)]
+    pub accept_regex: Option<String>,
+
+    /// Don't download URLs matching this regular expression
+    #[arg(long, value_name = "REGEX", default_value = None)]
+    pub reject_regex: Option<String>,
+
+    /// Comma-separated domains to download from, which recursion may follow links to
+    #[arg(short = 'D', long, value_name = "LIST", value_delimiter = ',')]
+    pub domains: Vec<String>,
+
+    /// Comma-separated domains not to download from
+    #[arg(long, value_name = "LIST", value_delimiter = ',')]
+    pub exclude_domains: Vec<String>,
+
+    /// Comma-separated directories to download from, which may contain wildcards
+    #[arg(short = 'I', long, value_name = "LIST", value_delimiter = ',')]
+    pub include_directories: Vec<String>,
+
+    /// Comma-separated directories not to download from, which may contain wildcards
+    #[arg(short = 'X', long, value_name = "LIST", value_delimiter = ',')]
+    pub exclude_directories: Vec<String>,
+
+    /// Don't ascend above the start URL's directory when recursing
+    #[arg(long)]
+    pub no_parent: bool,
+
+    /// Seconds to wait between requests to the same host
+    #[arg(short = 'w', long, value_name = "SECONDS", default_value = "0", value_parser = parse_seconds)]
+    pub wait: f64,
+
+    /// Vary each wait between 0.5 and 1.5 times --wait
+    #[arg(long)]
+    pub random_wait: bool,
+
+    /// Run a setting command, e.g. 'robots=off' to ignore robots.txt when recursing.
+    /// Can be given several times
+    #[arg(short = 'e', long, value_name = "COMMAND")]
+    pub execute: Vec<String>,
+
     /// Number of retries if download fails
     #[arg(long, default_value = "3")]
     pub retries: i32,
@@ -51,11 +192,53 @@ pub struct Args {
     #[arg(short = 'q', long, conflicts_with = "verbose")]
     pub quiet: bool,
 
-    /// HTTP version to use (unstable & unimplemented for HTTP/3)
-    #[arg(long, default_value_t = HttpVersion::Http11, value_enum)]
+    /// Extra request header as 'Name: value'. Can be given several times
+    #[arg(long = "header", value_name = "HEADER")]
+    pub headers: Vec<String>,
+
+    /// Seconds to wait for the connection and response headers
+    #[arg(long, default_value = None)]
+    pub connect_timeout: Option<u64>,
+
+    /// Seconds to wait for each chunk of the response body
+    #[arg(long, default_value = None)]
+    pub read_timeout: Option<u64>,
+
+    /// Output format: progress bars, or newline-delimited JSON events instead of them
+    #[arg(long, default_value_t = OutputFormat::Text, value_enum)]
+    pub output_format: OutputFormat,
+
+    /// Minimum milliseconds between JSON progress events
+    #[arg(long, default_value = "1000")]
+    pub progress_interval: u64,
+
+    /// Write JSON events to this file descriptor instead of stdout (Unix only)
+    #[arg(long, value_name = "FD")]
+    pub json_fd: Option<i32>,
+
+    /// Write a log of each download's start, retries and result, or of each --spider check,
+    /// to this file
+    #[arg(short = 'o', long, value_name = "FILE", default_value = None)]
+    pub output_file: Option<String>,
+
+    /// Like --output-file, but append to the log instead of replacing it
+    #[arg(short = 'a', long, value_name = "FILE", conflicts_with = "output_file")]
+    pub append_output: Option<String>,
+
+    /// Write a report of every download to a .json or .csv file when the run ends
+    #[arg(long, value_name = "FILE", default_value = None, conflicts_with = "spider")]
+    pub report: Option<String>,
+
+    /// HTTP version to use
+    #[arg(long, default_value_t = HttpVersion::Auto, value_enum)]
     pub http_version: HttpVersion,
 
-    /// Use a proxy server
+    /// Content encodings to request and decode (resumed downloads are never decoded)
+    #[arg(long, default_value_t = Compression::Auto, value_enum)]
+    pub compression: Compression,
+
+    /// Use a proxy server (http://, https://, socks5:// or socks5h:// to resolve
+    /// host names on the proxy)
     #[arg(long, default_value = "")]
     pub proxy_url: String,
 
@@ -66,4 +249,47 @@ pub struct Args {
     /// Proxy server password
     #[arg(long, default_value = "")]
     pub proxy_pass: String,
+
+    /// PEM file with CA certificates to trust in addition to the system ones
+    #[arg(long, default_value = None)]
+    pub ca_certificate: Option<String>,
+
+    /// Directory of PEM files with CA certificates to trust
+    #[arg(long, default_value = None)]
+    pub ca_directory: Option<String>,
+
+    /// PEM client certificate for mutual TLS
+    #[arg(long, default_value = None)]
+    pub certificate: Option<String>,
+
+    /// PEM (PKCS#8) private key for the client certificate, if not in the certificate file
+    #[arg(long, default_value = None, requires = "certificate")]
+    pub private_key: Option<String>,
+
+    /// Do not validate the server certificate (INSECURE)
+    #[arg(long)]
+    pub no_check_certificate: bool,
+
+    /// Public key pin as sha256//<base64>. Can be given several times; the server
+    /// chain must contain at least one pinned key
+    #[arg(long = "pinned-pubkey", value_name = "PIN")]
+    pub pinned_pubkeys: Vec<String>,
+}
+
+/// Parses a recursion depth, where `inf` means no limit like 0 does.
+fn parse_level(value: &str) -> Result<u32, String> {
+    match value {
+        "inf" => Ok(0),
+        _ => value
+            .parse()
+            .map_err(|_| format!("'{}' is not a number or 'inf'", value)),
+    }
+}
+
+/// Parses a non-negative number of seconds, which may have a fraction.
+fn parse_seconds(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(seconds) if seconds.is_finite() && seconds >= 0.0 => Ok(seconds),
+        _ => Err(format!("'{}' is not a number of seconds", value)),
+    }
 }
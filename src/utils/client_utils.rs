@@ -12,78 +12,60 @@
 // MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
 // GNU General Public License for more details.
 
-use reqwest::{Client, Proxy};
+use reqwest::{Client, ClientBuilder, Proxy};
 
 use crate::enums::HttpVersion;
 use crate::error::NgetError;
 use crate::proxy_utils::ProxyConfig;
+use crate::robots::USER_AGENT_TOKEN;
+use crate::tls_utils::{configure_tls, TlsConfig};
 
-/// Creates a Client with proxy support for each HTTP version.
+/// The User-Agent sent unless a request sets its own, e.g. `nget/0.1.0`.
+pub fn user_agent() -> String {
+    format!("{}/{}", USER_AGENT_TOKEN, env!("CARGO_PKG_VERSION"))
+}
+
+/// Creates a Client with proxy and TLS support for each HTTP version.
 pub fn get_client(
     proxy_config: &ProxyConfig,
+    tls_config: &TlsConfig,
     http_version: &HttpVersion,
 ) -> Result<Client, NgetError> {
-    if proxy_config.proxy_url.is_empty() {
-        match http_version {
-            HttpVersion::Http11 => Client::builder().build().map_err(NgetError::from),
-            HttpVersion::Http2 => Client::builder()
-                .http2_prior_knowledge()
-                .build()
-                .map_err(NgetError::from),
-            HttpVersion::Http3 => Client::builder().build().map_err(NgetError::from),
-        }
+    let mut builder = configure_tls(
+        Client::builder().user_agent(user_agent()),
+        tls_config,
+        http_version,
+    )?;
+
+    if proxy_config.is_enabled() {
+        builder = builder.proxy(build_proxy(proxy_config)?);
+    }
+
+    // Over TLS, ALPN offers both h2 and http/1.1 unless a single version is forced
+    match http_version {
+        HttpVersion::Auto | HttpVersion::Http2 => build(builder),
+        HttpVersion::Http11 => build(builder.http1_only()),
+        HttpVersion::H2c => build(builder.http2_prior_knowledge()),
+        HttpVersion::Http3 => build(builder),
+    }
+}
+
+/// Creates a Proxy for HTTP, HTTPS, SOCKS5 and SOCKS5h proxy URLs.
+/// Credentials work for every proxy type, as basic auth for HTTP(S) and
+/// username/password auth for SOCKS5.
+fn build_proxy(proxy_config: &ProxyConfig) -> Result<Proxy, NgetError> {
+    // Validates the scheme before reqwest gets a chance to reject it
+    proxy_config.proxy_type()?;
+
+    let proxy = Proxy::all(proxy_config.proxy_url.clone())?;
+
+    if proxy_config.has_credentials() {
+        Ok(proxy.basic_auth(&proxy_config.proxy_user, &proxy_config.proxy_password))
     } else {
-        match http_version {
-            HttpVersion::Http11 => {
-                if !proxy_config.proxy_password.is_empty() || !proxy_config.proxy_user.is_empty() {
-                    Client::builder()
-                        .proxy(
-                            Proxy::all(proxy_config.proxy_url.clone())?
-                                .basic_auth(&proxy_config.proxy_user, &proxy_config.proxy_password),
-                        )
-                        .build()
-                        .map_err(NgetError::from)
-                } else {
-                    Client::builder()
-                        .proxy(Proxy::all(proxy_config.proxy_url.clone())?)
-                        .build()
-                        .map_err(NgetError::from)
-                }
-            }
-            HttpVersion::Http2 => {
-                if !proxy_config.proxy_password.is_empty() || !proxy_config.proxy_user.is_empty() {
-                    Client::builder()
-                        .proxy(
-                            Proxy::all(proxy_config.proxy_url.clone())?
-                                .basic_auth(&proxy_config.proxy_user, &proxy_config.proxy_password),
-                        )
-                        .http2_prior_knowledge()
-                        .build()
-                        .map_err(NgetError::from)
-                } else {
-                    Client::builder()
-                        .proxy(Proxy::all(proxy_config.proxy_url.clone())?)
-                        .http2_prior_knowledge()
-                        .build()
-                        .map_err(NgetError::from)
-                }
-            }
-            HttpVersion::Http3 => {
-                if !proxy_config.proxy_password.is_empty() || !proxy_config.proxy_user.is_empty() {
-                    Client::builder()
-                        .proxy(
-                            Proxy::all(proxy_config.proxy_url.clone())?
-                                .basic_auth(&proxy_config.proxy_user, &proxy_config.proxy_password),
-                        )
-                        .build()
-                        .map_err(NgetError::from)
-                } else {
-                    Client::builder()
-                        .proxy(Proxy::all(proxy_config.proxy_url.clone())?)
-                        .build()
-                        .map_err(NgetError::from) // Map errors to your custom NgetError
-                }
-            }
-        }
+        Ok(proxy)
     }
 }
+
+fn build(builder: ClientBuilder) -> Result<Client, NgetError> {
+    builder.build().map_err(NgetError::from)
+}
@@ -0,0 +1,143 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::enums::Compression;
+use crate::error::NgetError;
+
+use async_compression::tokio::write::{BrotliDecoder, GzipDecoder, ZlibDecoder, ZstdDecoder};
+
+use bytes::Bytes;
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Decodes a response body on the fly according to its Content-Encoding.
+pub enum ContentDecoder {
+    Identity,
+    Gzip(GzipDecoder<Vec<u8>>),
+    Deflate(ZlibDecoder<Vec<u8>>),
+    Brotli(BrotliDecoder<Vec<u8>>),
+    Zstd(ZstdDecoder<Vec<u8>>),
+}
+
+impl ContentDecoder {
+    /// Creates a decoder for a response's Content-Encoding header.
+    /// Bodies are never decoded when compression is disabled.
+    pub fn new(
+        content_encoding: Option<&str>,
+        compression: &Compression,
+    ) -> Result<Self, NgetError> {
+        let encoding = match content_encoding {
+            Some(encoding) if *compression != Compression::None => {
+                encoding.trim().to_ascii_lowercase()
+            }
+            _ => return Ok(ContentDecoder::Identity),
+        };
+
+        match encoding.as_str() {
+            "" | "identity" => Ok(ContentDecoder::Identity),
+            "gzip" | "x-gzip" => Ok(ContentDecoder::Gzip(GzipDecoder::new(Vec::new()))),
+            "deflate" => Ok(ContentDecoder::Deflate(ZlibDecoder::new(Vec::new()))),
+            "br" => Ok(ContentDecoder::Brotli(BrotliDecoder::new(Vec::new()))),
+            "zstd" => Ok(ContentDecoder::Zstd(ZstdDecoder::new(Vec::new()))),
+            other => Err(NgetError::HttpRequest(format!(
+                "Unsupported Content-Encoding: {}",
+                other
+            ))),
+        }
+    }
+
+    /// Returns true if the body is being decoded.
+    pub fn is_decoding(&self) -> bool {
+        !matches!(self, ContentDecoder::Identity)
+    }
+
+    /// Gets the name of the encoding being decoded.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContentDecoder::Identity => "identity",
+            ContentDecoder::Gzip(_) => "gzip",
+            ContentDecoder::Deflate(_) => "deflate",
+            ContentDecoder::Brotli(_) => "br",
+            ContentDecoder::Zstd(_) => "zstd",
+        }
+    }
+
+    /// Decodes a chunk of the body, returning whatever decoded output is available.
+    pub async fn decode(&mut self, chunk: Bytes) -> Result<Bytes, NgetError> {
+        match self {
+            ContentDecoder::Identity => Ok(chunk),
+            ContentDecoder::Gzip(decoder) => write_chunk(decoder, &chunk).await,
+            ContentDecoder::Deflate(decoder) => write_chunk(decoder, &chunk).await,
+            ContentDecoder::Brotli(decoder) => write_chunk(decoder, &chunk).await,
+            ContentDecoder::Zstd(decoder) => write_chunk(decoder, &chunk).await,
+        }
+    }
+
+    /// Finishes decoding, returning the remaining output.
+    /// Fails if the encoded body was truncated.
+    pub async fn finish(&mut self) -> Result<Bytes, NgetError> {
+        match self {
+            ContentDecoder::Identity => Ok(Bytes::new()),
+            ContentDecoder::Gzip(decoder) => finish_decoder(decoder).await,
+            ContentDecoder::Deflate(decoder) => finish_decoder(decoder).await,
+            ContentDecoder::Brotli(decoder) => finish_decoder(decoder).await,
+            ContentDecoder::Zstd(decoder) => finish_decoder(decoder).await,
+        }
+    }
+}
+
+async fn write_chunk<D>(decoder: &mut D, chunk: &[u8]) -> Result<Bytes, NgetError>
+where
+    D: AsyncWrite + Unpin + DecoderOutput,
+{
+    decoder
+        .write_all(chunk)
+        .await
+        .map_err(|e| NgetError::HttpRequest(format!("Failed to decode chunk: {}", e)))?;
+    decoder
+        .flush()
+        .await
+        .map_err(|e| NgetError::HttpRequest(format!("Failed to decode chunk: {}", e)))?;
+    Ok(decoder.take_output())
+}
+
+async fn finish_decoder<D>(decoder: &mut D) -> Result<Bytes, NgetError>
+where
+    D: AsyncWrite + Unpin + DecoderOutput,
+{
+    decoder
+        .shutdown()
+        .await
+        .map_err(|e| NgetError::HttpRequest(format!("Failed to finish decoding: {}", e)))?;
+    Ok(decoder.take_output())
+}
+
+/// Takes the decoded bytes buffered by a decoder.
+trait DecoderOutput {
+    fn take_output(&mut self) -> Bytes;
+}
+
+macro_rules! impl_decoder_output {
+    ($($decoder:ident),*) => {
+        $(
+            impl DecoderOutput for $decoder<Vec<u8>> {
+                fn take_output(&mut self) -> Bytes {
+                    Bytes::from(std::mem::take(self.get_mut()))
+                }
+            }
+        )*
+    };
+}
+
+impl_decoder_output!(GzipDecoder, ZlibDecoder, BrotliDecoder, ZstdDecoder);
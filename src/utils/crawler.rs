@@ -0,0 +1,617 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::error::NgetError;
+use crate::utils::css_utils::extract_css_links;
+use crate::utils::dir_listing::{fetch_listing, ListingEntry};
+use crate::utils::downloader::{DownloadOutcome, Downloader};
+use crate::utils::file_utils::set_modified;
+use crate::utils::ftp::{self, is_ftp_url};
+use crate::utils::html_utils::{base_href, extract_links, LinkKind};
+use crate::utils::http::{probe_page, Probe};
+use crate::utils::progress::{DownloadEvent, ProgressObserver, SilentObserver};
+use crate::utils::robots::RobotsCache;
+use crate::utils::scheduler::Scheduler;
+use crate::utils::url_filter::{may_be_html, UrlFilter};
+use crate::utils::url_utils::mirror_path;
+use crate::utils::webdav::list_collection;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use url::Url;
+
+/// How many levels of links a crawl follows when no level is given.
+pub const DEFAULT_LEVEL: u32 = 5;
+
+/// The largest page a spider reads to find its links.
+const SPIDER_PAGE_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Creates the observer for each URL a crawl downloads.
+type ObserverFactory = Arc<dyn Fn(&str) -> Arc<dyn ProgressObserver> + Send + Sync>;
+
+/// A URL waiting to be downloaded by a crawl.
+#[derive(Debug, Clone)]
+pub struct QueuedUrl {
+    pub url: Url,
+    /// Number of links followed from a start URL to reach this one
+    pub depth: u32,
+    /// The page this URL was found on, if it wasn't a start URL
+    pub referrer: Option<String>,
+    /// True if the URL is only downloaded as a page requisite, outside the level or hosts
+    /// links are followed to
+    pub requisite: bool,
+}
+
+/// The URLs a crawl has yet to download, each queued at most once.
+#[derive(Default)]
+pub struct Frontier {
+    queue: VecDeque<QueuedUrl>,
+    seen: HashSet<String>,
+}
+
+impl Frontier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a URL unless it has been queued before, ignoring any fragment.
+    /// Returns true if it was queued.
+    pub fn push(&mut self, url: Url, depth: u32, referrer: Option<String>) -> bool {
+        self.enqueue(url, depth, referrer, false)
+    }
+
+    /// Queues a URL like [`Frontier::push`], as one only downloaded as a page requisite.
+    pub fn push_requisite(&mut self, url: Url, depth: u32, referrer: Option<String>) -> bool {
+        self.enqueue(url, depth, referrer, true)
+    }
+
+    fn enqueue(
+        &mut self,
+        mut url: Url,
+        depth: u32,
+        referrer: Option<String>,
+        requisite: bool,
+    ) -> bool {
+        url.set_fragment(None);
+        if !self.seen.insert(url.to_string()) {
+            return false;
+        }
+
+        self.queue.push_back(QueuedUrl {
+            url,
+            depth,
+            referrer,
+            requisite,
+        });
+        true
+    }
+
+    /// Takes the next URL to download, in the order they were found.
+    pub fn pop(&mut self) -> Option<QueuedUrl> {
+        self.queue.pop_front()
+    }
+
+    /// Gets how many URLs are waiting.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+/// The result of downloading one URL during a crawl.
+#[derive(Debug)]
+pub struct CrawlEntry {
+    pub url: String,
+    /// The page the URL was found on, if it wasn't a start URL
+    pub referrer: Option<String>,
+    pub depth: u32,
+    pub result: Result<DownloadOutcome, NgetError>,
+}
+
+/// The result of checking one URL with [`Crawler::spider`].
+#[derive(Debug)]
+pub struct SpiderEntry {
+    pub url: String,
+    /// The page the URL was found on, if it wasn't a start URL
+    pub referrer: Option<String>,
+    pub depth: u32,
+    pub result: Result<Probe, NgetError>,
+}
+
+/// Downloads pages and the pages they link to, mirroring each host's layout under a directory.
+///
+/// Links are only followed on the hosts of the start URLs, or the domains allowed by the
+/// [`UrlFilter`], and up to a number of levels deep. Each site's `robots.txt` is obeyed.
+/// With page requisites enabled, the images, stylesheets and other resources needed to render
+/// each page are downloaded too, from any host and regardless of the level. A page embedded
+/// from outside those limits, e.g. in a frame, is downloaded but its links aren't followed.
+#[derive(Clone)]
+pub struct Crawler {
+    downloader: Downloader,
+    output_dir: PathBuf,
+    level: Option<u32>,
+    page_requisites: bool,
+    filter: UrlFilter,
+    robots: bool,
+    timestamping: bool,
+    webdav: bool,
+    scheduler: Scheduler,
+    observers: ObserverFactory,
+}
+
+impl Crawler {
+    /// Creates a crawler that downloads each URL with the given downloader.
+    pub fn new(downloader: Downloader) -> Self {
+        Crawler {
+            downloader,
+            output_dir: PathBuf::from("."),
+            level: Some(DEFAULT_LEVEL),
+            page_requisites: false,
+            filter: UrlFilter::default(),
+            robots: true,
+            timestamping: false,
+            webdav: false,
+            scheduler: Scheduler::default(),
+            observers: Arc::new(|_: &str| Arc::new(SilentObserver) as Arc<dyn ProgressObserver>),
+        }
+    }
+
+    /// Sets the directory the host directories are created in. Defaults to the current directory.
+    pub fn output_dir(mut self, output_dir: impl AsRef<Path>) -> Self {
+        self.output_dir = output_dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Sets how many levels of links to follow, or None for no limit. Defaults to [`DEFAULT_LEVEL`].
+    pub fn level(mut self, level: Option<u32>) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Sets whether to download the resources needed to render each page. Defaults to false.
+    pub fn page_requisites(mut self, page_requisites: bool) -> Self {
+        self.page_requisites = page_requisites;
+        self
+    }
+
+    /// Sets which links are followed. Pages whose file names are rejected are still
+    /// downloaded for their links, then removed. Defaults to following every link.
+    pub fn filter(mut self, filter: UrlFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets whether to obey each site's `robots.txt`, including its `Crawl-delay`.
+    /// Defaults to true.
+    pub fn robots(mut self, robots: bool) -> Self {
+        self.robots = robots;
+        self
+    }
+
+    /// Sets whether [`Crawler::crawl_listing`] skips files that haven't changed since they
+    /// were saved, going by the times shown in each listing. Defaults to false.
+    pub fn timestamping(mut self, timestamping: bool) -> Self {
+        self.timestamping = timestamping;
+        self
+    }
+
+    /// Sets whether [`Crawler::crawl_listing`] lists directories as WebDAV collections
+    /// rather than listing pages. Defaults to false.
+    pub fn webdav(mut self, webdav: bool) -> Self {
+        self.webdav = webdav;
+        self
+    }
+
+    /// Sets the scheduler that spaces out requests to each host. Defaults to no waiting.
+    pub fn scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Sets how to create the observer for each downloaded URL. Defaults to none.
+    pub fn observers(
+        mut self,
+        observers: impl Fn(&str) -> Arc<dyn ProgressObserver> + Send + Sync + 'static,
+    ) -> Self {
+        self.observers = Arc::new(observers);
+        self
+    }
+
+    /// Crawls from the given start URLs, returning the result of every URL downloaded.
+    /// Stops early if the downloader is cancelled.
+    pub async fn crawl(&self, seeds: &[String]) -> Vec<CrawlEntry> {
+        self.walk(
+            seeds,
+            |url, error| CrawlEntry {
+                url,
+                referrer: None,
+                depth: 0,
+                result: Err(error),
+            },
+            async |queued: &QueuedUrl| {
+                let result = self.download(&queued.url).await;
+
+                let mut links = Vec::new();
+                if let Ok(outcome) = &result {
+                    // A page embedded as a requisite only leads on to stylesheets' resources
+                    if !(queued.requisite && is_html(outcome)) {
+                        links = self.links(outcome).await;
+                    }
+
+                    // Rejected pages are only downloaded for their links
+                    if !self.filter.accepts_file_name(&queued.url) {
+                        log::info!(
+                            "Removing {} since it should be rejected",
+                            outcome.path.display()
+                        );
+                        if let Err(e) = tokio::fs::remove_file(&outcome.path).await {
+                            log::warn!("Failed to remove {}: {}", outcome.path.display(), e);
+                        }
+                    }
+                }
+
+                let entry = CrawlEntry {
+                    url: queued.url.to_string(),
+                    referrer: queued.referrer.clone(),
+                    depth: queued.depth,
+                    result,
+                };
+                (Some(entry), links)
+            },
+        )
+        .await
+    }
+
+    /// Checks the start URLs and the pages they link to without saving anything, following
+    /// links the same way as [`Crawler::crawl`]. Stops early if the downloader is cancelled.
+    pub async fn spider(&self, seeds: &[String]) -> Vec<SpiderEntry> {
+        self.walk(
+            seeds,
+            |url, error| SpiderEntry {
+                url,
+                referrer: None,
+                depth: 0,
+                result: Err(error),
+            },
+            async |queued: &QueuedUrl| {
+                let result = probe_page(&self.downloader, queued.url.as_str(), SPIDER_PAGE_LIMIT)
+                    .await
+                    .map(|(probe, body)| {
+                        let css = probe
+                            .content_type
+                            .as_deref()
+                            .is_some_and(|content_type| mime_is(content_type, "text/css"));
+                        let links = match (&body, Url::parse(&probe.final_url)) {
+                            (Some(body), Ok(url)) if css || !queued.requisite => {
+                                page_links(body, url, css)
+                            }
+                            _ => Vec::new(),
+                        };
+                        (probe, links)
+                    });
+
+                let (result, links) = match result {
+                    Ok((probe, links)) => (Ok(probe), links),
+                    Err(e) => (Err(e), Vec::new()),
+                };
+                let entry = SpiderEntry {
+                    url: queued.url.to_string(),
+                    referrer: queued.referrer.clone(),
+                    depth: queued.depth,
+                    result,
+                };
+                (Some(entry), links)
+            },
+        )
+        .await
+    }
+
+    /// Mirrors the trees of the directory listings at the start URLs, walking into each
+    /// subdirectory. Listings themselves aren't saved. `ftp://` directories are listed over
+    /// FTP, and others are WebDAV collections if [`Crawler::webdav`] is set. With
+    /// timestamping, files the listing shows are no newer than their local copy are skipped,
+    /// and saved files are given the listed modification time. Stops early if the downloader is cancelled.
+    pub async fn crawl_listing(&self, seeds: &[String]) -> Vec<CrawlEntry> {
+        // Start URLs are directories, whether or not they were given with a trailing slash
+        let seeds: Vec<String> = seeds
+            .iter()
+            .map(
+                |seed| match seed.ends_with('/') || Url::parse(seed).is_err() {
+                    true => seed.clone(),
+                    false => format!("{}/", seed),
+                },
+            )
+            .collect();
+        let mut listed: HashMap<Url, ListingEntry> = HashMap::new();
+
+        self.walk(
+            &seeds,
+            |url, error| CrawlEntry {
+                url,
+                referrer: None,
+                depth: 0,
+                result: Err(error),
+            },
+            async |queued: &QueuedUrl| {
+                let failed = |error| CrawlEntry {
+                    url: queued.url.to_string(),
+                    referrer: queued.referrer.clone(),
+                    depth: queued.depth,
+                    result: Err(error),
+                };
+
+                if queued.url.path().ends_with('/') {
+                    return match self.list_directory(&queued.url).await {
+                        Ok(entries) => {
+                            let links = entries
+                                .iter()
+                                .map(|entry| (entry.url.clone(), LinkKind::Hyperlink))
+                                .collect();
+                            listed.extend(
+                                entries.into_iter().map(|entry| (entry.url.clone(), entry)),
+                            );
+                            (None, links)
+                        }
+                        Err(e) => {
+                            // Listings have no download to report their failure
+                            (self.observers)(queued.url.as_str()).on_event(
+                                &DownloadEvent::Failed {
+                                    error: e.to_string(),
+                                    kind: e.kind(),
+                                },
+                            );
+                            (Some(failed(e)), Vec::new())
+                        }
+                    };
+                }
+
+                if !self.filter.accepts_file_name(&queued.url) {
+                    return (None, Vec::new());
+                }
+
+                let listing = listed.get(&queued.url);
+                let modified = listing.and_then(|entry| entry.modified);
+                if self.timestamping {
+                    let path = self.output_dir.join(mirror_path(&queued.url));
+                    if listing.is_some_and(|entry| entry.is_unmodified(&path)) {
+                        log::info!("Skipping {} as the local copy is up to date", queued.url);
+                        return (None, Vec::new());
+                    }
+                }
+
+                let result = self.download(&queued.url).await;
+                if let (true, Ok(outcome), Some(modified)) = (self.timestamping, &result, modified)
+                {
+                    if let Err(e) = set_modified(&outcome.path, modified) {
+                        log::warn!(
+                            "Failed to set the time of {}: {}",
+                            outcome.path.display(),
+                            e
+                        );
+                    }
+                }
+
+                let entry = CrawlEntry {
+                    url: queued.url.to_string(),
+                    referrer: queued.referrer.clone(),
+                    depth: queued.depth,
+                    result,
+                };
+                (Some(entry), Vec::new())
+            },
+        )
+        .await
+    }
+
+    /// Lists a directory, over FTP, with a WebDAV `PROPFIND` or by reading its listing page.
+    async fn list_directory(&self, url: &Url) -> Result<Vec<ListingEntry>, NgetError> {
+        if is_ftp_url(url.as_str()) {
+            return ftp::list_directory(&self.downloader, url).await;
+        }
+        match self.webdav {
+            true => Ok(list_collection(&self.downloader, url)
+                .await?
+                .into_iter()
+                .map(ListingEntry::from)
+                .collect()),
+            false => fetch_listing(&self.downloader, url).await,
+        }
+    }
+
+    /// Visits each URL reachable from the start URLs, obeying `robots.txt`, the scheduler,
+    /// the level and the filter. `visit` handles one URL, returning its result, if it has
+    /// one, and its links.
+    async fn walk<T>(
+        &self,
+        seeds: &[String],
+        invalid: impl Fn(String, NgetError) -> T,
+        mut visit: impl AsyncFnMut(&QueuedUrl) -> (Option<T>, Vec<(Url, LinkKind)>),
+    ) -> Vec<T> {
+        let mut entries = Vec::new();
+        let mut frontier = Frontier::new();
+        let mut start_urls = Vec::new();
+        let mut hosts = Vec::new();
+
+        for seed in seeds {
+            match Url::parse(seed) {
+                Ok(url) => {
+                    hosts.extend(url.host_str().map(str::to_string));
+                    start_urls.push(url.clone());
+                    frontier.push(url, 0, None);
+                }
+                Err(e) => entries.push(invalid(
+                    seed.clone(),
+                    NgetError::InvalidUrl(format!("Failed to parse URL: {}", e)),
+                )),
+            }
+        }
+
+        let robots_cache = RobotsCache::new();
+
+        while let Some(queued) = frontier.pop() {
+            if self.downloader.is_cancelled() {
+                break;
+            }
+
+            let mut crawl_delay = Duration::ZERO;
+            if self.robots && is_web_url(&queued.url) {
+                let robots = robots_cache.get(&self.downloader, &queued.url).await;
+                if !robots.is_allowed(&queued.url) {
+                    log::info!("Skipping {} as robots.txt disallows it", queued.url);
+                    continue;
+                }
+                crawl_delay = robots.crawl_delay().unwrap_or_default();
+            }
+
+            tokio::select! {
+                _ = self.downloader.cancel_token.cancelled() => break,
+                _ = self.scheduler.wait_turn(&queued.url, crawl_delay) => {}
+            }
+
+            let (entry, links) = visit(&queued).await;
+
+            let within_level = self.level.is_none_or(|level| queued.depth < level);
+            for (link, kind) in links {
+                let within_limits = within_level
+                    && self.filter.allows(&link, &start_urls)
+                    && self.filter.spans_to(&link, &hosts);
+                // Requisites may come from any host and from outside the start directory
+                let requisite =
+                    kind == LinkKind::Requisite && self.page_requisites && !within_limits;
+                let follow = match requisite {
+                    true => self.filter.allows(&link, &[]),
+                    false => within_limits,
+                };
+                let wanted = self.filter.accepts_file_name(&link) || may_be_html(&link);
+                // Listings over other protocols, such as FTP, only lead to the same protocol
+                let reachable = is_web_url(&link) || link.scheme() == queued.url.scheme();
+                if follow && wanted && reachable {
+                    let referrer = Some(queued.url.to_string());
+                    match requisite {
+                        true => frontier.push_requisite(link, queued.depth + 1, referrer),
+                        false => frontier.push(link, queued.depth + 1, referrer),
+                    };
+                }
+            }
+
+            entries.extend(entry);
+        }
+
+        entries
+    }
+
+    /// Downloads a URL to its place in the mirror.
+    async fn download(&self, url: &Url) -> Result<DownloadOutcome, NgetError> {
+        let path = self.output_dir.join(mirror_path(url));
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| NgetError::FileError(format!("Failed to create directory: {}", e)))?;
+        }
+
+        self.downloader
+            .request(url.as_str())
+            .output_path(&path)
+            .observer((self.observers)(url.as_str()))
+            .send()
+            .await
+    }
+
+    /// Reads the links of a downloaded page or stylesheet. Other files have no links.
+    async fn links(&self, outcome: &DownloadOutcome) -> Vec<(Url, LinkKind)> {
+        let (html, css) = (is_html(outcome), is_css(outcome));
+        if !html && !css {
+            return Vec::new();
+        }
+
+        let content = match tokio::fs::read(&outcome.path).await {
+            Ok(content) => content,
+            Err(e) => {
+                log::warn!("Failed to read {}: {}", outcome.path.display(), e);
+                return Vec::new();
+            }
+        };
+        let Ok(url) = Url::parse(&outcome.final_url) else {
+            return Vec::new();
+        };
+
+        page_links(&content, url, css)
+    }
+}
+
+/// Gets the links of an HTML page or a stylesheet, resolved against the URL it came from
+/// or its `<base href>`.
+fn page_links(content: &[u8], url: Url, css: bool) -> Vec<(Url, LinkKind)> {
+    if css {
+        return extract_css_links(content)
+            .into_iter()
+            .filter_map(|link| Some((url.join(&link.value).ok()?, LinkKind::Requisite)))
+            .collect();
+    }
+
+    let base = base_href(content)
+        .and_then(|href| url.join(&href).ok())
+        .unwrap_or(url);
+    extract_links(content)
+        .into_iter()
+        .filter_map(|link| Some((base.join(&link.value).ok()?, link.kind)))
+        .collect()
+}
+
+/// Returns true if a Content-Type header has the given MIME type, ignoring any parameters.
+fn mime_is(content_type: &str, mime: &str) -> bool {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .eq_ignore_ascii_case(mime)
+}
+
+/// Returns true if a downloaded file is an HTML page, going by its extension if the
+/// server didn't say.
+pub fn is_html(outcome: &DownloadOutcome) -> bool {
+    match &outcome.content_type {
+        Some(content_type) => {
+            mime_is(content_type, "text/html") || mime_is(content_type, "application/xhtml+xml")
+        }
+        None => outcome
+            .path
+            .extension()
+            .is_some_and(|extension| extension == "html" || extension == "htm"),
+    }
+}
+
+/// Returns true if a downloaded file is a stylesheet, going by its extension if the server
+/// didn't say.
+pub fn is_css(outcome: &DownloadOutcome) -> bool {
+    match &outcome.content_type {
+        Some(content_type) => mime_is(content_type, "text/css"),
+        None => outcome
+            .path
+            .extension()
+            .is_some_and(|extension| extension == "css"),
+    }
+}
+
+/// Returns true if a URL can be downloaded by a crawl.
+pub(crate) fn is_web_url(url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https")
+}
@@ -0,0 +1,117 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use std::ops::Range;
+
+/// A resource referenced by a stylesheet, through `url(...)` or `@import`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CssLink {
+    /// The URL as written, without quotes
+    pub value: String,
+    /// Byte range of the URL in the stylesheet, as written
+    pub span: Range<usize>,
+}
+
+/// Finds the resources referenced by a stylesheet, in order.
+pub fn extract_css_links(css: &[u8]) -> Vec<CssLink> {
+    let mut links = Vec::new();
+    let mut pos = 0;
+
+    while pos < css.len() {
+        let rest = &css[pos..];
+
+        if rest.starts_with(b"/*") {
+            pos = find(css, pos + 2, b"*/").map_or(css.len(), |end| end + 2);
+        } else if starts_with_ignore_case(rest, b"@import") {
+            pos = skip_whitespace(css, pos + b"@import".len());
+            // `@import url(...)` is handled as any other url()
+            if let Some(&quote @ (b'"' | b'\'')) = css.get(pos) {
+                let (span, end) = quoted(css, pos, quote);
+                links.extend(css_link(css, span));
+                pos = end;
+            }
+        } else if starts_with_ignore_case(rest, b"url(") && !is_name_byte(css, pos) {
+            pos = skip_whitespace(css, pos + b"url(".len());
+            let span = match css.get(pos) {
+                Some(&quote @ (b'"' | b'\'')) => {
+                    let (span, end) = quoted(css, pos, quote);
+                    pos = end;
+                    span
+                }
+                _ => {
+                    let start = pos;
+                    while pos < css.len() && css[pos] != b')' {
+                        pos += 1;
+                    }
+                    let mut end = pos;
+                    while end > start && css[end - 1].is_ascii_whitespace() {
+                        end -= 1;
+                    }
+                    start..end
+                }
+            };
+            links.extend(css_link(css, span));
+        } else if let Some(&quote @ (b'"' | b'\'')) = rest.first() {
+            // Other strings can't hold references, e.g. `content: "url("`
+            pos = quoted(css, pos, quote).1;
+        } else {
+            pos += 1;
+        }
+    }
+
+    links
+}
+
+/// Creates a link for a non-empty URL.
+fn css_link(css: &[u8], span: Range<usize>) -> Option<CssLink> {
+    let value = String::from_utf8_lossy(&css[span.clone()]).to_string();
+    (!value.is_empty()).then_some(CssLink { value, span })
+}
+
+/// Reads a quoted string starting at `pos`, returning the range of its content and the
+/// position after the closing quote.
+fn quoted(css: &[u8], pos: usize, quote: u8) -> (Range<usize>, usize) {
+    let start = pos + 1;
+    let mut end = start;
+    while end < css.len() && css[end] != quote {
+        // Skip escaped characters, including quotes
+        end += if css[end] == b'\\' { 2 } else { 1 };
+    }
+    let end = end.min(css.len());
+    (start..end, (end + 1).min(css.len()))
+}
+
+fn skip_whitespace(css: &[u8], mut pos: usize) -> usize {
+    while pos < css.len() && css[pos].is_ascii_whitespace() {
+        pos += 1;
+    }
+    pos
+}
+
+/// Returns true if the byte before `pos` is part of a name, so `url(` there is e.g. `myurl(`.
+fn is_name_byte(css: &[u8], pos: usize) -> bool {
+    pos > 0 && (css[pos - 1].is_ascii_alphanumeric() || matches!(css[pos - 1], b'-' | b'_'))
+}
+
+fn starts_with_ignore_case(haystack: &[u8], prefix: &[u8]) -> bool {
+    haystack.len() >= prefix.len() && haystack[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+fn find(haystack: &[u8], from: usize, pattern: &[u8]) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(pattern.len())
+        .position(|window| window == pattern)
+        .map(|index| from + index)
+}
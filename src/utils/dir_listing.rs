@@ -0,0 +1,274 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::error::NgetError;
+use crate::utils::downloader::Downloader;
+use crate::utils::html_utils::extract_links;
+use crate::utils::http::fetch_body;
+use crate::utils::sitemap::days_from_civil;
+
+use once_cell::sync::Lazy;
+
+use regex::Regex;
+
+use serde_json::Value;
+
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use url::Url;
+
+/// Largest directory listing that is read.
+const LISTING_SIZE_LIMIT: usize = 16 * 1024 * 1024;
+
+/// How much of the text after a link is searched for its date and size.
+const DETAILS_LENGTH: usize = 200;
+
+/// Dates as shown by Apache, nginx and lighttpd, e.g. `01-May-2024 10:30`,
+/// `2024-05-01 10:30` and `2024-May-01 10:30:00`.
+static DATE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"(?:(?P<d1>\d{1,2})-(?P<m1>[A-Za-z]{3})-(?P<y1>\d{4})",
+        r"|(?P<y2>\d{4})-(?P<m2>\d{2}|[A-Za-z]{3})-(?P<d2>\d{1,2}))",
+        r"\s+(?P<hour>\d{1,2}):(?P<minute>\d{2})(?::(?P<second>\d{2}))?",
+    ))
+    .unwrap()
+});
+
+/// The size column following a date: exact bytes, a rounded size like `1.2K`, or `-`.
+static SIZE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\s*(?P<size>-|\d+(?:\.\d+)?)(?P<unit>[KMGTP]?)\b").unwrap());
+
+/// Tags, stripped from the text after a link.
+static TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").unwrap());
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// A file or subdirectory shown in a directory listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingEntry {
+    /// Absolute URL of the entry, ending with `/` for a directory
+    pub url: Url,
+    pub is_dir: bool,
+    /// When the entry was last modified, if the listing shows it
+    pub modified: Option<SystemTime>,
+    /// Size in bytes, if the listing shows it exactly
+    pub size: Option<u64>,
+}
+
+impl ListingEntry {
+    /// Returns true if the local copy at `path` is at least as new as the listing shows,
+    /// and the same size if the listing shows that too.
+    pub fn is_unmodified(&self, path: &Path) -> bool {
+        let Some(modified) = self.modified else {
+            return false;
+        };
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return false;
+        };
+
+        let newer = metadata.modified().is_ok_and(|local| modified <= local);
+        let same_size = self.size.is_none_or(|size| size == metadata.len());
+        newer && same_size
+    }
+}
+
+/// Fetches a directory listing and parses the entries in it.
+pub async fn fetch_listing(
+    downloader: &Downloader,
+    url: &Url,
+) -> Result<Vec<ListingEntry>, NgetError> {
+    let body = fetch_body(downloader, url.as_str(), LISTING_SIZE_LIMIT).await?;
+    Ok(parse_listing(&body, url))
+}
+
+/// Parses a directory listing: nginx's JSON autoindex, or the HTML of Apache, nginx,
+/// lighttpd or Python's http.server. Only entries directly inside `base` are returned.
+pub fn parse_listing(content: &[u8], base: &Url) -> Vec<ListingEntry> {
+    match serde_json::from_slice::<Vec<Value>>(content) {
+        Ok(entries) => parse_json_listing(&entries, base),
+        Err(_) => parse_html_listing(content, base),
+    }
+}
+
+/// Parses nginx's `autoindex_format json` listing.
+fn parse_json_listing(entries: &[Value], base: &Url) -> Vec<ListingEntry> {
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let name = entry["name"].as_str()?;
+            let is_dir = entry["type"] == "directory";
+            // Names are plain, so characters like '#' and '?' must not start a fragment or query
+            let mut url = base.clone();
+            url.path_segments_mut().ok()?.pop_if_empty().push(name);
+            if is_dir {
+                url.path_segments_mut().ok()?.push("");
+            }
+            child_url(base, &url)?;
+
+            Some(ListingEntry {
+                url,
+                is_dir,
+                modified: entry["mtime"].as_str().and_then(parse_listing_date),
+                size: entry["size"].as_u64(),
+            })
+        })
+        .collect()
+}
+
+/// Parses an HTML listing. Entries are the links to the directory's children, and any
+/// date and size are read from the text that follows each link.
+fn parse_html_listing(content: &[u8], base: &Url) -> Vec<ListingEntry> {
+    let links = extract_links(content);
+    let mut entries: Vec<ListingEntry> = Vec::new();
+
+    for (i, link) in links.iter().enumerate() {
+        let Some(url) = base
+            .join(&link.value)
+            .ok()
+            .and_then(|url| child_url(base, &url))
+        else {
+            continue;
+        };
+        // Listings may link to an entry from both its icon and its name
+        if entries.iter().any(|entry| entry.url == url) {
+            continue;
+        }
+
+        let end = links
+            .get(i + 1)
+            .map_or(content.len(), |next| next.span.start);
+        let after = &content[link.span.end..end.max(link.span.end)];
+        let details = link_details(after);
+        let (modified, size) = match DATE.captures(&details) {
+            Some(date) => (
+                parse_date_captures(&date),
+                parse_size(&details[date.get(0).map_or(0, |m| m.end())..]),
+            ),
+            None => (None, None),
+        };
+
+        entries.push(ListingEntry {
+            is_dir: url.path().ends_with('/'),
+            url,
+            modified,
+            size,
+        });
+    }
+
+    entries
+}
+
+/// Gets the text between the end of a link and the next one, without tags.
+fn link_details(after: &[u8]) -> String {
+    let after = String::from_utf8_lossy(after);
+    let after = match after.to_ascii_lowercase().find("</a>") {
+        Some(close) => &after[close + 4..],
+        None => &after[..],
+    };
+    let text = TAG.replace_all(after, " ");
+    text.chars().take(DETAILS_LENGTH).collect()
+}
+
+/// Returns the URL without any fragment if it is directly inside `base`, rather than
+/// `base` itself, its parent, a sort link or somewhere else.
+fn child_url(base: &Url, url: &Url) -> Option<Url> {
+    if url.query().is_some() || url.origin() != base.origin() {
+        return None;
+    }
+
+    let name = url.path().strip_prefix(base.path())?;
+    let name = name.strip_suffix('/').unwrap_or(name);
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+
+    let mut url = url.clone();
+    url.set_fragment(None);
+    Some(url)
+}
+
+/// Parses a listing's size column, ignoring rounded sizes like `1.2K` and `-` for directories.
+fn parse_size(text: &str) -> Option<u64> {
+    let size = SIZE.captures(text)?;
+    if !size["unit"].is_empty() {
+        return None;
+    }
+    size["size"].parse().ok()
+}
+
+/// Parses the dates of HTML listings and nginx's JSON listing, which servers show in UTC
+/// by default, e.g. `01-May-2024 10:30` or `Wed, 01 May 2024 10:30:00 GMT`.
+pub fn parse_listing_date(text: &str) -> Option<SystemTime> {
+    if let Some(date) = DATE.captures(text) {
+        return parse_date_captures(&date);
+    }
+
+    // The HTTP date format used by nginx's JSON listing
+    let mut fields = text.split_whitespace().skip(1);
+    let day = fields.next()?.parse().ok()?;
+    let month = month_number(fields.next()?)?;
+    let year = fields.next()?.parse().ok()?;
+    let mut clock = fields.next()?.split(':').map(|field| field.parse().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+    utc_time(year, month, day, hour, minute, second)
+}
+
+fn parse_date_captures(date: &regex::Captures) -> Option<SystemTime> {
+    let field = |name: &str| date.name(name).map(|m| m.as_str());
+    let (year, month, day) = match field("y1") {
+        Some(year) => (year, field("m1")?, field("d1")?),
+        None => (field("y2")?, field("m2")?, field("d2")?),
+    };
+    let month = match month.parse() {
+        Ok(month) => month,
+        Err(_) => month_number(month)?,
+    };
+
+    utc_time(
+        year.parse().ok()?,
+        month,
+        day.parse().ok()?,
+        field("hour")?.parse().ok()?,
+        field("minute")?.parse().ok()?,
+        field("second").map_or(Some(0), |second| second.parse().ok())?,
+    )
+}
+
+pub(crate) fn month_number(name: &str) -> Option<u32> {
+    let name = name.to_ascii_lowercase();
+    MONTHS
+        .iter()
+        .position(|month| *month == name)
+        .map(|index| index as u32 + 1)
+}
+
+pub(crate) fn utc_time(
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u64,
+    minute: u64,
+    second: u64,
+) -> Option<SystemTime> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
@@ -0,0 +1,109 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::error::NgetError;
+use crate::utils::progress::{DownloadEvent, ProgressObserver};
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// A human-readable log of downloads, shared by every download so lines never interleave.
+#[derive(Clone)]
+pub struct DownloadLog {
+    file: Arc<Mutex<File>>,
+}
+
+impl DownloadLog {
+    /// Opens the log file, adding to the end of it if `append` is set and replacing it otherwise.
+    pub fn open(path: &Path, append: bool) -> Result<Self, NgetError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .map_err(|e| {
+                NgetError::FileError(format!("Failed to open log {}: {}", path.display(), e))
+            })?;
+
+        Ok(DownloadLog {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Writes a line prefixed with the current time.
+    /// Write errors are ignored, as a full disk shouldn't stop downloads.
+    pub fn write(&self, message: &str) {
+        let timestamp = humantime::format_rfc3339_seconds(SystemTime::now());
+        let mut file = self.file.lock().unwrap();
+        let _ = writeln!(file, "{} {}", timestamp, message);
+    }
+
+    /// Creates an observer that logs the events of a download of `url`.
+    pub fn observer(&self, url: &str) -> LogObserver {
+        LogObserver {
+            log: self.clone(),
+            url: url.to_string(),
+        }
+    }
+}
+
+/// Logs the start, retries and result of one download. Progress isn't logged.
+pub struct LogObserver {
+    log: DownloadLog,
+    url: String,
+}
+
+impl ProgressObserver for LogObserver {
+    fn on_event(&self, event: &DownloadEvent) {
+        let message = match event {
+            DownloadEvent::Started { url, path, attempt } => format!(
+                "Downloading {} to {} (attempt {})",
+                url,
+                path.display(),
+                attempt
+            ),
+            DownloadEvent::Retrying {
+                attempt,
+                error,
+                delay,
+                ..
+            } => format!(
+                "Attempt {} for {} failed, retrying in {}: {}",
+                attempt,
+                self.url,
+                humantime::format_duration(*delay),
+                error
+            ),
+            DownloadEvent::Finished { outcome } => format!(
+                "Saved {} to {} ({} bytes, HTTP {}, {:.1}s)",
+                outcome.final_url,
+                outcome.path.display(),
+                outcome.bytes,
+                outcome.status.as_u16(),
+                outcome.elapsed.as_secs_f64()
+            ),
+            DownloadEvent::Failed { error, .. } => {
+                format!("Failed to download {}: {}", self.url, error)
+            }
+            DownloadEvent::Cancelled => format!("Cancelled {}", self.url),
+            DownloadEvent::HeadersReceived { .. } | DownloadEvent::Bytes { .. } => return,
+        };
+
+        self.log.write(&message);
+    }
+}
@@ -0,0 +1,448 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::enums::{Compression, HttpVersion, MirrorOrder};
+use crate::error::NgetError;
+use crate::utils::ftp::fetch_ftp;
+use crate::utils::http::fetch;
+use crate::utils::local_url::{fetch_data, fetch_file};
+use crate::utils::mirrors::{fetch_segments, hand_over, order_by_latency};
+use crate::utils::progress::{DownloadEvent, ProgressObserver, SilentObserver};
+use crate::utils::proxy_utils::ProxyConfig;
+use crate::utils::tls_utils::TlsConfig;
+use crate::utils::url_utils::{self, get_file_name};
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio_util::sync::CancellationToken;
+
+/// Downloads files with shared settings, such as the proxy, TLS and retry configuration.
+/// Clones share the same cancellation, so [`Downloader::cancel`] stops downloads from any of them.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), nget::error::NgetError> {
+/// let outcome = nget::Downloader::new()
+///     .retries(2)
+///     .request("https://example.com/file.tar.gz")
+///     .output_dir("downloads")
+///     .send()
+///     .await?;
+/// println!("Saved {} bytes to {}", outcome.bytes, outcome.path.display());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default, Clone)]
+pub struct Downloader {
+    pub(crate) http_version: HttpVersion,
+    pub(crate) compression: Compression,
+    pub(crate) proxy_config: ProxyConfig,
+    pub(crate) tls_config: TlsConfig,
+    pub(crate) headers: HeaderMap,
+    pub(crate) retries: u32,
+    pub(crate) retry_delay: Duration,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) checksum: bool,
+    pub(crate) ftp_active: bool,
+    pub(crate) mirror_order: MirrorOrder,
+    pub(crate) mirror_segments: bool,
+    pub(crate) cancel_token: CancellationToken,
+}
+
+impl Downloader {
+    /// Creates a downloader with default settings: auto HTTP version and compression,
+    /// no proxy, system TLS trust, no retries and no timeouts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the HTTP version to use.
+    pub fn http_version(mut self, http_version: HttpVersion) -> Self {
+        self.http_version = http_version;
+        self
+    }
+
+    /// Sets the content encodings to request and decode.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the proxy server to download through.
+    pub fn proxy(mut self, proxy_config: ProxyConfig) -> Self {
+        self.proxy_config = proxy_config;
+        self
+    }
+
+    /// Sets the TLS trust, client certificate and pinning configuration.
+    pub fn tls(mut self, tls_config: TlsConfig) -> Self {
+        self.tls_config = tls_config;
+        self
+    }
+
+    /// Adds a header sent with every request.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Sets how many times a failed download is retried, resuming from what was saved.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets the delay between retries.
+    pub fn retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Sets how long to wait for the connection and the response headers.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long to wait for each chunk of the response body.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Computes the SHA-256 of each saved file, reported in [`DownloadOutcome::sha256`].
+    pub fn checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Uses active mode for FTP, where the server connects back for each transfer,
+    /// instead of passive mode.
+    pub fn ftp_active(mut self, active: bool) -> Self {
+        self.ftp_active = active;
+        self
+    }
+
+    /// Sets the order the mirrors of a request are tried in. Defaults to the order given.
+    pub fn mirror_order(mut self, order: MirrorOrder) -> Self {
+        self.mirror_order = order;
+        self
+    }
+
+    /// Splits each new HTTP download that has mirrors into segments, one per mirror,
+    /// downloaded at the same time. Servers must support byte ranges.
+    pub fn mirror_segments(mut self, segments: bool) -> Self {
+        self.mirror_segments = segments;
+        self
+    }
+
+    /// Starts building a request to download the given URL with these settings.
+    pub fn request(&self, url: &str) -> DownloadRequest {
+        DownloadRequest {
+            downloader: self.clone(),
+            url: url.to_string(),
+            mirrors: Vec::new(),
+            output_dir: PathBuf::from("."),
+            file_name: None,
+            headers: HeaderMap::new(),
+            observer: Arc::new(SilentObserver),
+            cancel_token: self.cancel_token.child_token(),
+        }
+    }
+
+    /// Cancels every download started from this downloader, including those in progress.
+    /// Partially downloaded files are kept so they can be resumed later.
+    pub fn cancel(&self) {
+        self.cancel_token.cancel();
+    }
+
+    /// Returns true if the downloader has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel_token.is_cancelled()
+    }
+
+    /// Downloads a URL into the current directory.
+    pub async fn download(&self, url: &str) -> Result<DownloadOutcome, NgetError> {
+        self.request(url).send().await
+    }
+}
+
+/// A single download, built from a [`Downloader`].
+pub struct DownloadRequest {
+    downloader: Downloader,
+    url: String,
+    mirrors: Vec<String>,
+    output_dir: PathBuf,
+    file_name: Option<String>,
+    headers: HeaderMap,
+    observer: Arc<dyn ProgressObserver>,
+    cancel_token: CancellationToken,
+}
+
+impl DownloadRequest {
+    /// Sets the directory the file is saved in. Defaults to the current directory.
+    pub fn output_dir(mut self, output_dir: impl AsRef<Path>) -> Self {
+        self.output_dir = output_dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Sets the name of the saved file. Defaults to the last segment of the URL.
+    pub fn file_name(mut self, file_name: &str) -> Self {
+        self.file_name = Some(file_name.to_string());
+        self
+    }
+
+    /// Sets the full path of the saved file.
+    pub fn output_path(mut self, output_path: impl AsRef<Path>) -> Self {
+        let output_path = output_path.as_ref();
+        self.output_dir = output_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf();
+        self.file_name = output_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string());
+        self
+    }
+
+    /// Adds a header sent with this request only.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Adds other URLs of the same file. When an attempt fails, the next one is tried,
+    /// resuming from what was saved, so the mirrors must serve identical content.
+    pub fn mirrors(mut self, mirrors: Vec<String>) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// Sets the observer told about the download's progress. Defaults to none.
+    pub fn observer(mut self, observer: Arc<dyn ProgressObserver>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Gets the URL to download.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Gets a token that cancels this download only.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel_token.clone()
+    }
+
+    /// Gets the path the file will be saved to. Fails if the file is named after the URL
+    /// and it isn't valid.
+    pub fn path(&self) -> Result<PathBuf, NgetError> {
+        let file_name = match &self.file_name {
+            Some(name) => name.clone(),
+            None => get_file_name(url_utils::parse_url(&self.url)?),
+        };
+        Ok(self.output_dir.join(file_name))
+    }
+
+    /// Downloads the file, retrying failed attempts as configured. A failed attempt moves
+    /// on to the next mirror straight away, and a retry starts again from the first.
+    pub async fn send(self) -> Result<DownloadOutcome, NgetError> {
+        let started = Instant::now();
+        // Retrying can't fix the URL the file is named after
+        if let Err(e) = self.path() {
+            self.observer.on_event(&DownloadEvent::Failed {
+                error: e.to_string(),
+                kind: e.kind(),
+            });
+            return Err(e);
+        }
+        let mut sources = vec![self.url.clone()];
+        sources.extend(self.mirrors.iter().cloned());
+        if sources.len() > 1 && self.downloader.mirror_order == MirrorOrder::Latency {
+            sources = order_by_latency(&self.downloader, sources).await;
+        }
+
+        let mut attempt = 0;
+        let mut retries = 0;
+        let mut source = 0;
+
+        loop {
+            attempt += 1;
+            let url = &sources[source];
+            log::info!("Attempt {} for URL: {}", attempt, url);
+
+            if self.cancel_token.is_cancelled() {
+                self.observer.on_event(&DownloadEvent::Cancelled);
+                return Err(NgetError::Cancelled(url.clone()));
+            }
+
+            match self.attempt(attempt, url, &sources).await {
+                Ok(outcome) => {
+                    let outcome = DownloadOutcome {
+                        elapsed: started.elapsed(),
+                        ..outcome
+                    };
+                    self.observer.on_event(&DownloadEvent::Finished {
+                        outcome: outcome.clone(),
+                    });
+                    return Ok(outcome);
+                }
+                Err(e @ NgetError::Cancelled(_)) => {
+                    self.observer.on_event(&DownloadEvent::Cancelled);
+                    return Err(e);
+                }
+                Err(e) if source + 1 < sources.len() => {
+                    log::warn!(
+                        "Attempt {} failed for URL {}: {:?}. Trying mirror {}...",
+                        attempt,
+                        url,
+                        e,
+                        sources[source + 1]
+                    );
+                    self.observer.on_event(&DownloadEvent::Retrying {
+                        attempt,
+                        error: e.to_string(),
+                        kind: e.kind(),
+                        delay: Duration::ZERO,
+                    });
+                    source += 1;
+                }
+                Err(e) if retries < self.downloader.retries => {
+                    log::warn!(
+                        "Attempt {} failed for URL {}: {:?}. Retrying...",
+                        attempt,
+                        url,
+                        e
+                    );
+                    self.observer.on_event(&DownloadEvent::Retrying {
+                        attempt,
+                        error: e.to_string(),
+                        kind: e.kind(),
+                        delay: self.downloader.retry_delay,
+                    });
+                    tokio::select! {
+                        _ = self.cancel_token.cancelled() => {}
+                        _ = tokio::time::sleep(self.downloader.retry_delay) => {}
+                    }
+                    retries += 1;
+                    source = 0;
+                }
+                Err(e) => {
+                    self.observer.on_event(&DownloadEvent::Failed {
+                        error: e.to_string(),
+                        kind: e.kind(),
+                    });
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Downloads the file once from `url`, one of the request's `sources`, without retrying.
+    async fn attempt(
+        &self,
+        attempt: u32,
+        url: &str,
+        sources: &[String],
+    ) -> Result<DownloadOutcome, NgetError> {
+        let file_path = self.path()?;
+        self.observer.on_event(&DownloadEvent::Started {
+            url: url.to_string(),
+            path: file_path.clone(),
+            attempt,
+        });
+
+        // What another mirror saved is resumed from this one
+        if sources.len() > 1 {
+            hand_over(&file_path, sources, url).await?;
+        }
+
+        // FTP, file and data URLs have their own backends, which send no headers
+        let (observer, cancel) = (self.observer.as_ref(), &self.cancel_token);
+        let scheme = url.split(':').next().unwrap_or_default();
+        match scheme.to_ascii_lowercase().as_str() {
+            "ftp" | "ftps" => fetch_ftp(&self.downloader, url, &file_path, observer, cancel).await,
+            "file" => fetch_file(&self.downloader, url, &file_path, observer, cancel).await,
+            "data" => fetch_data(&self.downloader, url, &file_path, observer, cancel).await,
+            _ => {
+                let mut headers = self.downloader.headers.clone();
+                headers.extend(self.headers.clone());
+
+                if self.downloader.mirror_segments && sources.len() > 1 {
+                    let segmented = fetch_segments(
+                        &self.downloader,
+                        url,
+                        sources,
+                        &headers,
+                        &file_path,
+                        observer,
+                        cancel,
+                    )
+                    .await?;
+                    if let Some(outcome) = segmented {
+                        return Ok(outcome);
+                    }
+                }
+
+                fetch(&self.downloader, url, headers, &file_path, observer, cancel).await
+            }
+        }
+    }
+}
+
+/// The result of a completed download.
+#[derive(Debug, Clone)]
+pub struct DownloadOutcome {
+    /// Path of the saved file
+    pub path: PathBuf,
+    /// URL the file was served from, after any redirects
+    pub final_url: String,
+    /// Size of the saved file in bytes
+    pub bytes: u64,
+    /// Bytes received over the network by the final attempt, before decoding
+    pub received: u64,
+    /// Content encoding the body was decoded from, if any
+    pub content_encoding: Option<String>,
+    /// SHA-256 of the saved file as lowercase hex, if checksums are enabled
+    pub sha256: Option<String>,
+    /// HTTP status of the final response
+    pub status: StatusCode,
+    /// ETag of the downloaded content, if the server sent one
+    pub etag: Option<String>,
+    /// Content type of the downloaded content, if the server sent one
+    pub content_type: Option<String>,
+    /// Time taken, including any retries
+    pub elapsed: Duration,
+}
+
+/// Parses a `Name: value` header line, as given on the command line.
+pub fn parse_header(line: &str) -> Result<(HeaderName, HeaderValue), NgetError> {
+    let (name, value) = line.split_once(':').ok_or_else(|| {
+        NgetError::ConfigurationError(format!("Header must be given as 'Name: value': {}", line))
+    })?;
+
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|e| {
+        NgetError::ConfigurationError(format!("Invalid header name {}: {}", name, e))
+    })?;
+    let value = HeaderValue::from_str(value.trim()).map_err(|e| {
+        NgetError::ConfigurationError(format!("Invalid header value for {}: {}", name, e))
+    })?;
+
+    Ok((name, value))
+}
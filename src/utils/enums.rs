@@ -14,17 +14,105 @@
 
 use clap::ValueEnum;
 
-#[derive(Debug, ValueEnum, Clone)]
+#[derive(Debug, Default, ValueEnum, Clone, PartialEq, Eq)]
 pub enum HttpVersion {
-    /// Use HTTP/3 (unstable & unimplemented)
+    /// Negotiate HTTP/2 via ALPN, falling back to HTTP/1.1 (default)
+    #[default]
+    #[clap(name = "auto")]
+    Auto,
+
+    /// Use HTTP/3 over QUIC (https:// only, no proxy support)
     #[clap(name = "http3")]
     Http3,
 
-    /// Use HTTP/2
+    /// Require HTTP/2 negotiated via ALPN (https:// only)
     #[clap(name = "http2")]
     Http2,
 
-    /// Use HTTP/1.1 (default)
+    /// Use HTTP/2 with prior knowledge, without ALPN (cleartext h2c servers)
+    #[clap(name = "h2c")]
+    H2c,
+
+    /// Use HTTP/1.1 only
     #[clap(name = "http11")]
     Http11,
 }
+
+/// The kind of proxy server described by a proxy URL's scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyType {
+    /// `http://` proxy (also used when no scheme is given)
+    Http,
+
+    /// `https://` proxy
+    Https,
+
+    /// `socks5://` proxy, host names are resolved locally
+    Socks5,
+
+    /// `socks5h://` proxy, host names are resolved by the proxy
+    Socks5h,
+}
+
+#[derive(Debug, Default, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Accept any supported encoding and decode it (default)
+    #[default]
+    #[clap(name = "auto")]
+    Auto,
+
+    /// Ask for uncompressed content and save the body as received
+    #[clap(name = "none")]
+    None,
+
+    /// Accept gzip only
+    #[clap(name = "gzip")]
+    Gzip,
+
+    /// Accept brotli only
+    #[clap(name = "br")]
+    Br,
+
+    /// Accept zstd only
+    #[clap(name = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Gets the Accept-Encoding header value to send for this setting.
+    pub fn accept_encoding(&self) -> &'static str {
+        match self {
+            Compression::Auto => "gzip, deflate, br, zstd",
+            Compression::None => "identity",
+            Compression::Gzip => "gzip",
+            Compression::Br => "br",
+            Compression::Zstd => "zstd",
+        }
+    }
+}
+
+/// How progress and results are reported.
+#[derive(Debug, Default, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Progress bars for people (default)
+    #[default]
+    #[clap(name = "text")]
+    Text,
+
+    /// Newline-delimited JSON events for programs
+    #[clap(name = "json")]
+    Json,
+}
+
+/// The order the mirrors of a file are tried in.
+#[derive(Debug, Default, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum MirrorOrder {
+    /// In the order they were given (default)
+    #[default]
+    #[clap(name = "listed")]
+    Listed,
+
+    /// Quickest to respond first, timed with a request to each before downloading
+    #[clap(name = "latency")]
+    Latency,
+}
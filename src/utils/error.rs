@@ -108,6 +108,10 @@ pub enum NgetError {
     #[error("Rate limit exceeded. Please wait before retrying.")]
     RateLimitExceeded,
 
+    // Cancellation
+    #[error("Download cancelled: {0}")]
+    Cancelled(String),
+
     // Unexpected or generic errors
     #[error("Unexpected error: {0}")]
     Unexpected(String),
@@ -115,6 +119,47 @@ pub enum NgetError {
     Unknown,
 }
 
+impl NgetError {
+    /// Gets the name of the error variant, for machine-readable output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NgetError::InvalidUrl(_) => "InvalidUrl",
+            NgetError::InvalidTcpUrl(_) => "InvalidTcpUrl",
+            NgetError::InvalidUri(_) => "InvalidUri",
+            NgetError::TcpConnectionError(_) => "TcpConnectionError",
+            NgetError::DnsResolutionError(_) => "DnsResolutionError",
+            NgetError::InvalidDomain(_) => "InvalidDomain",
+            NgetError::TlsError(_) => "TlsError",
+            NgetError::ReqwestError(_) => "ReqwestError",
+            NgetError::ResolveAddressError(_) => "ResolveAddressError",
+            NgetError::H2Error(_) => "H2Error",
+            NgetError::UrlParseError(_) => "UrlParseError",
+            NgetError::InvalidDnsNameError(_) => "InvalidDnsNameError",
+            NgetError::ConnectionError(_) => "ConnectionError",
+            NgetError::ToStringError(_) => "ToStringError",
+            NgetError::UnsupportedHTTPVersion(_) => "UnsupportedHTTPVersion",
+            NgetError::HttpRequest(_) => "HttpRequest",
+            NgetError::InvalidStatusCode(_) => "InvalidStatusCode",
+            NgetError::RedirectionLoop => "RedirectionLoop",
+            NgetError::FileError(_) => "FileError",
+            NgetError::FileChecksumMismatch(_) => "FileChecksumMismatch",
+            NgetError::FileLocked(_) => "FileLocked",
+            NgetError::IoError(_) => "IoError",
+            NgetError::ClientError(_) => "ClientError",
+            NgetError::ConfigurationError(_) => "ConfigurationError",
+            NgetError::NetworkError(_) => "NetworkError",
+            NgetError::DnsResolutionFailed(_) => "DnsResolutionFailed",
+            NgetError::ConnectionTimeout(_) => "ConnectionTimeout",
+            NgetError::ReadTimeout(_) => "ReadTimeout",
+            NgetError::TooManyRetries(_) => "TooManyRetries",
+            NgetError::RateLimitExceeded => "RateLimitExceeded",
+            NgetError::Cancelled(_) => "Cancelled",
+            NgetError::Unexpected(_) => "Unexpected",
+            NgetError::Unknown => "Unknown",
+        }
+    }
+}
+
 // Implement From traits for automatic conversion
 impl From<std::io::Error> for NgetError {
     fn from(err: std::io::Error) -> Self {
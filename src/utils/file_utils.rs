@@ -12,12 +12,13 @@
 // MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
 // GNU General Public License for more details.
 
-// This file isnt currently in use yet. Will implement later.
+use sha2::{Digest, Sha256};
 
 use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 use std::path::Path;
+use std::time::SystemTime;
 
 use crate::error::NgetError;
 
@@ -39,3 +40,43 @@ pub async fn save_to_file(content: &[u8], file_path: &str) -> Result<(), NgetErr
 
     Ok(())
 }
+
+/// Hashes the first `len` bytes of a file with SHA-256, so a resumed download can continue the hash.
+pub async fn sha256_prefix(file_path: &Path, len: u64) -> Result<Sha256, NgetError> {
+    let mut hasher = Sha256::new();
+    if len == 0 {
+        return Ok(hasher);
+    }
+
+    let file = File::open(file_path)
+        .await
+        .map_err(|e| NgetError::FileError(format!("Failed to read file: {}", e)))?;
+    let mut reader = file.take(len);
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = reader
+            .read(&mut buf)
+            .await
+            .map_err(|e| NgetError::FileError(format!("Failed to read file: {}", e)))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+
+    Ok(hasher)
+}
+
+/// Formats a digest as lowercase hex.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Sets the modification time of a saved file, e.g. to the time the server reports.
+pub fn set_modified(path: &Path, modified: SystemTime) -> std::io::Result<()> {
+    std::fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(modified)
+}
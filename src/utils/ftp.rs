@@ -0,0 +1,922 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::enums::Compression;
+use crate::error::NgetError;
+use crate::utils::dir_listing::{month_number, utc_time, ListingEntry};
+use crate::utils::downloader::{DownloadOutcome, Downloader};
+use crate::utils::file_utils::set_modified;
+use crate::utils::http::{already_complete, cancellable, resumable_size, within, write_body};
+use crate::utils::progress::{DownloadEvent, ProgressObserver};
+use crate::utils::tls_utils::build_rustls_config;
+use crate::utils::transport::TransportResponse;
+use crate::utils::url_filter::glob_match;
+
+use bytes::Bytes;
+
+use futures_util::stream::{self, StreamExt};
+
+use percent_encoding::percent_decode_str;
+
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{StatusCode, Version};
+
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::TlsConnector;
+
+use tokio_util::sync::CancellationToken;
+
+use url::Url;
+
+/// Largest directory listing that is read.
+const LISTING_SIZE_LIMIT: u64 = 16 * 1024 * 1024;
+
+/// Size of each read from a data connection.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A control or data connection, either plain or protected with TLS.
+trait FtpStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> FtpStream for T {}
+
+/// A file or directory in an FTP listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteFile {
+    pub name: String,
+    pub is_dir: bool,
+    /// Size in bytes, if the listing shows it
+    pub size: Option<u64>,
+    /// When the file was last modified, if the listing shows it
+    pub modified: Option<SystemTime>,
+}
+
+/// Returns true for `ftp://` and `ftps://` URLs.
+pub fn is_ftp_url(url: &str) -> bool {
+    let url = url.to_ascii_lowercase();
+    url.starts_with("ftp://") || url.starts_with("ftps://")
+}
+
+/// Returns true if the last segment of an FTP URL has `*`, `?` or `[...]` wildcards.
+pub fn has_wildcards(url: &str) -> bool {
+    let Some((_, rest)) = url.split_once("://") else {
+        return false;
+    };
+    let name = match rest.split_once('/') {
+        Some((_, path)) => path.rsplit('/').next().unwrap_or_default(),
+        None => "",
+    };
+    is_ftp_url(url) && name.contains(['*', '?', '['])
+}
+
+/// A reply from the server.
+struct Reply {
+    code: u16,
+    /// Text of the reply, without the code
+    text: String,
+}
+
+/// The control connection of an FTP session.
+struct Control {
+    url: String,
+    stream: BufReader<Box<dyn FtpStream>>,
+    read_timeout: Option<Duration>,
+}
+
+impl Control {
+    /// Reads a reply, joining the lines of a multi-line reply.
+    async fn read_reply(&mut self) -> Result<Reply, NgetError> {
+        let url = self.url.clone();
+        let stream = &mut self.stream;
+        within(
+            self.read_timeout,
+            async move {
+                let mut text = String::new();
+                let mut code: Option<String> = None;
+                loop {
+                    let mut line = String::new();
+                    let read = stream.read_line(&mut line).await.map_err(|e| {
+                        NgetError::NetworkError(format!("Failed to read reply: {}", e))
+                    })?;
+                    if read == 0 {
+                        return Err(NgetError::NetworkError(format!(
+                            "Server closed the connection for {}",
+                            url
+                        )));
+                    }
+
+                    let line = line.trim_end_matches(['\r', '\n']);
+                    let done = match &code {
+                        // A multi-line reply ends with its code followed by a space
+                        Some(code) => line.starts_with(&format!("{} ", code)),
+                        None => {
+                            let (start, rest) = line.split_at_checked(3).ok_or_else(|| {
+                                NgetError::NetworkError(format!("Invalid reply: {}", line))
+                            })?;
+                            code = Some(start.to_string());
+                            !rest.starts_with('-')
+                        }
+                    };
+                    if !text.is_empty() {
+                        text.push('\n');
+                    }
+                    text.push_str(reply_text(line));
+                    if done {
+                        break;
+                    }
+                }
+
+                let code = code.unwrap_or_default();
+                let code = code.parse().map_err(|_| {
+                    NgetError::NetworkError(format!("Invalid reply code: {}", code))
+                })?;
+                Ok(Reply { code, text })
+            },
+            || NgetError::ReadTimeout(self.url.clone()),
+        )
+        .await
+    }
+
+    /// Sends a command and reads its reply.
+    async fn command(&mut self, command: &str) -> Result<Reply, NgetError> {
+        match command.starts_with("PASS ") {
+            true => log::info!("FTP command: PASS ****"),
+            false => log::info!("FTP command: {}", command),
+        }
+
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .await
+            .map_err(|e| NgetError::NetworkError(format!("Failed to send command: {}", e)))?;
+        stream
+            .flush()
+            .await
+            .map_err(|e| NgetError::NetworkError(format!("Failed to send command: {}", e)))?;
+
+        self.read_reply().await
+    }
+
+    /// Sends a command, failing unless the reply has one of the expected codes.
+    async fn expect(&mut self, command: &str, codes: &[u16]) -> Result<Reply, NgetError> {
+        let reply = self.command(command).await?;
+        check_reply(&self.url, command, reply, codes)
+    }
+}
+
+/// Gets the text of a reply line, without its code if it starts with one.
+/// Lines in the middle of a multi-line reply may not.
+fn reply_text(line: &str) -> &str {
+    let coded = line
+        .get(..3)
+        .is_some_and(|code| code.bytes().all(|b| b.is_ascii_digit()));
+    match (coded, line.get(3..4)) {
+        (true, None) => "",
+        (true, Some("-" | " ")) => &line[4..],
+        _ => line,
+    }
+}
+
+/// Fails unless the reply has one of the expected codes.
+/// A missing file is reported as an invalid URL.
+fn check_reply(url: &str, command: &str, reply: Reply, codes: &[u16]) -> Result<Reply, NgetError> {
+    if codes.contains(&reply.code) {
+        return Ok(reply);
+    }
+
+    let verb = command.split(' ').next().unwrap_or_default();
+    let message = format!("{} failed for {}: {} {}", verb, url, reply.code, reply.text);
+    match reply.code {
+        550 => Err(NgetError::InvalidUrl(message)),
+        530 => Err(NgetError::ConfigurationError(message)),
+        _ => Err(NgetError::NetworkError(message)),
+    }
+}
+
+/// A data connection, connected in passive mode or waiting for the server in active mode.
+enum DataConnection {
+    Connected(TcpStream),
+    Listening(TcpListener),
+}
+
+/// A logged in FTP session.
+struct FtpSession {
+    control: Control,
+    /// Connector and server name that data connections are protected with, after `AUTH TLS`
+    tls: Option<(TlsConnector, ServerName<'static>)>,
+    peer: SocketAddr,
+    local: SocketAddr,
+    active: bool,
+    connect_timeout: Option<Duration>,
+    /// True if the server supports `MLSD` listings
+    mlsd: bool,
+}
+
+impl FtpSession {
+    /// Connects and logs in, with the credentials in the URL or anonymously.
+    /// `ftps://` URLs switch the control connection to TLS with `AUTH TLS` first.
+    async fn connect(downloader: &Downloader, url: &Url) -> Result<Self, NgetError> {
+        if downloader.proxy_config.is_enabled() {
+            return Err(NgetError::ConfigurationError(
+                "FTP can't be used through a proxy.".to_string(),
+            ));
+        }
+
+        // Line breaks in the credentials would end the command and start another
+        let user = match url.username() {
+            "" => "anonymous".to_string(),
+            user => decode(user),
+        };
+        let password = url.password().map(decode);
+        if user.contains(['\r', '\n'])
+            || password
+                .as_deref()
+                .is_some_and(|p| p.contains(['\r', '\n']))
+        {
+            return Err(NgetError::InvalidUrl(format!(
+                "Invalid FTP credentials for {}",
+                url.host_str().unwrap_or_default()
+            )));
+        }
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| NgetError::InvalidUrl(format!("No host in URL: {}", url)))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = url.port().unwrap_or(21);
+
+        log::info!("Connecting to FTP server {}:{}", host, port);
+        let stream = within(
+            downloader.connect_timeout,
+            async {
+                TcpStream::connect((host, port)).await.map_err(|e| {
+                    NgetError::ConnectionError(format!("Failed to connect to {}: {}", url, e))
+                })
+            },
+            || NgetError::ConnectionTimeout(url.to_string()),
+        )
+        .await?;
+        let peer = stream
+            .peer_addr()
+            .map_err(|e| NgetError::ConnectionError(e.to_string()))?;
+        let local = stream
+            .local_addr()
+            .map_err(|e| NgetError::ConnectionError(e.to_string()))?;
+
+        let mut control = Control {
+            url: url.to_string(),
+            stream: BufReader::new(Box::new(stream)),
+            read_timeout: downloader.read_timeout,
+        };
+        let greeting = control.read_reply().await?;
+        check_reply(control.url.as_str(), "Connect", greeting, &[220])?;
+
+        let mut tls = None;
+        if url.scheme() == "ftps" {
+            let reply = control.command("AUTH TLS").await?;
+            if reply.code != 234 {
+                return Err(NgetError::TlsError(format!(
+                    "{} does not support AUTH TLS: {} {}",
+                    url, reply.code, reply.text
+                )));
+            }
+
+            let config = build_rustls_config(&downloader.tls_config, Vec::new())?;
+            let connector = TlsConnector::from(Arc::new(config));
+            let name = ServerName::try_from(host.to_string())
+                .map_err(|e| NgetError::TlsError(format!("Invalid server name {}: {}", host, e)))?;
+            let stream = connector
+                .connect(name.clone(), control.stream.into_inner())
+                .await
+                .map_err(|e| NgetError::TlsError(format!("TLS handshake failed: {}", e)))?;
+            control.stream = BufReader::new(Box::new(stream));
+
+            // Data connections are protected too
+            control.expect("PBSZ 0", &[200]).await?;
+            control.expect("PROT P", &[200]).await?;
+            tls = Some((connector, name));
+        }
+
+        let reply = control
+            .expect(&format!("USER {}", user), &[230, 331])
+            .await?;
+        if reply.code == 331 {
+            let password = password.as_deref().unwrap_or("anonymous@");
+            control
+                .expect(&format!("PASS {}", password), &[230, 202])
+                .await?;
+        }
+
+        let features = control.command("FEAT").await?;
+        let mlsd = features.code == 211
+            && features
+                .text
+                .lines()
+                .any(|line| line.trim().to_ascii_uppercase().starts_with("MLST"));
+
+        control.expect("TYPE I", &[200]).await?;
+
+        Ok(FtpSession {
+            control,
+            tls,
+            peer,
+            local,
+            active: downloader.ftp_active,
+            connect_timeout: downloader.connect_timeout,
+            mlsd,
+        })
+    }
+
+    /// Gets the size of a file, if the server supports `SIZE`.
+    async fn size(&mut self, path: &str) -> Result<Option<u64>, NgetError> {
+        let reply = self.control.command(&format!("SIZE {}", path)).await?;
+        Ok(match reply.code {
+            213 => reply.text.trim().parse().ok(),
+            _ => None,
+        })
+    }
+
+    /// Gets the `MDTM` modification time of a file as the server gives it.
+    async fn modified(&mut self, path: &str) -> Result<Option<String>, NgetError> {
+        let reply = self.control.command(&format!("MDTM {}", path)).await?;
+        Ok(match reply.code {
+            213 => Some(reply.text.trim().to_string()),
+            _ => None,
+        })
+    }
+
+    /// Sets up a data connection in passive mode with `EPSV` or `PASV`,
+    /// or in active mode with `PORT` or `EPRT`.
+    async fn open_data(&mut self) -> Result<DataConnection, NgetError> {
+        let url = self.control.url.clone();
+
+        if self.active {
+            let listener = TcpListener::bind((self.local.ip(), 0))
+                .await
+                .map_err(|e| NgetError::ConnectionError(format!("Failed to listen: {}", e)))?;
+            let port = listener
+                .local_addr()
+                .map_err(|e| NgetError::ConnectionError(e.to_string()))?
+                .port();
+            let command = match self.local.ip() {
+                IpAddr::V4(ip) => {
+                    let [a, b, c, d] = ip.octets();
+                    format!("PORT {},{},{},{},{},{}", a, b, c, d, port >> 8, port & 0xff)
+                }
+                IpAddr::V6(ip) => format!("EPRT |2|{}|{}|", ip, port),
+            };
+            self.control.expect(&command, &[200]).await?;
+            return Ok(DataConnection::Listening(listener));
+        }
+
+        // The address the server gives is ignored, as it may be wrong behind NAT
+        let reply = self.control.command("EPSV").await?;
+        let port = match reply.code {
+            229 => parse_epsv(&reply.text),
+            _ => {
+                let reply = self.control.expect("PASV", &[227]).await?;
+                parse_pasv(&reply.text)
+            }
+        }
+        .ok_or_else(|| NgetError::NetworkError(format!("Invalid passive reply for {}", url)))?;
+
+        let address = SocketAddr::new(self.peer.ip(), port);
+        let stream = within(
+            self.connect_timeout,
+            async {
+                TcpStream::connect(address).await.map_err(|e| {
+                    NgetError::ConnectionError(format!("Failed to open data connection: {}", e))
+                })
+            },
+            || NgetError::ConnectionTimeout(url.clone()),
+        )
+        .await?;
+        Ok(DataConnection::Connected(stream))
+    }
+
+    /// Sends a command that transfers data, such as `RETR` or `LIST`, and returns its
+    /// data connection.
+    async fn transfer(&mut self, command: &str) -> Result<Box<dyn FtpStream>, NgetError> {
+        let data = self.open_data().await?;
+        self.control.expect(command, &[125, 150]).await?;
+
+        let url = self.control.url.clone();
+        let stream = match data {
+            DataConnection::Connected(stream) => stream,
+            DataConnection::Listening(listener) => {
+                within(
+                    self.connect_timeout,
+                    async {
+                        listener
+                            .accept()
+                            .await
+                            .map(|(stream, _)| stream)
+                            .map_err(|e| {
+                                NgetError::ConnectionError(format!(
+                                    "Failed to accept data connection: {}",
+                                    e
+                                ))
+                            })
+                    },
+                    || NgetError::ConnectionTimeout(url.clone()),
+                )
+                .await?
+            }
+        };
+
+        match &self.tls {
+            Some((connector, name)) => {
+                let stream = connector
+                    .connect(name.clone(), stream)
+                    .await
+                    .map_err(|e| NgetError::TlsError(format!("TLS handshake failed: {}", e)))?;
+                Ok(Box::new(stream))
+            }
+            None => Ok(Box::new(stream)),
+        }
+    }
+
+    /// Reads the reply that ends a transfer.
+    async fn finish(&mut self) -> Result<(), NgetError> {
+        let reply = self.control.read_reply().await?;
+        check_reply(&self.control.url, "Transfer", reply, &[226, 250]).map(|_| ())
+    }
+
+    /// Lists a directory with `MLSD`, or `LIST` if the server doesn't support it.
+    async fn list(&mut self, path: &str) -> Result<Vec<RemoteFile>, NgetError> {
+        let command = match self.mlsd {
+            true => "MLSD",
+            false => "LIST",
+        };
+        let command = match path.is_empty() {
+            true => command.to_string(),
+            false => format!("{} {}", command, path),
+        };
+
+        let data = self.transfer(&command).await?;
+        let mut content = Vec::new();
+        let read = within(
+            self.control.read_timeout,
+            async {
+                let mut data = data.take(LISTING_SIZE_LIMIT + 1);
+                match data.read_to_end(&mut content).await {
+                    Err(e) if e.kind() != ErrorKind::UnexpectedEof => Err(NgetError::NetworkError(
+                        format!("Failed to read listing: {}", e),
+                    )),
+                    _ => Ok(()),
+                }
+            },
+            || NgetError::ReadTimeout(self.control.url.clone()),
+        )
+        .await;
+        read?;
+        if content.len() as u64 > LISTING_SIZE_LIMIT {
+            return Err(NgetError::NetworkError(format!(
+                "Listing of {} is too large",
+                self.control.url
+            )));
+        }
+        self.finish().await?;
+
+        let content = String::from_utf8_lossy(&content);
+        Ok(match self.mlsd {
+            true => parse_mlsd(&content),
+            false => parse_list(&content),
+        })
+    }
+
+    /// Turns the data connection of a `RETR` into a body stream, which checks the reply
+    /// that ends the transfer once the data is read.
+    fn into_body(
+        mut self,
+        data: Box<dyn FtpStream>,
+    ) -> futures_util::stream::BoxStream<'static, Result<Bytes, NgetError>> {
+        let chunks = stream::unfold(Some(data), |data| async move {
+            let mut data = data?;
+            let mut buffer = vec![0; CHUNK_SIZE];
+            match data.read(&mut buffer).await {
+                Ok(0) => None,
+                Ok(read) => {
+                    buffer.truncate(read);
+                    Some((Ok(Bytes::from(buffer)), Some(data)))
+                }
+                // Servers may close TLS data connections without a close_notify
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => None,
+                Err(e) => Some((
+                    Err(NgetError::NetworkError(format!(
+                        "Failed to read data: {}",
+                        e
+                    ))),
+                    None,
+                )),
+            }
+        });
+        let done = stream::once(async move { self.finish().await })
+            .filter_map(|result| async move { result.err().map(Err) });
+
+        chunks.chain(done).boxed()
+    }
+}
+
+/// Downloads an `ftp://` or `ftps://` URL to `file_path`, resuming a partial file with
+/// `REST`. The `MDTM` time guards the resume the way an ETag does over HTTP, and is set
+/// as the saved file's modification time.
+pub(crate) async fn fetch_ftp(
+    downloader: &Downloader,
+    url: &str,
+    file_path: &Path,
+    observer: &dyn ProgressObserver,
+    cancel: &CancellationToken,
+) -> Result<DownloadOutcome, NgetError> {
+    let started = Instant::now();
+    let parsed_url = Url::parse(url)
+        .map_err(|e| NgetError::InvalidUrl(format!("Failed to parse URL: {}", e)))?;
+    let path = remote_path(&parsed_url)?;
+
+    let mut session =
+        cancellable(cancel, url, FtpSession::connect(downloader, &parsed_url)).await?;
+    let (size, mdtm) = cancellable(cancel, url, async {
+        Ok((session.size(&path).await?, session.modified(&path).await?))
+    })
+    .await?;
+    let modified = mdtm.as_deref().and_then(parse_mdtm);
+
+    // Only resume if the file hasn't changed since the partial file was saved
+    let (existing_size, _) = resumable_size(file_path, url, size, Some(mdtm.as_deref())).await;
+
+    // There is nothing past the end to transfer, so the file is already complete
+    if existing_size > 0 && size == Some(existing_size) {
+        return already_complete(
+            downloader,
+            file_path,
+            url.to_string(),
+            existing_size,
+            None,
+            started,
+        )
+        .await;
+    }
+
+    let (resume_from, data) = cancellable(cancel, url, async {
+        let mut resume_from = 0;
+        if existing_size > 0 {
+            let reply = session
+                .control
+                .command(&format!("REST {}", existing_size))
+                .await?;
+            if reply.code == 350 {
+                resume_from = existing_size;
+            }
+        }
+        let data = session.transfer(&format!("RETR {}", path)).await?;
+        Ok((resume_from, data))
+    })
+    .await?;
+
+    let status = match resume_from {
+        0 => StatusCode::OK,
+        _ => StatusCode::PARTIAL_CONTENT,
+    };
+    observer.on_event(&DownloadEvent::HeadersReceived {
+        final_url: url.to_string(),
+        status,
+        content_length: size.map(|size| size - resume_from),
+        resume_from,
+    });
+
+    // The resume state keeps the MDTM time in place of an ETag
+    let mut headers = HeaderMap::new();
+    if let Some(value) = mdtm
+        .as_deref()
+        .and_then(|mdtm| HeaderValue::from_str(mdtm).ok())
+    {
+        headers.insert(header::ETAG, value);
+    }
+    let response = TransportResponse {
+        url: url.to_string(),
+        status,
+        version: Version::default(),
+        headers,
+        body: session.into_body(data),
+    };
+
+    let body = write_body(
+        url,
+        response,
+        file_path,
+        resume_from,
+        observer,
+        &Compression::None,
+        downloader.read_timeout,
+        downloader.checksum,
+        cancel,
+    )
+    .await?;
+
+    if let Some(modified) = modified {
+        if let Err(e) = set_modified(file_path, modified) {
+            log::warn!("Failed to set the time of {}: {}", file_path.display(), e);
+        }
+    }
+
+    Ok(DownloadOutcome {
+        path: file_path.to_path_buf(),
+        final_url: url.to_string(),
+        bytes: resume_from + body.written,
+        received: body.received,
+        content_encoding: None,
+        sha256: body.sha256,
+        status,
+        etag: None,
+        content_type: None,
+        elapsed: started.elapsed(),
+    })
+}
+
+/// Lists an FTP directory, whose URL ends with `/`, as absolute URLs.
+pub async fn list_directory(
+    downloader: &Downloader,
+    url: &Url,
+) -> Result<Vec<ListingEntry>, NgetError> {
+    let path = remote_path(url)?;
+    let mut session = cancellable(
+        &downloader.cancel_token,
+        url.as_str(),
+        FtpSession::connect(downloader, url),
+    )
+    .await?;
+    let files = cancellable(&downloader.cancel_token, url.as_str(), session.list(&path)).await?;
+
+    Ok(files
+        .into_iter()
+        .filter_map(|file| {
+            let mut child = url.clone();
+            child.set_query(None);
+            {
+                let mut segments = child.path_segments_mut().ok()?;
+                segments.pop_if_empty().push(&file.name);
+                if file.is_dir {
+                    segments.push("");
+                }
+            }
+            Some(ListingEntry {
+                url: child,
+                is_dir: file.is_dir,
+                modified: file.modified,
+                size: file.size,
+            })
+        })
+        .collect())
+}
+
+/// Expands the wildcards in the last segment of an FTP URL to the files they match,
+/// by listing the directory.
+pub async fn expand_glob(downloader: &Downloader, url: &str) -> Result<Vec<String>, NgetError> {
+    let parsed_url = Url::parse(url)
+        .map_err(|e| NgetError::InvalidUrl(format!("Failed to parse URL: {}", e)))?;
+    // A `?` wildcard starts what the URL parser takes as the query
+    let name = url.rsplit('/').next().unwrap_or_default();
+    let pattern = decode(name);
+    let directory = parsed_url
+        .join("./")
+        .map_err(|e| NgetError::InvalidUrl(format!("Failed to parse URL: {}", e)))?;
+
+    let matches: Vec<String> = list_directory(downloader, &directory)
+        .await?
+        .into_iter()
+        .filter(|entry| !entry.is_dir)
+        .filter(|entry| {
+            let name = entry
+                .url
+                .path_segments()
+                .and_then(|mut segments| segments.next_back());
+            glob_match(&pattern, &decode(name.unwrap_or_default()))
+        })
+        .map(|entry| entry.url.to_string())
+        .collect();
+
+    match matches.is_empty() {
+        true => Err(NgetError::InvalidUrl(format!("No files match {}", url))),
+        false => Ok(matches),
+    }
+}
+
+/// Parses an `MLSD` listing. The current and parent directory entries are left out.
+pub fn parse_mlsd(content: &str) -> Vec<RemoteFile> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (facts, name) = line.split_once(' ')?;
+            let mut file = RemoteFile {
+                name: name.to_string(),
+                is_dir: false,
+                size: None,
+                modified: None,
+            };
+
+            for fact in facts.split(';') {
+                let Some((key, value)) = fact.split_once('=') else {
+                    continue;
+                };
+                match key.to_ascii_lowercase().as_str() {
+                    "type" => match value.to_ascii_lowercase().as_str() {
+                        "cdir" | "pdir" => return None,
+                        "dir" => file.is_dir = true,
+                        _ => {}
+                    },
+                    "size" => file.size = value.parse().ok(),
+                    "modify" => file.modified = parse_mdtm(value),
+                    _ => {}
+                }
+            }
+            Some(file)
+        })
+        .collect()
+}
+
+/// Parses a `LIST` listing in the Unix `ls -l` or the DOS format.
+/// Links are listed as files, and the current and parent directory entries are left out.
+pub fn parse_list(content: &str) -> Vec<RemoteFile> {
+    content
+        .lines()
+        .filter_map(|line| parse_unix_line(line).or_else(|| parse_dos_line(line)))
+        .filter(|file| file.name != "." && file.name != "..")
+        .collect()
+}
+
+/// Parses a line such as `-rw-r--r-- 1 ftp ftp 1024 Mar 01 12:00 name`.
+/// The group may be missing, and a year is shown in place of the time for old files.
+fn parse_unix_line(line: &str) -> Option<RemoteFile> {
+    let kind = line.chars().next()?;
+    if !matches!(kind, '-' | 'd' | 'l') {
+        return None;
+    }
+
+    let (fields, name) = split_fields(line, 8)
+        .filter(|(fields, _)| month_number(fields[5]).is_some())
+        .or_else(|| split_fields(line, 7))?;
+    let [size, month, day, time] = fields[fields.len() - 4..] else {
+        return None;
+    };
+    let month = month_number(month)?;
+    let day = day.parse().ok()?;
+
+    let modified = match time.split_once(':') {
+        Some((hour, minute)) => {
+            let (hour, minute) = (hour.parse().ok()?, minute.parse().ok()?);
+            // The year is left out for times within the last six months
+            let now = SystemTime::now();
+            let this_year = 1970
+                + now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64 / 31_556_952;
+            (this_year - 1..=this_year + 1)
+                .rev()
+                .filter_map(|year| utc_time(year, month, day, hour, minute, 0))
+                .find(|time| *time <= now + Duration::from_secs(86400))
+        }
+        None => utc_time(time.parse().ok()?, month, day, 0, 0, 0),
+    };
+
+    let name = match kind {
+        'l' => name.split(" -> ").next().unwrap_or(name),
+        _ => name,
+    };
+    Some(RemoteFile {
+        name: name.to_string(),
+        is_dir: kind == 'd',
+        size: size.parse().ok(),
+        modified,
+    })
+}
+
+/// Parses a line such as `03-01-24  12:00PM       <DIR>          name`.
+fn parse_dos_line(line: &str) -> Option<RemoteFile> {
+    let (fields, name) = split_fields(line, 3)?;
+    let mut date = fields[0].split('-');
+    let (month, day, year) = (date.next()?, date.next()?, date.next()?);
+    let year: i64 = year.parse().ok()?;
+    let year = match year {
+        0..70 => 2000 + year,
+        70..100 => 1900 + year,
+        _ => year,
+    };
+
+    let time = fields[1].to_ascii_uppercase();
+    let (clock, pm) = match time.strip_suffix("PM") {
+        Some(clock) => (clock, true),
+        None => (time.strip_suffix("AM").unwrap_or(&time), false),
+    };
+    let (hour, minute) = clock.split_once(':')?;
+    let hour: u64 = hour.parse().ok()?;
+    let hour = match (hour, pm) {
+        (12, false) => 0,
+        (12, true) => 12,
+        (hour, true) => hour + 12,
+        (hour, false) => hour,
+    };
+
+    let is_dir = fields[2].eq_ignore_ascii_case("<DIR>");
+    Some(RemoteFile {
+        name: name.to_string(),
+        is_dir,
+        size: match is_dir {
+            true => None,
+            false => Some(fields[2].parse().ok()?),
+        },
+        modified: utc_time(
+            year,
+            month.parse().ok()?,
+            day.parse().ok()?,
+            hour,
+            minute.parse().ok()?,
+            0,
+        ),
+    })
+}
+
+/// Splits the first `count` whitespace separated fields off a line, keeping the rest,
+/// which may contain spaces, whole.
+fn split_fields(line: &str, count: usize) -> Option<(Vec<&str>, &str)> {
+    let mut fields = Vec::with_capacity(count);
+    let mut rest = line.trim_start();
+    for _ in 0..count {
+        let end = rest.find(char::is_whitespace)?;
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    match rest.is_empty() {
+        true => None,
+        false => Some((fields, rest)),
+    }
+}
+
+/// Parses an `MDTM` or `MLSD` time, such as `20240301120000` or `20240301120000.123`, in UTC.
+pub fn parse_mdtm(value: &str) -> Option<SystemTime> {
+    let digits = value.get(..14)?;
+    if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| digits[range].parse::<u64>().ok();
+
+    utc_time(
+        number(0..4)? as i64,
+        number(4..6)? as u32,
+        number(6..8)? as u32,
+        number(8..10)?,
+        number(10..12)?,
+        number(12..14)?,
+    )
+}
+
+/// Parses the port from an `EPSV` reply such as `Entering Extended Passive Mode (|||6446|)`.
+fn parse_epsv(text: &str) -> Option<u16> {
+    text.split('|').nth(3)?.parse().ok()
+}
+
+/// Parses the port from a `PASV` reply such as `Entering Passive Mode (127,0,0,1,25,46)`.
+fn parse_pasv(text: &str) -> Option<u16> {
+    let numbers = text
+        .split(|c: char| !(c.is_ascii_digit() || c == ','))
+        .find(|part| part.matches(',').count() == 5)?;
+    let numbers: Vec<u8> = numbers
+        .split(',')
+        .map(|number| number.parse().ok())
+        .collect::<Option<_>>()?;
+    Some(u16::from(numbers[4]) * 256 + u16::from(numbers[5]))
+}
+
+/// Gets the path of a URL as sent to the server: decoded, and relative to the login
+/// directory.
+fn remote_path(url: &Url) -> Result<String, NgetError> {
+    let path = decode(url.path().strip_prefix('/').unwrap_or(url.path()));
+    match path.contains(['\r', '\n']) {
+        true => Err(NgetError::InvalidUrl(format!(
+            "Invalid FTP path in {}",
+            url
+        ))),
+        false => Ok(path),
+    }
+}
+
+/// Decodes a percent-encoded URL part.
+fn decode(part: &str) -> String {
+    percent_decode_str(part).decode_utf8_lossy().into_owned()
+}
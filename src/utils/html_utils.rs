@@ -0,0 +1,449 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::utils::css_utils::extract_css_links;
+
+use std::ops::Range;
+
+/// Elements whose content is raw text rather than markup.
+const RAW_TEXT_ELEMENTS: [&str; 5] = ["script", "style", "textarea", "title", "xmp"];
+
+/// How a page uses a link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// A link to another document, e.g. `<a href>`
+    Hyperlink,
+    /// A resource needed to render the page, e.g. `<img src>` or a stylesheet
+    Requisite,
+}
+
+/// A link found in an HTML document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtmlLink {
+    /// The link as written, with character references decoded
+    pub value: String,
+    /// Byte range of the link in the document, as written
+    pub span: Range<usize>,
+    pub kind: LinkKind,
+    /// True if the link is in an attribute value, rather than the raw text of a `<style>`
+    pub in_attribute: bool,
+}
+
+/// An attribute of a start tag.
+struct Attribute {
+    name: String,
+    value: String,
+    span: Range<usize>,
+}
+
+/// A start tag, with the content that follows it if the element holds raw text.
+struct Tag {
+    name: String,
+    attributes: Vec<Attribute>,
+    raw_text: Option<Range<usize>>,
+}
+
+impl Tag {
+    fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.name == name)
+    }
+}
+
+/// Finds the links in an HTML document, in document order, including those in
+/// `<style>` elements and `style` attributes. `<base href>` isn't included, see [`base_href`].
+pub fn extract_links(html: &[u8]) -> Vec<HtmlLink> {
+    let mut links = Vec::new();
+
+    for tag in Tags::new(html) {
+        for attribute in &tag.attributes {
+            if attribute.name == "style" {
+                links.extend(style_links(html, attribute.span.clone(), true));
+                continue;
+            }
+
+            let Some(kind) = link_kind(&tag, &attribute.name) else {
+                continue;
+            };
+
+            if attribute.name == "srcset" {
+                links.extend(srcset_links(html, attribute, kind));
+            } else if !attribute.value.trim().is_empty() {
+                links.push(HtmlLink {
+                    value: attribute.value.trim().to_string(),
+                    span: attribute.span.clone(),
+                    kind,
+                    in_attribute: true,
+                });
+            }
+        }
+
+        if let (true, Some(raw_text)) = (tag.name == "style", tag.raw_text) {
+            links.extend(style_links(html, raw_text, false));
+        }
+    }
+
+    links
+}
+
+/// Finds the resources referenced by CSS embedded in a document, either in a `style`
+/// attribute or in the raw text of a `<style>`, where character references aren't decoded.
+fn style_links(html: &[u8], css: Range<usize>, in_attribute: bool) -> Vec<HtmlLink> {
+    extract_css_links(&html[css.clone()])
+        .into_iter()
+        .map(|link| {
+            let mut span = css.start + link.span.start..css.start + link.span.end;
+            if !in_attribute {
+                return HtmlLink {
+                    value: link.value,
+                    span,
+                    kind: LinkKind::Requisite,
+                    in_attribute,
+                };
+            }
+
+            // Quotes inside a `style` attribute are written as references, e.g. `url(&quot;a.png&quot;)`
+            for quote in ["&quot;", "&#34;", "&#39;", "&apos;"] {
+                let raw = &html[span.clone()];
+                if raw.len() >= 2 * quote.len()
+                    && raw.starts_with(quote.as_bytes())
+                    && raw.ends_with(quote.as_bytes())
+                {
+                    span = span.start + quote.len()..span.end - quote.len();
+                }
+            }
+
+            HtmlLink {
+                value: decode_references(&String::from_utf8_lossy(&html[span.clone()])),
+                span,
+                kind: LinkKind::Requisite,
+                in_attribute,
+            }
+        })
+        .collect()
+}
+
+/// Gets the `<base href>` of a document, which relative links are resolved against.
+pub fn base_href(html: &[u8]) -> Option<String> {
+    base_link(html).map(|link| link.value)
+}
+
+/// Gets the `<base href>` of a document like [`base_href`], with where it is written.
+pub fn base_link(html: &[u8]) -> Option<HtmlLink> {
+    Tags::new(html)
+        .find(|tag| tag.name == "base")
+        .and_then(|tag| {
+            tag.attribute("href").map(|href| HtmlLink {
+                value: href.value.trim().to_string(),
+                span: href.span.clone(),
+                kind: LinkKind::Hyperlink,
+                in_attribute: true,
+            })
+        })
+        .filter(|link| !link.value.is_empty())
+}
+
+/// Decides whether an attribute of a tag holds a link, and what kind.
+fn link_kind(tag: &Tag, attribute: &str) -> Option<LinkKind> {
+    match (tag.name.as_str(), attribute) {
+        ("a" | "area", "href") => Some(LinkKind::Hyperlink),
+        ("link", "href") => {
+            let rel = tag
+                .attribute("rel")
+                .map(|rel| rel.value.to_ascii_lowercase())
+                .unwrap_or_default();
+            let requisite = rel.split_ascii_whitespace().any(|rel| {
+                matches!(
+                    rel,
+                    "stylesheet" | "icon" | "apple-touch-icon" | "preload" | "manifest"
+                )
+            });
+            match requisite {
+                true => Some(LinkKind::Requisite),
+                false => Some(LinkKind::Hyperlink),
+            }
+        }
+        ("img" | "source", "src" | "srcset")
+        | ("script" | "iframe" | "frame" | "embed" | "audio" | "track", "src")
+        | ("video", "src" | "poster")
+        | ("object", "data")
+        | ("body" | "table" | "td" | "th", "background") => Some(LinkKind::Requisite),
+        ("input", "src") => {
+            let image = tag
+                .attribute("type")
+                .is_some_and(|kind| kind.value.eq_ignore_ascii_case("image"));
+            image.then_some(LinkKind::Requisite)
+        }
+        _ => None,
+    }
+}
+
+/// Splits a `srcset` attribute into its image candidates, skipping the width or density of each.
+fn srcset_links(html: &[u8], attribute: &Attribute, kind: LinkKind) -> Vec<HtmlLink> {
+    let raw = &html[attribute.span.clone()];
+    let mut links = Vec::new();
+    let mut pos = 0;
+
+    while pos < raw.len() {
+        while pos < raw.len() && (raw[pos].is_ascii_whitespace() || raw[pos] == b',') {
+            pos += 1;
+        }
+        let start = pos;
+        while pos < raw.len() && !raw[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        // A URL directly followed by a comma has no descriptor
+        let mut end = pos;
+        while end > start && raw[end - 1] == b',' {
+            end -= 1;
+        }
+        if end > start {
+            links.push(HtmlLink {
+                value: decode_references(&String::from_utf8_lossy(&raw[start..end])),
+                span: attribute.span.start + start..attribute.span.start + end,
+                kind,
+                in_attribute: true,
+            });
+        }
+        // Skip the descriptor up to the next candidate
+        while pos < raw.len() && raw[pos] != b',' {
+            pos += 1;
+        }
+    }
+
+    links
+}
+
+/// Iterates over the start tags of a document, skipping comments, end tags and text.
+struct Tags<'a> {
+    html: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Tags<'a> {
+    fn new(html: &'a [u8]) -> Self {
+        Tags { html, pos: 0 }
+    }
+
+    /// Moves past the next occurrence of `pattern`, or to the end of the document.
+    fn skip_past(&mut self, pattern: &[u8]) {
+        self.pos = find(self.html, self.pos, pattern)
+            .map(|index| index + pattern.len())
+            .unwrap_or(self.html.len());
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.html.len() && self.html[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    /// Reads the attributes of a tag, leaving the position after its `>`.
+    fn attributes(&mut self) -> Vec<Attribute> {
+        let html = self.html;
+        let mut attributes = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            match html.get(self.pos) {
+                None => break,
+                Some(b'>') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'/') => {
+                    self.pos += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let name_start = self.pos;
+            while self.pos < html.len()
+                && !html[self.pos].is_ascii_whitespace()
+                && !matches!(html[self.pos], b'=' | b'>' | b'/')
+            {
+                self.pos += 1;
+            }
+            let name = String::from_utf8_lossy(&html[name_start..self.pos]).to_ascii_lowercase();
+
+            self.skip_whitespace();
+            if html.get(self.pos) != Some(&b'=') {
+                attributes.push(Attribute {
+                    name,
+                    value: String::new(),
+                    span: self.pos..self.pos,
+                });
+                continue;
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+
+            let span = match html.get(self.pos) {
+                Some(&quote @ (b'"' | b'\'')) => {
+                    let start = self.pos + 1;
+                    let end = html[start..]
+                        .iter()
+                        .position(|&byte| byte == quote)
+                        .map(|index| start + index)
+                        .unwrap_or(html.len());
+                    self.pos = (end + 1).min(html.len());
+                    start..end
+                }
+                _ => {
+                    let start = self.pos;
+                    while self.pos < html.len()
+                        && !html[self.pos].is_ascii_whitespace()
+                        && html[self.pos] != b'>'
+                    {
+                        self.pos += 1;
+                    }
+                    start..self.pos
+                }
+            };
+
+            attributes.push(Attribute {
+                name,
+                value: decode_references(&String::from_utf8_lossy(&html[span.clone()])),
+                span,
+            });
+        }
+
+        attributes
+    }
+}
+
+impl Iterator for Tags<'_> {
+    type Item = Tag;
+
+    fn next(&mut self) -> Option<Tag> {
+        let html = self.html;
+
+        loop {
+            self.pos = self.pos + html[self.pos..].iter().position(|&byte| byte == b'<')?;
+
+            let rest = &html[self.pos..];
+            if rest.starts_with(b"<!--") {
+                self.skip_past(b"-->");
+                continue;
+            }
+            if rest.starts_with(b"</") || rest.starts_with(b"<!") || rest.starts_with(b"<?") {
+                self.skip_past(b">");
+                continue;
+            }
+
+            let name_start = self.pos + 1;
+            let name_end = name_start
+                + html[name_start..]
+                    .iter()
+                    .position(|byte| !byte.is_ascii_alphanumeric())
+                    .unwrap_or(html.len() - name_start);
+            if name_end == name_start {
+                // A lone `<` in text
+                self.pos += 1;
+                continue;
+            }
+
+            let name = String::from_utf8_lossy(&html[name_start..name_end]).to_ascii_lowercase();
+            self.pos = name_end;
+            let attributes = self.attributes();
+
+            // Markup inside scripts and the like is just text
+            let raw_text = match RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                true => {
+                    let start = self.pos;
+                    let end_tag = format!("</{}", name);
+                    self.pos =
+                        find_ignore_case(html, start, end_tag.as_bytes()).unwrap_or(html.len());
+                    Some(start..self.pos)
+                }
+                false => None,
+            };
+
+            return Some(Tag {
+                name,
+                attributes,
+                raw_text,
+            });
+        }
+    }
+}
+
+/// Finds `pattern` in `haystack` at or after `from`.
+fn find(haystack: &[u8], from: usize, pattern: &[u8]) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(pattern.len())
+        .position(|window| window == pattern)
+        .map(|index| from + index)
+}
+
+/// Finds `pattern` in `haystack` at or after `from`, ignoring ASCII case.
+fn find_ignore_case(haystack: &[u8], from: usize, pattern: &[u8]) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(pattern.len())
+        .position(|window| window.eq_ignore_ascii_case(pattern))
+        .map(|index| from + index)
+}
+
+/// Decodes the character references that commonly appear in URLs, e.g. `&amp;`.
+pub fn decode_references(value: &str) -> String {
+    if !value.contains('&') {
+        return value.to_string();
+    }
+
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) = rest.find('&') {
+        decoded.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        let reference = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .map(|end| &rest[1..end + 1]);
+        let character = reference.and_then(|reference| match reference {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let number = reference.strip_prefix('#')?;
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+
+        match (reference, character) {
+            (Some(reference), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[reference.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
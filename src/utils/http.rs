@@ -12,255 +12,689 @@
 // MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
 // GNU General Public License for more details.
 
-use crate::enums::HttpVersion;
+use crate::enums::{Compression, HttpVersion};
 use crate::error::NgetError;
-use crate::utils::client_utils::get_client;
+use crate::utils::compression_utils::ContentDecoder;
+use crate::utils::downloader::{DownloadOutcome, Downloader};
+use crate::utils::file_utils::{sha256_prefix, to_hex};
+use crate::utils::http3::record_alt_svc;
+use crate::utils::progress::{DownloadEvent, ProgressObserver};
 use crate::utils::proxy_utils::ProxyConfig;
-use crate::utils::resolver_utils::build_resolver;
-use crate::utils::url_utils::{self, get_file_name};
+use crate::utils::resume_utils::ResumeState;
+use crate::utils::tls_utils::TlsConfig;
+use crate::utils::transport::{Transport, TransportResponse};
+use crate::utils::url_utils;
+
+use bytes::Bytes;
 
 use futures_util::StreamExt;
 
-use indicatif::ProgressBar;
+use sha2::Digest;
 
-use reqwest::header;
-use reqwest::Version;
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{Method, StatusCode, Version};
 
-use std::error::Error;
 use std::fs::metadata;
+use std::future::Future;
+use std::io::SeekFrom;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use tokio::fs::OpenOptions;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use tokio_util::sync::CancellationToken;
+
+use url::Url;
 
+/// Downloads a URL into `save_dir`, resuming a partial file if one exists.
+/// `ftp://`, `file://` and `data:` URLs are handled by their own backends.
+/// See [`Downloader`] for more options.
+#[allow(clippy::too_many_arguments)]
 pub async fn download_file(
     url: &str,
     save_dir: &str,
     output_file_name: &Option<&str>,
-    progress_bar: &ProgressBar,
+    observer: Arc<dyn ProgressObserver>,
     http_version: &HttpVersion,
     config: &ProxyConfig,
+    tls_config: &TlsConfig,
+    compression: &Compression,
 ) -> Result<(), NgetError> {
-    match http_version {
-        HttpVersion::Http11 => {
-            http11_download(url, save_dir, *output_file_name, progress_bar, config).await
-        }
-        HttpVersion::Http2 => {
-            http2_download(url, save_dir, *output_file_name, progress_bar, config).await
-        }
-        HttpVersion::Http3 => {
-            http11_download(url, save_dir, *output_file_name, progress_bar, config).await
-        }
+    let mut request = Downloader::new()
+        .http_version(http_version.clone())
+        .proxy(config.clone())
+        .tls(tls_config.clone())
+        .compression(*compression)
+        .request(url)
+        .output_dir(save_dir)
+        .observer(observer);
+    if let Some(name) = output_file_name {
+        request = request.file_name(name);
     }
+
+    request.send().await.map(|_| ())
 }
 
-pub async fn http11_download(
+/// Downloads a URL into `file_path` once, resuming a partial file if one exists.
+/// The same pipeline is used for every HTTP version, only the transport differs.
+pub(crate) async fn fetch(
+    downloader: &Downloader,
     url: &str,
-    save_dir: &str,
-    output_file_name: Option<&str>,
-    progress_bar: &ProgressBar,
-    config: &ProxyConfig,
-) -> Result<(), NgetError> {
+    mut headers: HeaderMap,
+    file_path: &Path,
+    observer: &dyn ProgressObserver,
+    cancel: &CancellationToken,
+) -> Result<DownloadOutcome, NgetError> {
     let _ = env_logger::try_init();
 
-    // Create a client
-    let client = match get_client(config, &HttpVersion::Http11) {
-        Ok(client) => client,
-        Err(e) => {
-            // Handle the error (e.g., print it, log it, etc.)
-            return Err(e);
-        }
-    };
+    let started = Instant::now();
+    let parsed_url = url_utils::parse_url(url)?;
 
-    let parsed_url = url_utils::parse_url(url);
+    // Check if the file already exists and get its size, trusting the resume state if
+    // an earlier download stopped early. The server checks its ETag with If-Range.
+    let (existing_size, state) = resumable_size(file_path, url, None, None).await;
 
-    // Make sure the URL actually exists using DNS lookup
-    let resolver = build_resolver();
+    // Byte ranges refer to the encoded body, so resumed downloads are never decoded
+    let compression = if existing_size > 0 {
+        &Compression::None
+    } else {
+        &downloader.compression
+    };
+    headers.extend(request_headers(existing_size, compression));
 
-    let _dns_response = resolver
-        .lookup_ip(parsed_url.host_str().unwrap_or_default())
-        .await?;
+    // Only resume if the content hasn't changed since the partial file was saved
+    if let Some(etag) = state.and_then(|state| state.etag) {
+        if existing_size > 0 {
+            if let Ok(value) = HeaderValue::from_str(&etag) {
+                headers.insert(header::IF_RANGE, value);
+            }
+        }
+    }
 
-    // Extract file name from URL or use user arg file name.
-    let file_name = match output_file_name {
-        Some(name) => name.to_string(),
-        None => get_file_name(parsed_url),
-    };
+    log::info!("Sending request to URL: {}", url);
+    let response = send_request(downloader, &parsed_url, url, headers, cancel).await?;
+
+    let status = response.status;
+    let etag = response.header(header::ETAG).map(str::to_string);
+    let final_url = response.url.clone();
+    let content_type = response.header(header::CONTENT_TYPE).map(str::to_string);
+
+    // The server can't serve anything past the end, so the file is already complete
+    if existing_size > 0 && status == StatusCode::RANGE_NOT_SATISFIABLE {
+        return already_complete(
+            downloader,
+            file_path,
+            final_url,
+            existing_size,
+            etag,
+            started,
+        )
+        .await;
+    }
 
-    progress_bar.set_message(file_name.clone());
-    let file_path = Path::new(save_dir).join(&file_name);
+    // Servers that ignore the Range header send the whole file again
+    let resume_from = if status == StatusCode::PARTIAL_CONTENT {
+        existing_size
+    } else {
+        0
+    };
 
-    // Check if the file already exists and get its size
-    let existing_size = metadata(&file_path).map(|meta| meta.len()).unwrap_or(0);
+    observer.on_event(&DownloadEvent::HeadersReceived {
+        final_url: final_url.clone(),
+        status,
+        content_length: response.content_length(),
+        resume_from,
+    });
 
-    let mut request = client.get(url);
+    check_response(url, &response, &downloader.http_version)?;
 
-    // If the file exists, set the range to download only the remaining part
-    if existing_size > 0 {
-        request = request.header(header::RANGE, format!("bytes={}-", existing_size));
+    // Remember HTTP/3 alternatives for later requests to this origin, which only use
+    // them when HTTP/3 is asked for
+    let alt_svc = match downloader.http_version {
+        HttpVersion::Http3 => response.header(header::ALT_SVC),
+        _ => None,
+    };
+    if let Some(alt_svc) = alt_svc {
+        record_alt_svc(&parsed_url, alt_svc);
     }
 
-    println!("Sending request to URL: {}", url);
+    if response.content_length().is_none() {
+        log::warn!("Content length is not provided for {}.", url);
+    }
 
-    let response = request
-        .send()
-        .await
-        .map_err(|e| NgetError::HttpRequest(format!("Request error for {}: {}", url, e)))?;
+    let body = write_body(
+        url,
+        response,
+        file_path,
+        resume_from,
+        observer,
+        compression,
+        downloader.read_timeout,
+        downloader.checksum,
+        cancel,
+    )
+    .await?;
+
+    Ok(DownloadOutcome {
+        path: file_path.to_path_buf(),
+        final_url,
+        bytes: resume_from + body.written,
+        received: body.received,
+        content_encoding: body.content_encoding,
+        sha256: body.sha256,
+        status,
+        etag,
+        content_type,
+        elapsed: started.elapsed(),
+    })
+}
 
-    // Handle the response status
-    if !response.status().is_success() && response.status() != reqwest::StatusCode::PARTIAL_CONTENT
-    {
-        if response.status() == reqwest::StatusCode::NOT_FOUND {
-            return Err(NgetError::InvalidUrl(
-                "URL is Invalid. Please make sure the URL is correct.".to_string(),
-            ));
-        }
-        return Err(NgetError::HttpRequest(format!(
-            "Unexpected status: {}",
-            response.status()
-        )));
-    }
+/// Gets how many bytes of the partial file at `file_path` can be resumed, and the resume
+/// state saved with it. The whole file counts if there is no state. Nothing is resumed if
+/// the file was decoded, saved from another URL, or is larger than `size`, or if `version`
+/// is given and the ETag or modification time the state was saved with differs.
+pub(crate) async fn resumable_size(
+    file_path: &Path,
+    url: &str,
+    size: Option<u64>,
+    version: Option<Option<&str>>,
+) -> (u64, Option<ResumeState>) {
+    let changed = |state: &ResumeState| {
+        version.is_some_and(|version| state.etag.is_some() && state.etag.as_deref() != version)
+    };
+    let state = ResumeState::load(file_path).await;
+    let local_size = metadata(file_path).map(|meta| meta.len()).unwrap_or(0);
+    let existing_size = match &state {
+        Some(state) if state.decoded || state.url != url || changed(state) => 0,
+        Some(state) => state.bytes.min(local_size),
+        None => local_size,
+    };
+    let existing_size = match size {
+        Some(size) if existing_size > size => 0,
+        _ => existing_size,
+    };
+    (existing_size, state)
+}
 
-    // Set the total length in the progress bar if available
-    if let Some(total_length) = response.content_length() {
-        progress_bar.set_length(total_length + existing_size);
-    }
+/// Gets the outcome of a download whose partial file turned out to be complete, once its
+/// resume state is removed.
+pub(crate) async fn already_complete(
+    downloader: &Downloader,
+    file_path: &Path,
+    final_url: String,
+    size: u64,
+    etag: Option<String>,
+    started: Instant,
+) -> Result<DownloadOutcome, NgetError> {
+    ResumeState::remove(file_path).await;
+    let sha256 = match downloader.checksum {
+        true => Some(to_hex(&sha256_prefix(file_path, size).await?.finalize())),
+        false => None,
+    };
+    Ok(DownloadOutcome {
+        path: file_path.to_path_buf(),
+        final_url,
+        bytes: size,
+        received: 0,
+        content_encoding: None,
+        sha256,
+        status: StatusCode::RANGE_NOT_SATISFIABLE,
+        etag,
+        content_type: None,
+        elapsed: started.elapsed(),
+    })
+}
 
-    // Open the file in append mode for writing the downloaded data
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&file_path)
-        .await
-        .map_err(|e| NgetError::FileError(format!("Failed to open file: {}", e)))?;
+/// Connects and sends a GET request, giving up after the connect timeout or when cancelled.
+pub(crate) async fn send_request(
+    downloader: &Downloader,
+    parsed_url: &Url,
+    url: &str,
+    headers: HeaderMap,
+    cancel: &CancellationToken,
+) -> Result<TransportResponse, NgetError> {
+    send_method(
+        downloader,
+        Method::GET,
+        parsed_url,
+        url,
+        headers,
+        Bytes::new(),
+        cancel,
+    )
+    .await
+}
 
-    let mut stream = response.bytes_stream();
+/// Sends a request with any method, headers and body, within the connect timeout.
+async fn send_method(
+    downloader: &Downloader,
+    method: Method,
+    parsed_url: &Url,
+    url: &str,
+    headers: HeaderMap,
+    body: Bytes,
+    cancel: &CancellationToken,
+) -> Result<TransportResponse, NgetError> {
+    cancellable(
+        cancel,
+        url,
+        within(
+            downloader.connect_timeout,
+            async {
+                Transport::connect(
+                    parsed_url,
+                    &downloader.http_version,
+                    &downloader.proxy_config,
+                    &downloader.tls_config,
+                    downloader.connect_timeout,
+                )
+                .await?
+                .send_method(method, url, headers, body)
+                .await
+            },
+            || NgetError::ConnectionTimeout(url.to_string()),
+        ),
+    )
+    .await
+}
 
-    // Process each chunk of the response data
-    while let Some(chunk) = stream.next().await {
-        let data =
-            chunk.map_err(|e| NgetError::HttpRequest(format!("Failed to read chunk: {}", e)))?;
-        // Write the chunk to the file
-        file.write_all(&data)
-            .await
-            .map_err(|e| NgetError::FileError(format!("Failed to write file: {}", e)))?;
+/// Gets a URL's body into memory rather than a file, e.g. for `robots.txt`, decoding it
+/// if the server compressed it. Responses are checked as for downloads, and bodies over
+/// `limit` bytes fail.
+pub(crate) async fn fetch_body(
+    downloader: &Downloader,
+    url: &str,
+    limit: usize,
+) -> Result<Vec<u8>, NgetError> {
+    request_body(
+        downloader,
+        Method::GET,
+        url,
+        HeaderMap::new(),
+        Bytes::new(),
+        None,
+        limit,
+    )
+    .await
+}
 
-        // Update progress bar with the number of bytes downloaded
-        progress_bar.inc(data.len() as u64);
+/// Gets a URL's status, and its body like [`fetch_body`] if the request succeeded, for
+/// files like `robots.txt` whose error statuses mean different things.
+pub(crate) async fn fetch_status_body(
+    downloader: &Downloader,
+    url: &str,
+    limit: usize,
+) -> Result<(StatusCode, Option<Vec<u8>>), NgetError> {
+    let parsed_url = Url::parse(url)?;
+
+    let mut headers = downloader.headers.clone();
+    headers.extend(request_headers(0, &downloader.compression));
+    let response = send_request(
+        downloader,
+        &parsed_url,
+        url,
+        headers,
+        &downloader.cancel_token,
+    )
+    .await?;
+    let status = response.status;
+    if !status.is_success() {
+        return Ok((status, None));
     }
+    check_response(url, &response, &downloader.http_version)?;
 
-    // Finish the progress bar with a message indicating completion
-    progress_bar.finish_with_message(format!("Saved to: {}", file_path.display()));
-    Ok(())
+    let body = read_body(downloader, url, response, limit).await?;
+    Ok((status, Some(body)))
 }
 
-pub async fn http2_download(
+/// Sends a request like [`fetch_body`], with any method and extra headers and body,
+/// e.g. a WebDAV `PROPFIND`. If `status` is given, any other successful status fails.
+pub(crate) async fn request_body(
+    downloader: &Downloader,
+    method: Method,
     url: &str,
-    save_dir: &str,
-    output_file_name: Option<&str>,
-    progress_bar: &ProgressBar,
-    config: &ProxyConfig,
-) -> Result<(), NgetError> {
-    let _ = env_logger::try_init();
+    extra_headers: HeaderMap,
+    body: Bytes,
+    status: Option<StatusCode>,
+    limit: usize,
+) -> Result<Vec<u8>, NgetError> {
+    let parsed_url = Url::parse(url)?;
+    let cancel = &downloader.cancel_token;
+
+    let mut headers = downloader.headers.clone();
+    headers.extend(request_headers(0, &downloader.compression));
+    headers.extend(extra_headers);
+    let response = send_method(downloader, method, &parsed_url, url, headers, body, cancel).await?;
+    check_response(url, &response, &downloader.http_version)?;
+    if status.is_some_and(|status| status != response.status) {
+        return Err(NgetError::HttpRequest(format!(
+            "Unexpected status: {} for URL: {}",
+            response.status, url
+        )));
+    }
 
-    // Create a client
-    let client = match get_client(config, &HttpVersion::Http2) {
-        Ok(client) => client,
-        Err(e) => {
-            // Handle the error (e.g., print it, log it, etc.)
-            return Err(e);
+    read_body(downloader, url, response, limit).await
+}
+
+/// Reads a response body into memory, decoding it if the server compressed it.
+async fn read_body(
+    downloader: &Downloader,
+    url: &str,
+    response: TransportResponse,
+    limit: usize,
+) -> Result<Vec<u8>, NgetError> {
+    let cancel = &downloader.cancel_token;
+    let mut decoder = ContentDecoder::new(
+        response.header(header::CONTENT_ENCODING),
+        &downloader.compression,
+    )?;
+    let mut stream = response.body;
+
+    let mut body = Vec::new();
+    while let Some(chunk) = cancellable(
+        cancel,
+        url,
+        within(
+            downloader.read_timeout,
+            async { Ok(stream.next().await) },
+            || NgetError::ReadTimeout(url.to_string()),
+        ),
+    )
+    .await?
+    {
+        body.extend_from_slice(&decoder.decode(chunk?).await?);
+        if body.len() > limit {
+            return Err(NgetError::HttpRequest(format!(
+                "Response from {} is larger than {} bytes",
+                url, limit
+            )));
         }
-    };
+    }
+    body.extend_from_slice(&decoder.finish().await?);
 
-    let parsed_url = url_utils::parse_url(url);
+    Ok(body)
+}
 
-    //log::info!("Parsed URL: {}", parsed_url);
+/// What a URL returned when checked without saving it.
+#[derive(Debug, Clone)]
+pub struct Probe {
+    /// URL the response came from, after any redirects
+    pub final_url: String,
+    pub status: StatusCode,
+    pub content_type: Option<String>,
+    /// Size of the content, if the server sent it or the body was read
+    pub size: Option<u64>,
+    /// Why the response can't be downloaded, e.g. an unexpected status
+    pub error: Option<String>,
+}
 
-    // Make sure the URL actually exists using DNS lookup
-    let resolver = build_resolver();
+impl Probe {
+    /// Returns true if the URL is a broken link, i.e. the server answered 4xx or 5xx.
+    pub fn is_broken(&self) -> bool {
+        self.status.is_client_error() || self.status.is_server_error()
+    }
+}
 
-    let _dns_response = resolver
-        .lookup_ip(parsed_url.host_str().unwrap_or_default())
-        .await?;
+/// Checks a URL without saving it, e.g. for `--spider`. Error statuses are reported in
+/// the probe rather than failing, and only connection problems are errors.
+pub async fn probe(downloader: &Downloader, url: &str) -> Result<Probe, NgetError> {
+    probe_page(downloader, url, 0).await.map(|(probe, _)| probe)
+}
 
-    // Extract file name from URL or use user arg file name.
-    let file_name = match output_file_name {
-        Some(name) => name.to_string(),
-        None => get_file_name(parsed_url),
+/// Checks a URL like [`probe`], also reading the body of successful HTML and CSS
+/// responses, up to `limit` bytes, so their links can be followed.
+pub(crate) async fn probe_page(
+    downloader: &Downloader,
+    url: &str,
+    limit: usize,
+) -> Result<(Probe, Option<Vec<u8>>), NgetError> {
+    let parsed_url = Url::parse(url)?;
+
+    let mut headers = downloader.headers.clone();
+    headers.extend(request_headers(0, &downloader.compression));
+    let response = send_request(
+        downloader,
+        &parsed_url,
+        url,
+        headers,
+        &downloader.cancel_token,
+    )
+    .await?;
+
+    let mut probe = Probe {
+        final_url: response.url.clone(),
+        status: response.status,
+        content_type: response.header(header::CONTENT_TYPE).map(str::to_string),
+        size: response.content_length(),
+        error: check_response(url, &response, &downloader.http_version)
+            .err()
+            .map(|e| e.to_string()),
     };
 
-    progress_bar.set_message(file_name.clone());
-    let file_path = Path::new(save_dir).join(&file_name);
+    // The body is only needed to find the links of a page
+    let wants_body =
+        limit > 0 && probe.error.is_none() && probe.content_type.as_deref().is_some_and(is_markup);
+    if !wants_body {
+        return Ok((probe, None));
+    }
 
-    // Check if the file already exists and get its size
-    let existing_size = metadata(&file_path).map(|meta| meta.len()).unwrap_or(0);
+    let body = read_body(downloader, url, response, limit).await?;
+    probe.size = probe.size.or(Some(body.len() as u64));
+    Ok((probe, Some(body)))
+}
 
-    //log::info!(
-    //"File path: {:?}, Existing size: {}",
-    //file_path,
-    //existing_size
-    //);
+/// Returns true for the content types whose links a crawl follows: HTML and CSS.
+fn is_markup(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    ["text/html", "application/xhtml+xml", "text/css"]
+        .iter()
+        .any(|markup| mime.eq_ignore_ascii_case(markup))
+}
 
-    // Prepare HTTP request with HTTP/2
-    let request = client.get(url).version(Version::HTTP_2);
+/// Builds the headers sent with every download request.
+pub(crate) fn request_headers(existing_size: u64, compression: &Compression) -> HeaderMap {
+    let mut headers = HeaderMap::new();
 
-    // Handle the response and catch errors
-    let response = match request.send().await {
-        Ok(resp) => resp,
-        Err(e) => {
-            if let Some(inner) = e.source() {
-                if inner.to_string().contains("UserUnsupportedVersion") {
-                    //log::error!("HTTP/2 unsupported for URL: {}", url);
-                    return Err(NgetError::InvalidUrl(
-                        "URL is Invalid. Please make sure the URL is correct.".to_string(),
-                    ));
-                }
-            }
-            return Err(NgetError::HttpRequest(format!("Request failed: {}", e)));
-        }
-    };
+    // If the file exists, set the range to download only the remaining part
+    if existing_size > 0 {
+        headers.insert(
+            header::RANGE,
+            HeaderValue::from_str(&format!("bytes={}-", existing_size)).unwrap(),
+        );
+    }
 
-    //log::info!("Response status: {}", response.status());
+    headers.insert(
+        header::ACCEPT_ENCODING,
+        HeaderValue::from_static(compression.accept_encoding()),
+    );
 
-    // Handle the response status
-    if !response.status().is_success() && response.status() != reqwest::StatusCode::PARTIAL_CONTENT
-    {
+    headers
+}
+
+/// Checks the response status, and that HTTP/2 was negotiated when it is required.
+pub(crate) fn check_response(
+    url: &str,
+    response: &TransportResponse,
+    http_version: &HttpVersion,
+) -> Result<(), NgetError> {
+    if response.status == StatusCode::NOT_FOUND {
+        return Err(NgetError::InvalidUrl(
+            "URL is Invalid. Please make sure the URL is correct.".to_string(),
+        ));
+    }
+    if !response.status.is_success() {
         return Err(NgetError::HttpRequest(format!(
             "Unexpected status: {} for URL: {}",
-            response.status(),
-            url
+            response.status, url
         )));
     }
 
-    // Set the total length in the progress bar if available
-    if let Some(total_length) = response.content_length() {
-        progress_bar.set_length(total_length + existing_size);
-    } else {
-        log::warn!("Content length is not provided. Progress bar will not be set.");
-        progress_bar.set_length(0); // Default or no progress tracking
+    // The server may have picked HTTP/1.1 during ALPN
+    if *http_version == HttpVersion::Http2 && response.version != Version::HTTP_2 {
+        return Err(NgetError::UnsupportedHTTPVersion(format!(
+            "{} did not negotiate HTTP/2 via ALPN",
+            url
+        )));
     }
 
-    // Open the file in append mode for writing the downloaded data
+    Ok(())
+}
+
+/// Byte counts of a saved response body.
+pub(crate) struct WrittenBody {
+    /// Bytes received on the wire
+    pub(crate) received: u64,
+    /// Bytes written to the file, after decoding
+    pub(crate) written: u64,
+    /// Content encoding the body was decoded from
+    pub(crate) content_encoding: Option<String>,
+    /// SHA-256 of the whole file, if checksums are enabled
+    pub(crate) sha256: Option<String>,
+}
+
+/// Writes the response body to the file, decoding it and reporting each chunk.
+/// The file is written from `resume_from` bytes, or truncated when it is 0.
+/// If the body is interrupted, what was received is flushed and the resume state saved.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn write_body(
+    url: &str,
+    response: TransportResponse,
+    file_path: &Path,
+    resume_from: u64,
+    observer: &dyn ProgressObserver,
+    compression: &Compression,
+    read_timeout: Option<Duration>,
+    checksum: bool,
+    cancel: &CancellationToken,
+) -> Result<WrittenBody, NgetError> {
+    let etag = response.header(header::ETAG).map(str::to_string);
+
+    // Decode the body if the server compressed it
+    let mut decoder = ContentDecoder::new(response.header(header::CONTENT_ENCODING), compression)?;
+
+    // Anything past `resume_from` is left over from an interrupted write, so it is dropped
     let mut file = OpenOptions::new()
         .create(true)
-        .append(true)
-        .open(&file_path)
+        .write(true)
+        .truncate(false)
+        .open(file_path)
+        .await
+        .map_err(|e| NgetError::FileError(format!("Failed to open file: {}", e)))?;
+    file.set_len(resume_from)
+        .await
+        .map_err(|e| NgetError::FileError(format!("Failed to open file: {}", e)))?;
+    file.seek(SeekFrom::End(0))
         .await
         .map_err(|e| NgetError::FileError(format!("Failed to open file: {}", e)))?;
 
-    let mut stream = response.bytes_stream();
+    // The hash covers the whole file, including any part saved earlier
+    let mut hasher = match checksum {
+        true => Some(sha256_prefix(file_path, resume_from).await?),
+        false => None,
+    };
+
+    let mut stream = response.body;
+
+    let mut received: u64 = 0;
+    let mut written: u64 = 0;
+
+    let result: Result<(), NgetError> = async {
+        // Process each chunk of the response data
+        while let Some(chunk) = cancellable(
+            cancel,
+            url,
+            within(read_timeout, async { Ok(stream.next().await) }, || {
+                NgetError::ReadTimeout(url.to_string())
+            }),
+        )
+        .await?
+        {
+            let data = chunk?;
+            let chunk_len = data.len() as u64;
+            let data = decoder.decode(data).await?;
+
+            // Write the chunk to the file
+            file.write_all(&data)
+                .await
+                .map_err(|e| NgetError::FileError(format!("Failed to write file: {}", e)))?;
+            written += data.len() as u64;
+            if let Some(hasher) = &mut hasher {
+                hasher.update(&data);
+            }
+
+            // Report the number of bytes downloaded.
+            // This counts received bytes, as Content-Length is the encoded size.
+            observer.on_event(&DownloadEvent::Bytes {
+                received: chunk_len,
+            });
+            received += chunk_len;
+        }
 
-    // Process each chunk of the response data
-    while let Some(chunk) = stream.next().await {
-        let data =
-            chunk.map_err(|e| NgetError::HttpRequest(format!("Failed to read chunk: {}", e)))?;
+        let data = decoder.finish().await?;
         file.write_all(&data)
             .await
             .map_err(|e| NgetError::FileError(format!("Failed to write file: {}", e)))?;
-        progress_bar.inc(data.len() as u64);
+        written += data.len() as u64;
+        if let Some(hasher) = &mut hasher {
+            hasher.update(&data);
+        }
+
+        Ok(())
     }
+    .await;
 
-    // Finish the progress bar with a message indicating completion
-    progress_bar.finish_with_message(format!("Saved to: {}", file_path.display()));
-    Ok(())
+    file.flush()
+        .await
+        .map_err(|e| NgetError::FileError(format!("Failed to write file: {}", e)))?;
+
+    match result {
+        Ok(()) => {
+            ResumeState::remove(file_path).await;
+            Ok(WrittenBody {
+                received,
+                written,
+                content_encoding: decoder.is_decoding().then(|| decoder.name().to_string()),
+                sha256: hasher.map(|hasher| to_hex(&hasher.finalize())),
+            })
+        }
+        Err(e) => {
+            let state = ResumeState {
+                url: url.to_string(),
+                etag,
+                bytes: resume_from + written,
+                decoded: decoder.is_decoding(),
+            };
+            state.save(file_path).await?;
+            Err(e)
+        }
+    }
+}
+
+/// Runs a future until it completes or the download is cancelled.
+pub(crate) async fn cancellable<T>(
+    cancel: &CancellationToken,
+    url: &str,
+    future: impl Future<Output = Result<T, NgetError>>,
+) -> Result<T, NgetError> {
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(NgetError::Cancelled(url.to_string())),
+        result = future => result,
+    }
+}
+
+/// Runs a future with an optional time limit, failing with `on_timeout` if it runs out.
+pub(crate) async fn within<T>(
+    limit: Option<Duration>,
+    future: impl Future<Output = Result<T, NgetError>>,
+    on_timeout: impl FnOnce() -> NgetError,
+) -> Result<T, NgetError> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, future)
+            .await
+            .unwrap_or_else(|_| Err(on_timeout())),
+        None => future.await,
+    }
 }
@@ -0,0 +1,368 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::enums::HttpVersion;
+use crate::error::NgetError;
+use crate::utils::client_utils::{get_client, user_agent};
+use crate::utils::proxy_utils::ProxyConfig;
+use crate::utils::resolver_utils::build_resolver;
+use crate::utils::tls_utils::{build_rustls_config, TlsConfig};
+use crate::utils::transport::TransportResponse;
+
+use bytes::{Buf, Bytes};
+
+use futures_util::StreamExt;
+
+use once_cell::sync::Lazy;
+
+use quinn::crypto::rustls::QuicClientConfig;
+use quinn::{ConnectionError, Endpoint};
+
+use reqwest::header::{self, HeaderMap, HeaderValue};
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::task::JoinHandle;
+
+use url::{Host, Url};
+
+/// How long to wait for the QUIC handshake before deciding a server doesn't speak HTTP/3,
+/// and for the origin to tell its Alt-Svc, when no connect timeout is set.
+const QUIC_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long an Alt-Svc entry stays valid when the header has no `ma` parameter.
+const ALT_SVC_DEFAULT_MAX_AGE: u64 = 86400;
+
+/// HTTP/3 alternative services advertised by origins, with their expiry time.
+static ALT_SVC_CACHE: Lazy<Mutex<HashMap<String, (AltSvc, Instant)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// An HTTP/3 alternative service advertised through the Alt-Svc header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AltSvc {
+    /// Alternative host, or None for the origin's own host
+    pub host: Option<String>,
+    /// UDP port of the alternative service
+    pub port: u16,
+    /// How long the advertisement is valid for, in seconds
+    pub max_age: u64,
+}
+
+/// Parses an Alt-Svc header value, returning the first `h3` alternative.
+pub fn parse_alt_svc(value: &str) -> Option<AltSvc> {
+    value.split(',').find_map(|entry| {
+        let mut params = entry.split(';').map(str::trim);
+        let (protocol, authority) = params.next()?.split_once('=')?;
+        if protocol != "h3" {
+            return None;
+        }
+
+        let authority = authority.trim_matches('"');
+        let (host, port) = authority.rsplit_once(':')?;
+        let port = port.parse().ok()?;
+        let host = (!host.is_empty()).then(|| host.to_string());
+
+        let max_age = params
+            .filter_map(|param| param.strip_prefix("ma="))
+            .find_map(|ma| ma.parse().ok())
+            .unwrap_or(ALT_SVC_DEFAULT_MAX_AGE);
+
+        Some(AltSvc {
+            host,
+            port,
+            max_age,
+        })
+    })
+}
+
+/// Records the Alt-Svc header of a response from the given URL's origin.
+/// `clear` or a header without an `h3` alternative forgets the origin.
+pub fn record_alt_svc(url: &Url, value: &str) {
+    let origin = url.origin().ascii_serialization();
+    let mut cache = ALT_SVC_CACHE.lock().unwrap();
+
+    match parse_alt_svc(value) {
+        Some(alt_svc) => {
+            let expiry = Instant::now() + Duration::from_secs(alt_svc.max_age);
+            cache.insert(origin, (alt_svc, expiry));
+        }
+        None => {
+            cache.remove(&origin);
+        }
+    }
+}
+
+/// Gets the unexpired HTTP/3 alternative service recorded for a URL's origin.
+pub fn cached_alt_svc(url: &Url) -> Option<AltSvc> {
+    let origin = url.origin().ascii_serialization();
+    let mut cache = ALT_SVC_CACHE.lock().unwrap();
+
+    match cache.get(&origin) {
+        Some((alt_svc, expiry)) if *expiry > Instant::now() => Some(alt_svc.clone()),
+        Some(_) => {
+            cache.remove(&origin);
+            None
+        }
+        None => None,
+    }
+}
+
+/// An established HTTP/3 connection, ready to send a request.
+pub struct Http3Connection {
+    endpoint: Endpoint,
+    connection: quinn::Connection,
+}
+
+/// Closes a connection and stops the task driving it when dropped, whether a request
+/// failed, its body was read to the end or it was dropped part way through.
+struct ConnectionGuard {
+    connection: quinn::Connection,
+    drive: JoinHandle<()>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.connection.close(0u32.into(), b"done");
+        self.drive.abort();
+    }
+}
+
+/// Connects to a URL's origin over HTTP/3, preferring an advertised alternative service.
+pub async fn connect_http3(
+    parsed_url: &Url,
+    config: &ProxyConfig,
+    tls_config: &TlsConfig,
+    connect_timeout: Option<Duration>,
+) -> Result<Http3Connection, NgetError> {
+    let timeout = connect_timeout.unwrap_or(QUIC_CONNECT_TIMEOUT);
+    if parsed_url.scheme() != "https" {
+        return Err(NgetError::UnsupportedHTTPVersion(format!(
+            "HTTP/3 requires an https:// URL: {}",
+            parsed_url
+        )));
+    }
+    if config.is_enabled() {
+        return Err(NgetError::UnsupportedHTTPVersion(
+            "HTTP/3 can't be used through a proxy.".to_string(),
+        ));
+    }
+
+    // TLS always uses the origin's host name, even when connecting to an alternative service
+    let server_name = match parsed_url.host() {
+        Some(Host::Ipv6(ip)) => ip.to_string(),
+        Some(host) => host.to_string(),
+        None => {
+            return Err(NgetError::InvalidUrl(format!(
+                "URL has no host: {}",
+                parsed_url
+            )))
+        }
+    };
+    let port = parsed_url.port_or_known_default().unwrap_or(443);
+
+    // Prefer an advertised alternative service, asking the origin over TCP if none is known yet
+    let alt_svc = match cached_alt_svc(parsed_url) {
+        Some(alt_svc) => Some(alt_svc),
+        None => discover_alt_svc(parsed_url, config, tls_config, timeout).await,
+    };
+    let (connect_host, connect_port) = match alt_svc {
+        Some(alt_svc) => (alt_svc.host.unwrap_or(server_name.clone()), alt_svc.port),
+        None => (server_name.clone(), port),
+    };
+
+    let (endpoint, connection) = connect_quic(
+        &connect_host,
+        connect_port,
+        &server_name,
+        tls_config,
+        timeout,
+    )
+    .await?;
+
+    Ok(Http3Connection {
+        endpoint,
+        connection,
+    })
+}
+
+impl Http3Connection {
+    /// Sends a GET request, streaming the response body.
+    /// The connection is closed once the body has been read, or if the request fails.
+    pub async fn send(
+        self,
+        url: &str,
+        mut headers: HeaderMap,
+    ) -> Result<TransportResponse, NgetError> {
+        let (mut driver, mut send_request) =
+            match h3::client::new(h3_quinn::Connection::new(self.connection.clone())).await {
+                Ok(client) => client,
+                Err(e) => {
+                    self.connection.close(0u32.into(), b"setup failed");
+                    return Err(NgetError::ConnectionError(format!(
+                        "HTTP/3 setup failed: {}",
+                        e
+                    )));
+                }
+            };
+        let guard = ConnectionGuard {
+            connection: self.connection,
+            drive: tokio::spawn(async move {
+                let _ = std::future::poll_fn(|cx| driver.poll_close(cx)).await;
+            }),
+        };
+
+        // reqwest adds the default User-Agent for TCP requests, h3 has to be told
+        if let Ok(agent) = HeaderValue::from_str(&user_agent()) {
+            headers.entry(header::USER_AGENT).or_insert(agent);
+        }
+        let mut request = http::Request::get(url).body(())?;
+        *request.headers_mut() = headers;
+
+        let mut stream = send_request
+            .send_request(request)
+            .await
+            .map_err(|e| NgetError::HttpRequest(format!("Request error for {}: {}", url, e)))?;
+        stream
+            .finish()
+            .await
+            .map_err(|e| NgetError::HttpRequest(format!("Request error for {}: {}", url, e)))?;
+
+        let response = stream
+            .recv_response()
+            .await
+            .map_err(|e| NgetError::HttpRequest(format!("Request error for {}: {}", url, e)))?;
+
+        let body = futures_util::stream::unfold(Some(stream), move |stream| async move {
+            let mut stream = stream?;
+            match stream.recv_data().await {
+                Ok(Some(mut chunk)) => {
+                    Some((Ok(chunk.copy_to_bytes(chunk.remaining())), Some(stream)))
+                }
+                Ok(None) => None,
+                Err(e) => Some((
+                    Err(NgetError::HttpRequest(format!(
+                        "Failed to read chunk: {}",
+                        e
+                    ))),
+                    None,
+                )),
+            }
+        });
+
+        // Close the connection cleanly once the body is done
+        let closing = futures_util::stream::once(async move {
+            drop(send_request);
+            drop(guard);
+            self.endpoint.wait_idle().await;
+            None
+        })
+        .filter_map(|chunk: Option<Result<Bytes, NgetError>>| async move { chunk });
+
+        Ok(TransportResponse {
+            url: url.to_string(),
+            status: response.status(),
+            version: http::Version::HTTP_3,
+            headers: response.headers().clone(),
+            body: body.chain(closing).boxed(),
+        })
+    }
+}
+
+/// Asks the origin for its Alt-Svc header over TCP, recording any HTTP/3 alternative.
+async fn discover_alt_svc(
+    parsed_url: &Url,
+    config: &ProxyConfig,
+    tls_config: &TlsConfig,
+    timeout: Duration,
+) -> Option<AltSvc> {
+    let client = get_client(config, tls_config, &HttpVersion::Http11).ok()?;
+    let response = client
+        .head(parsed_url.as_str())
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(|e| log::debug!("Alt-Svc discovery failed for {}: {}", parsed_url, e))
+        .ok()?;
+
+    let value = response.headers().get(header::ALT_SVC)?.to_str().ok()?;
+    record_alt_svc(parsed_url, value);
+    cached_alt_svc(parsed_url)
+}
+
+/// Opens a QUIC connection negotiating HTTP/3, trying each address of the host in turn.
+/// Servers that don't answer the handshake don't support HTTP/3.
+async fn connect_quic(
+    host: &str,
+    port: u16,
+    server_name: &str,
+    tls_config: &TlsConfig,
+    timeout: Duration,
+) -> Result<(Endpoint, quinn::Connection), NgetError> {
+    let rustls_config = build_rustls_config(tls_config, vec![b"h3".to_vec()])?;
+    let quic_config = QuicClientConfig::try_from(rustls_config)
+        .map_err(|e| NgetError::TlsError(format!("Failed to configure QUIC TLS: {}", e)))?;
+    let client_config = quinn::ClientConfig::new(Arc::new(quic_config));
+
+    let resolver = build_resolver();
+    let addresses = resolver.lookup_ip(host).await?;
+
+    let mut last_error = format!("no addresses found for {}", host);
+    for ip in addresses.iter() {
+        let bind_addr: SocketAddr = if ip.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+
+        let mut endpoint = Endpoint::client(bind_addr)
+            .map_err(|e| NgetError::ConnectionError(format!("Failed to open UDP socket: {}", e)))?;
+        endpoint.set_default_client_config(client_config.clone());
+
+        let connecting = endpoint
+            .connect(SocketAddr::new(ip, port), server_name)
+            .map_err(|e| NgetError::ConnectionError(format!("QUIC connect error: {}", e)))?;
+
+        match tokio::time::timeout(timeout, connecting).await {
+            Ok(Ok(connection)) => return Ok((endpoint, connection)),
+            Ok(Err(e)) if is_crypto_error(&e) => {
+                return Err(NgetError::TlsError(format!(
+                    "TLS handshake failed for {}:{}: {}",
+                    host, port, e
+                )))
+            }
+            Ok(Err(e)) => last_error = e.to_string(),
+            Err(_) => last_error = "QUIC handshake timed out".to_string(),
+        }
+    }
+
+    Err(NgetError::UnsupportedHTTPVersion(format!(
+        "{}:{} does not support HTTP/3: {}",
+        host, port, last_error
+    )))
+}
+
+/// Returns true if a QUIC connection failed in the TLS handshake, on either side.
+fn is_crypto_error(err: &ConnectionError) -> bool {
+    let code = match err {
+        ConnectionError::TransportError(e) => e.code,
+        ConnectionError::ConnectionClosed(close) => close.error_code,
+        _ => return false,
+    };
+
+    // TLS alerts are carried as CRYPTO_ERROR codes 0x100-0x1ff (RFC 9001)
+    (0x100..=0x1ff).contains(&u64::from(code))
+}
@@ -0,0 +1,150 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::utils::progress::{DownloadEvent, ProgressObserver};
+
+use serde_json::{json, Value};
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Writes newline-delimited JSON events, shared by every download so lines never interleave.
+#[derive(Clone)]
+pub struct JsonWriter {
+    out: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl JsonWriter {
+    /// Creates a writer for the given output, e.g. stdout.
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        JsonWriter {
+            out: Arc::new(Mutex::new(Box::new(out))),
+        }
+    }
+
+    /// Writes a single event as one line.
+    /// Write errors are ignored, as a closed output shouldn't stop downloads.
+    pub fn write(&self, value: &Value) {
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(out, "{}", value);
+        let _ = out.flush();
+    }
+}
+
+/// Byte counts of a download, for progress events.
+#[derive(Default)]
+struct JsonProgress {
+    downloaded: u64,
+    total: Option<u64>,
+    last_tick: Option<Instant>,
+}
+
+/// Reports the events of one download as JSON lines, with progress at most once per interval.
+pub struct JsonObserver {
+    writer: JsonWriter,
+    url: String,
+    interval: Duration,
+    progress: Mutex<JsonProgress>,
+}
+
+impl JsonObserver {
+    /// Creates an observer for a download of `url`.
+    pub fn new(writer: JsonWriter, url: &str, interval: Duration) -> Self {
+        JsonObserver {
+            writer,
+            url: url.to_string(),
+            interval,
+            progress: Mutex::new(JsonProgress::default()),
+        }
+    }
+}
+
+impl ProgressObserver for JsonObserver {
+    fn on_event(&self, event: &DownloadEvent) {
+        let mut progress = self.progress.lock().unwrap();
+
+        let value = match event {
+            DownloadEvent::Started { url, path, attempt } => json!({
+                "event": "start",
+                "url": url,
+                "path": path.display().to_string(),
+                "attempt": attempt,
+            }),
+            DownloadEvent::HeadersReceived {
+                content_length,
+                resume_from,
+                ..
+            } => {
+                progress.downloaded = *resume_from;
+                progress.total = content_length.map(|length| length + resume_from);
+                return;
+            }
+            DownloadEvent::Bytes { received } => {
+                progress.downloaded += received;
+
+                let due = progress
+                    .last_tick
+                    .is_none_or(|last_tick| last_tick.elapsed() >= self.interval);
+                if !due {
+                    return;
+                }
+                progress.last_tick = Some(Instant::now());
+
+                json!({
+                    "event": "progress",
+                    "url": self.url,
+                    "bytes": progress.downloaded,
+                    "total": progress.total,
+                })
+            }
+            DownloadEvent::Retrying {
+                attempt,
+                error,
+                kind,
+                delay,
+            } => json!({
+                "event": "retry",
+                "url": self.url,
+                "attempt": attempt,
+                "error": error,
+                "kind": kind,
+                "delay_ms": delay.as_millis() as u64,
+            }),
+            DownloadEvent::Finished { outcome } => json!({
+                "event": "complete",
+                "url": self.url,
+                "path": outcome.path.display().to_string(),
+                "bytes": outcome.bytes,
+                "status": outcome.status.as_u16(),
+                "duration_ms": outcome.elapsed.as_millis() as u64,
+                "sha256": outcome.sha256,
+                "etag": outcome.etag,
+            }),
+            DownloadEvent::Failed { error, kind } => json!({
+                "event": "failure",
+                "url": self.url,
+                "error": error,
+                "kind": kind,
+            }),
+            DownloadEvent::Cancelled => json!({
+                "event": "cancelled",
+                "url": self.url,
+                "bytes": progress.downloaded,
+            }),
+        };
+
+        self.writer.write(&value);
+    }
+}
@@ -0,0 +1,220 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::error::NgetError;
+use crate::utils::crawler::{is_css, is_html, is_web_url, CrawlEntry};
+use crate::utils::css_utils::extract_css_links;
+use crate::utils::downloader::DownloadOutcome;
+use crate::utils::html_utils::{base_link, extract_links};
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+use url::Url;
+
+/// Rewrites the links in downloaded HTML and CSS files for offline browsing.
+/// Links to files that were downloaded become relative paths to them, and links to
+/// anything else become absolute URLs. A `<base href>` is pointed at the page itself, so
+/// it doesn't send the relative links back to the site. Returns how many files were changed.
+///
+/// With `keep_backups`, each file is copied to `<name>.orig` before it is changed.
+/// Files that can't be converted are skipped with a warning.
+pub async fn convert_links(entries: &[CrawlEntry], keep_backups: bool) -> usize {
+    // Pages downloaded only for their links have been removed again
+    let downloads: Vec<(&str, &DownloadOutcome)> = entries
+        .iter()
+        .filter_map(|entry| Some((entry.url.as_str(), entry.result.as_ref().ok()?)))
+        .filter(|(_, outcome)| outcome.path.exists())
+        .collect();
+
+    let mut files = HashMap::new();
+    for (url, outcome) in &downloads {
+        for url in [url, &outcome.final_url.as_str()] {
+            if let Ok(mut url) = Url::parse(url) {
+                url.set_fragment(None);
+                files.insert(url.to_string(), outcome.path.clone());
+            }
+        }
+    }
+
+    // Several URLs can be saved to the same file, which must only be converted once
+    let mut seen = HashSet::new();
+    let mut converted = 0;
+    for (_, outcome) in downloads {
+        if !seen.insert(outcome.path.clone()) {
+            continue;
+        }
+
+        match convert_file(outcome, &files, keep_backups).await {
+            Ok(true) => converted += 1,
+            Ok(false) => {}
+            Err(e) => log::warn!(
+                "Failed to convert links in {}: {}",
+                outcome.path.display(),
+                e
+            ),
+        }
+    }
+
+    converted
+}
+
+/// Rewrites the links of one downloaded file, returning true if it changed.
+async fn convert_file(
+    outcome: &DownloadOutcome,
+    files: &HashMap<String, PathBuf>,
+    keep_backups: bool,
+) -> Result<bool, NgetError> {
+    let (html, css) = (is_html(outcome), is_css(outcome));
+    if !html && !css {
+        return Ok(false);
+    }
+
+    let content = tokio::fs::read(&outcome.path)
+        .await
+        .map_err(|e| NgetError::FileError(format!("Failed to read file: {}", e)))?;
+    let url = Url::parse(&outcome.final_url)?;
+
+    let directory = outcome.path.parent().unwrap_or(Path::new(""));
+    let mut replacements = Vec::new();
+
+    // Only links in attribute values need escaping
+    let (base, links): (Url, Vec<(Range<usize>, String, bool)>) = match html {
+        true => {
+            let base_link = base_link(&content);
+            let base = base_link
+                .as_ref()
+                .and_then(|link| url.join(&link.value).ok())
+                .unwrap_or(url);
+            // Converted links are relative to the file, so the base becomes the file itself
+            if let Some(link) = base_link {
+                let href = escape_attribute(&relative_link(directory, &outcome.path));
+                if content[link.span.clone()] != *href.as_bytes() {
+                    replacements.push((link.span, href));
+                }
+            }
+            let links = extract_links(&content)
+                .into_iter()
+                .map(|link| (link.span, link.value, link.in_attribute))
+                .collect();
+            (base, links)
+        }
+        false => {
+            let links = extract_css_links(&content)
+                .into_iter()
+                .map(|link| (link.span, link.value, false))
+                .collect();
+            (url, links)
+        }
+    };
+
+    for (span, value, in_attribute) in links {
+        // Links within the page work offline as they are
+        if value.starts_with('#') {
+            continue;
+        }
+        let Some(target) = base.join(&value).ok().filter(is_web_url) else {
+            continue;
+        };
+
+        let mut file_url = target.clone();
+        file_url.set_fragment(None);
+        let link = match files.get(file_url.as_str()) {
+            Some(path) => {
+                let mut link = relative_link(directory, path);
+                if let Some(fragment) = target.fragment() {
+                    link.push('#');
+                    link.push_str(fragment);
+                }
+                link
+            }
+            None => target.to_string(),
+        };
+        let link = match in_attribute {
+            true => escape_attribute(&link),
+            false => link,
+        };
+
+        if content[span.clone()] != *link.as_bytes() {
+            replacements.push((span, link));
+        }
+    }
+
+    if replacements.is_empty() {
+        return Ok(false);
+    }
+
+    replacements.sort_by_key(|(span, _)| span.start);
+    let mut converted = Vec::with_capacity(content.len());
+    let mut pos = 0;
+    for (span, link) in replacements {
+        converted.extend_from_slice(&content[pos..span.start]);
+        converted.extend_from_slice(link.as_bytes());
+        pos = span.end;
+    }
+    converted.extend_from_slice(&content[pos..]);
+
+    if keep_backups {
+        tokio::fs::copy(&outcome.path, backup_path(&outcome.path))
+            .await
+            .map_err(|e| NgetError::FileError(format!("Failed to back up file: {}", e)))?;
+    }
+    tokio::fs::write(&outcome.path, converted)
+        .await
+        .map_err(|e| NgetError::FileError(format!("Failed to write file: {}", e)))?;
+
+    Ok(true)
+}
+
+/// Gets the path a file is backed up to before its links are converted.
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = OsString::from(path.as_os_str());
+    backup.push(".orig");
+    PathBuf::from(backup)
+}
+
+/// Creates a relative link from a file in `directory` to the file at `path`.
+fn relative_link(directory: &Path, path: &Path) -> String {
+    let normal = |path: &Path| -> Vec<OsString> {
+        path.components()
+            .filter(|component| !matches!(component, Component::CurDir))
+            .map(|component| component.as_os_str().to_os_string())
+            .collect()
+    };
+    let (from, to) = (normal(directory), normal(path));
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut parts: Vec<String> = vec!["..".to_string(); from.len() - common];
+    parts.extend(
+        to[common..]
+            .iter()
+            .map(|part| escape_path_segment(&part.to_string_lossy())),
+    );
+    parts.join("/")
+}
+
+/// Escapes the characters of a file name that mean something else in a URL.
+fn escape_path_segment(segment: &str) -> String {
+    segment
+        .replace('%', "%25")
+        .replace('?', "%3F")
+        .replace('#', "%23")
+}
+
+/// Escapes a link for use as an HTML attribute value.
+fn escape_attribute(link: &str) -> String {
+    link.replace('&', "&amp;").replace('"', "&quot;")
+}
@@ -0,0 +1,292 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::enums::Compression;
+use crate::error::NgetError;
+use crate::utils::downloader::{DownloadOutcome, Downloader};
+use crate::utils::file_utils::set_modified;
+use crate::utils::http::{already_complete, resumable_size, write_body};
+use crate::utils::progress::{DownloadEvent, ProgressObserver};
+use crate::utils::transport::TransportResponse;
+
+use base64::alphabet;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+
+use bytes::Bytes;
+
+use futures_util::stream::{self, StreamExt};
+
+use percent_encoding::percent_decode_str;
+
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{StatusCode, Version};
+
+use std::fs::metadata;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::time::{Instant, UNIX_EPOCH};
+
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use tokio_util::sync::CancellationToken;
+
+use url::Url;
+
+/// Size of each read from a local file.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Media type of a `data:` URL that doesn't give one.
+const DEFAULT_DATA_TYPE: &str = "text/plain;charset=US-ASCII";
+
+/// Base64 that accepts payloads with or without padding.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Returns true for `file://` URLs.
+pub fn is_file_url(url: &str) -> bool {
+    url.get(..7)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("file://"))
+}
+
+/// Returns true for `data:` URLs.
+pub fn is_data_url(url: &str) -> bool {
+    url.get(..5)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("data:"))
+}
+
+/// Decodes a `data:` URL into its media type and payload, which is base64 or
+/// percent-encoded.
+pub fn parse_data_url(url: &str) -> Result<(String, Vec<u8>), NgetError> {
+    let invalid = |reason: &str| NgetError::InvalidUrl(format!("{}: {}", reason, url));
+    if !is_data_url(url) {
+        return Err(invalid("Not a data URL"));
+    }
+
+    // The fragment isn't part of the payload
+    let content = url[5..].split('#').next().unwrap_or_default();
+    let (meta, payload) = content
+        .split_once(',')
+        .ok_or_else(|| invalid("Missing ',' in data URL"))?;
+
+    let meta = meta.trim();
+    let (media_type, base64) = match meta.rsplit_once(';') {
+        Some((media_type, last)) if last.trim().eq_ignore_ascii_case("base64") => {
+            (media_type, true)
+        }
+        _ if meta.eq_ignore_ascii_case("base64") => ("", true),
+        _ => (meta, false),
+    };
+    let media_type = match percent_decode_str(media_type).decode_utf8_lossy().trim() {
+        "" => DEFAULT_DATA_TYPE.to_string(),
+        // Parameters without a type, such as `;charset=utf-8`, apply to text
+        parameters if parameters.starts_with(';') => format!("text/plain{}", parameters),
+        media_type => media_type.to_string(),
+    };
+
+    let payload: Vec<u8> = percent_decode_str(payload).collect();
+    let payload = match base64 {
+        true => {
+            let encoded: Vec<u8> = payload
+                .into_iter()
+                .filter(|byte| !byte.is_ascii_whitespace())
+                .collect();
+            BASE64
+                .decode(encoded)
+                .map_err(|e| invalid(&format!("Invalid base64 ({})", e)))?
+        }
+        false => payload,
+    };
+
+    Ok((media_type, payload))
+}
+
+/// Copies a `file://` URL to `file_path`, resuming a partial copy. The source's
+/// modification time guards the resume the way an ETag does over HTTP, and is set as the
+/// copy's modification time.
+pub(crate) async fn fetch_file(
+    downloader: &Downloader,
+    url: &str,
+    file_path: &Path,
+    observer: &dyn ProgressObserver,
+    cancel: &CancellationToken,
+) -> Result<DownloadOutcome, NgetError> {
+    let started = Instant::now();
+    let source = Url::parse(url)
+        .ok()
+        .and_then(|url| url.to_file_path().ok())
+        .ok_or_else(|| NgetError::InvalidUrl(format!("Invalid file URL: {}", url)))?;
+
+    let source_meta = metadata(&source)
+        .map_err(|e| NgetError::InvalidUrl(format!("Failed to read {}: {}", url, e)))?;
+    if source_meta.is_dir() {
+        return Err(NgetError::InvalidUrl(format!("{} is a directory", url)));
+    }
+    if source.canonicalize().ok() == file_path.canonicalize().ok() {
+        return Err(NgetError::FileError(format!(
+            "{} would be copied onto itself",
+            source.display()
+        )));
+    }
+    let size = source_meta.len();
+    let modified = source_meta.modified().ok();
+    let version = modified.and_then(|modified| {
+        let since = modified.duration_since(UNIX_EPOCH).ok()?;
+        Some(format!("{}.{:09}", since.as_secs(), since.subsec_nanos()))
+    });
+
+    // Only resume if the source hasn't changed since the partial copy was saved
+    let (resume_from, _) =
+        resumable_size(file_path, url, Some(size), Some(version.as_deref())).await;
+
+    // There is nothing past the end to copy, so the file is already complete
+    if resume_from > 0 && resume_from == size {
+        return already_complete(downloader, file_path, url.to_string(), size, None, started).await;
+    }
+
+    let mut file = File::open(&source)
+        .await
+        .map_err(|e| NgetError::FileError(format!("Failed to open {}: {}", url, e)))?;
+    file.seek(SeekFrom::Start(resume_from))
+        .await
+        .map_err(|e| NgetError::FileError(format!("Failed to read {}: {}", url, e)))?;
+    let body = stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut buffer = vec![0; CHUNK_SIZE];
+        match file.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(read) => {
+                buffer.truncate(read);
+                Some((Ok(Bytes::from(buffer)), Some(file)))
+            }
+            Err(e) => Some((
+                Err(NgetError::FileError(format!("Failed to read file: {}", e))),
+                None,
+            )),
+        }
+    });
+
+    let status = match resume_from {
+        0 => StatusCode::OK,
+        _ => StatusCode::PARTIAL_CONTENT,
+    };
+    observer.on_event(&DownloadEvent::HeadersReceived {
+        final_url: url.to_string(),
+        status,
+        content_length: Some(size - resume_from),
+        resume_from,
+    });
+
+    // The resume state keeps the source's modification time in place of an ETag
+    let mut headers = HeaderMap::new();
+    if let Some(value) = version.and_then(|version| HeaderValue::from_str(&version).ok()) {
+        headers.insert(header::ETAG, value);
+    }
+    let response = TransportResponse {
+        url: url.to_string(),
+        status,
+        version: Version::default(),
+        headers,
+        body: body.boxed(),
+    };
+
+    let written = write_body(
+        url,
+        response,
+        file_path,
+        resume_from,
+        observer,
+        &Compression::None,
+        downloader.read_timeout,
+        downloader.checksum,
+        cancel,
+    )
+    .await?;
+
+    if let Some(modified) = modified {
+        if let Err(e) = set_modified(file_path, modified) {
+            log::warn!("Failed to set the time of {}: {}", file_path.display(), e);
+        }
+    }
+
+    Ok(DownloadOutcome {
+        path: file_path.to_path_buf(),
+        final_url: url.to_string(),
+        bytes: resume_from + written.written,
+        received: written.received,
+        content_encoding: None,
+        sha256: written.sha256,
+        status,
+        etag: None,
+        content_type: None,
+        elapsed: started.elapsed(),
+    })
+}
+
+/// Saves the decoded payload of a `data:` URL to `file_path`.
+pub(crate) async fn fetch_data(
+    downloader: &Downloader,
+    url: &str,
+    file_path: &Path,
+    observer: &dyn ProgressObserver,
+    cancel: &CancellationToken,
+) -> Result<DownloadOutcome, NgetError> {
+    let started = Instant::now();
+    let (media_type, payload) = parse_data_url(url)?;
+    let size = payload.len() as u64;
+
+    observer.on_event(&DownloadEvent::HeadersReceived {
+        final_url: url.to_string(),
+        status: StatusCode::OK,
+        content_length: Some(size),
+        resume_from: 0,
+    });
+
+    let response = TransportResponse {
+        url: url.to_string(),
+        status: StatusCode::OK,
+        version: Version::default(),
+        headers: HeaderMap::new(),
+        body: stream::once(async move { Ok(Bytes::from(payload)) }).boxed(),
+    };
+    let written = write_body(
+        url,
+        response,
+        file_path,
+        0,
+        observer,
+        &Compression::None,
+        downloader.read_timeout,
+        downloader.checksum,
+        cancel,
+    )
+    .await?;
+
+    Ok(DownloadOutcome {
+        path: file_path.to_path_buf(),
+        final_url: url.to_string(),
+        bytes: written.written,
+        received: written.received,
+        content_encoding: None,
+        sha256: written.sha256,
+        status: StatusCode::OK,
+        etag: None,
+        content_type: Some(media_type),
+        elapsed: started.elapsed(),
+    })
+}
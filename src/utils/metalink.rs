@@ -0,0 +1,572 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::error::NgetError;
+use crate::utils::crawler::{is_web_url, CrawlEntry};
+use crate::utils::downloader::{DownloadOutcome, Downloader};
+use crate::utils::file_utils::to_hex;
+use crate::utils::http::fetch_body;
+use crate::utils::progress::ProgressObserver;
+use crate::utils::resume_utils::ResumeState;
+use crate::utils::scheduler::Scheduler;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use sha1::Sha1;
+use sha2::digest::DynDigest;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::{Component, Path};
+use std::sync::Arc;
+
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use url::Url;
+
+/// Largest Metalink document that is read.
+const METALINK_SIZE_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Hash types that can be checked, strongest first.
+const HASH_TYPES: [&str; 4] = ["sha-512", "sha-384", "sha-256", "sha-1"];
+
+/// Priority of a Metalink 4 URL that doesn't give one, the lowest there is.
+const LOWEST_PRIORITY: u32 = 999999;
+
+/// The files described by a Metalink document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metalink {
+    pub files: Vec<MetalinkFile>,
+}
+
+/// A file in a Metalink, with the mirrors it can be downloaded from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetalinkFile {
+    /// Path to save the file to, relative to the output directory
+    pub name: String,
+    pub size: Option<u64>,
+    /// Hashes of the whole file as (type, lowercase hex) pairs, such as `("sha-256", "…")`
+    pub hashes: Vec<(String, String)>,
+    pub pieces: Option<Pieces>,
+    /// Mirrors, most preferred first
+    pub urls: Vec<MetalinkUrl>,
+}
+
+/// Hashes of consecutive pieces of a file, so a corrupt download can be fetched again from
+/// the first bad piece.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pieces {
+    /// Length of each piece in bytes, except the last
+    pub length: u64,
+    pub hash_type: String,
+    /// Lowercase hex hash of each piece, in order
+    pub hashes: Vec<String>,
+}
+
+/// A mirror of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetalinkUrl {
+    pub url: String,
+    /// Lower is preferred, as in Metalink 4. Metalink 3 preferences are turned around.
+    pub priority: u32,
+    /// ISO 3166 country code of the mirror, lowercase
+    pub location: Option<String>,
+}
+
+impl Metalink {
+    /// Parses a Metalink 4 (RFC 5854) or Metalink 3 document. File names that would be saved
+    /// outside the output directory are rejected.
+    pub fn parse(content: &[u8]) -> Result<Self, NgetError> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            NgetError::ConfigurationError(format!("Invalid Metalink: {}", e))
+        };
+
+        let mut reader = Reader::from_reader(content);
+        let mut buf = Vec::new();
+        let mut root: Option<String> = None;
+        let mut files = Vec::new();
+        let mut file: Option<MetalinkFile> = None;
+        let mut in_pieces = false;
+        // The element whose text is being read, with its attributes
+        let mut field: Option<(String, HashMap<String, String>)> = None;
+        let mut text = String::new();
+
+        loop {
+            match reader.read_event_into(&mut buf).map_err(|e| invalid(&e))? {
+                Event::Start(start) => {
+                    let name = String::from_utf8_lossy(start.local_name().as_ref()).to_string();
+                    let attributes = attributes(&start).map_err(|e| invalid(&e))?;
+                    match name.as_str() {
+                        _ if root.is_none() => root = Some(name),
+                        "file" => {
+                            let name = attributes
+                                .get("name")
+                                .ok_or_else(|| invalid(&"a file has no name"))?;
+                            if !is_safe_name(name) {
+                                return Err(invalid(&format!("unsafe file name {}", name)));
+                            }
+                            file = Some(MetalinkFile {
+                                name: name.clone(),
+                                ..Default::default()
+                            });
+                        }
+                        "pieces" => {
+                            if let Some(file) = &mut file {
+                                in_pieces = true;
+                                file.pieces = Some(Pieces {
+                                    length: attribute_number(&attributes, "length").unwrap_or(0),
+                                    hash_type: hash_type(
+                                        attributes.get("type").map_or("", String::as_str),
+                                    ),
+                                    hashes: Vec::new(),
+                                });
+                            }
+                        }
+                        "size" | "hash" | "url" if file.is_some() => {
+                            field = Some((name, attributes));
+                            text.clear();
+                        }
+                        _ => {}
+                    }
+                }
+                Event::End(end) => match end.local_name().as_ref() {
+                    b"file" => files.extend(file.take()),
+                    b"pieces" => in_pieces = false,
+                    b"size" | b"hash" | b"url" => {
+                        if let (Some(file), Some((name, attributes))) = (&mut file, field.take()) {
+                            file.add(&name, &attributes, text.trim(), in_pieces);
+                        }
+                    }
+                    _ => {}
+                },
+                Event::Text(content) if field.is_some() => {
+                    text.push_str(&content.unescape().map_err(|e| invalid(&e))?);
+                }
+                Event::CData(data) if field.is_some() => {
+                    text.push_str(&String::from_utf8_lossy(&data));
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        match root.as_deref() {
+            Some("metalink") => {}
+            Some(other) => return Err(invalid(&format!("unexpected root element <{}>", other))),
+            None => return Err(invalid(&"no root element")),
+        }
+
+        for file in &mut files {
+            file.urls.sort_by_key(|url| url.priority);
+        }
+        Ok(Metalink { files })
+    }
+}
+
+impl MetalinkFile {
+    /// Adds what a `<size>`, `<hash>` or `<url>` element gives.
+    fn add(
+        &mut self,
+        element: &str,
+        attributes: &HashMap<String, String>,
+        text: &str,
+        in_pieces: bool,
+    ) {
+        match element {
+            "size" => self.size = text.parse().ok(),
+            "hash" => match (&mut self.pieces, in_pieces) {
+                (Some(pieces), true) => pieces.hashes.push(text.to_ascii_lowercase()),
+                _ => {
+                    let kind = hash_type(attributes.get("type").map_or("", String::as_str));
+                    self.hashes.push((kind, text.to_ascii_lowercase()));
+                }
+            },
+            "url" => {
+                // Torrents, magnet links and other peer-to-peer resources can't be downloaded
+                let kind = attributes.get("type").map(|kind| kind.to_ascii_lowercase());
+                let scheme = Url::parse(text).map(|url| url.scheme().to_string());
+                if !kind.as_deref().is_none_or(is_downloadable_scheme)
+                    || !scheme.as_deref().is_ok_and(is_downloadable_scheme)
+                {
+                    return;
+                }
+
+                // Metalink 3 preferences run from 100, the most preferred, down to 0
+                let priority = match attribute_number(attributes, "priority") {
+                    Some(priority) => priority as u32,
+                    None => match attribute_number(attributes, "preference") {
+                        Some(preference) => 101 - preference.min(100) as u32,
+                        None => LOWEST_PRIORITY,
+                    },
+                };
+                self.urls.push(MetalinkUrl {
+                    url: text.to_string(),
+                    priority,
+                    location: attributes
+                        .get("location")
+                        .map(|location| location.to_ascii_lowercase()),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// Gets the strongest hash of the whole file that can be checked.
+    fn strongest_hash(&self) -> Option<&(String, String)> {
+        HASH_TYPES
+            .iter()
+            .find_map(|kind| self.hashes.iter().find(|(hash_type, _)| hash_type == kind))
+    }
+
+    /// Gets the piece hashes, if there are any that can be checked.
+    fn checkable_pieces(&self) -> Option<&Pieces> {
+        self.pieces.as_ref().filter(|pieces| {
+            pieces.length > 0 && !pieces.hashes.is_empty() && hasher(&pieces.hash_type).is_some()
+        })
+    }
+}
+
+/// Reads a Metalink from a local file or an HTTP(S) URL.
+pub async fn load_metalink(downloader: &Downloader, source: &str) -> Result<Metalink, NgetError> {
+    let content = match Url::parse(source) {
+        Ok(url) if is_web_url(&url) => fetch_body(downloader, source, METALINK_SIZE_LIMIT).await?,
+        _ => tokio::fs::read(source)
+            .await
+            .map_err(|e| NgetError::FileError(format!("Failed to read {}: {}", source, e)))?,
+    };
+    Metalink::parse(&content)
+}
+
+/// Downloads every file in a Metalink into `output_dir`, concurrently through the
+/// scheduler. Each file is fetched from its mirrors in order of priority, preferring
+/// `preferred_location` among mirrors of equal priority, and moves on to the next mirror if
+/// the download fails or the file's size or hash is wrong. Where piece hashes are given,
+/// only the part from the first bad piece is fetched again, and so is a partial file left
+/// by an earlier run. Each entry's URL is the last mirror tried.
+pub async fn download_files(
+    downloader: &Downloader,
+    metalink: &Metalink,
+    output_dir: &Path,
+    scheduler: &Scheduler,
+    preferred_location: Option<&str>,
+    observers: &dyn Fn(&str) -> Arc<dyn ProgressObserver>,
+) -> Vec<CrawlEntry> {
+    let files = &metalink.files;
+    let preferred_location = preferred_location.map(str::to_ascii_lowercase);
+    let mirrors: Vec<Vec<&MetalinkUrl>> = files
+        .iter()
+        .map(|file| {
+            let mut urls: Vec<&MetalinkUrl> = file.urls.iter().collect();
+            urls.sort_by_key(|url| {
+                (
+                    url.priority,
+                    preferred_location.is_some() && url.location != preferred_location,
+                )
+            });
+            urls
+        })
+        .collect();
+
+    let mut entries: Vec<CrawlEntry> = files
+        .iter()
+        .map(|file| CrawlEntry {
+            url: file.name.clone(),
+            referrer: None,
+            depth: 0,
+            result: Err(NgetError::InvalidUrl(format!(
+                "No mirrors for {}",
+                file.name
+            ))),
+        })
+        .collect();
+
+    // Each round tries the next mirror of every file that isn't done yet, keeping what
+    // earlier runs and rounds left behind that can be trusted
+    let mut pending: Vec<usize> = (0..files.len()).collect();
+    let mut tried = vec![0; files.len()];
+    while !pending.is_empty() && !downloader.is_cancelled() {
+        let mut round = Vec::new();
+        let mut requests = Vec::new();
+        for index in pending.drain(..) {
+            let Some(mirror) = mirrors[index].get(tried[index]) else {
+                continue;
+            };
+            let path = output_dir.join(&files[index].name);
+            if let Err(e) = prepare(&files[index], &path).await {
+                entries[index].result = Err(e);
+                continue;
+            }
+            tried[index] += 1;
+            entries[index].url = mirror.url.clone();
+            requests.push(
+                downloader
+                    .request(&mirror.url)
+                    .output_path(path)
+                    .observer(observers(&mirror.url)),
+            );
+            round.push(index);
+        }
+
+        let results = scheduler.run(requests).await;
+        for (index, result) in round.into_iter().zip(results) {
+            let file = &files[index];
+            let result = match result {
+                Ok(outcome) => verify(file, outcome).await,
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = &result {
+                let more = tried[index] < mirrors[index].len();
+                if more && !matches!(e, NgetError::Cancelled(_)) {
+                    log::warn!(
+                        "Failed to download {} from {}: {}. Trying the next mirror.",
+                        file.name,
+                        entries[index].url,
+                        e
+                    );
+                    pending.push(index);
+                }
+            }
+            entries[index].result = result;
+        }
+    }
+
+    entries
+}
+
+/// Checks a downloaded file's size and hash. A bad file is cut back to its verified
+/// pieces, or removed if it has none, so the next mirror starts from there.
+async fn verify(
+    file: &MetalinkFile,
+    outcome: DownloadOutcome,
+) -> Result<DownloadOutcome, NgetError> {
+    let problem = match (file.size, file.strongest_hash()) {
+        (Some(size), _) if outcome.bytes != size => {
+            Some(format!("expected {} bytes, got {}", size, outcome.bytes))
+        }
+        (_, Some((hash_type, expected))) => {
+            let actual = hash_range(&outcome.path, hash_type, 0, None).await?;
+            (actual.as_deref() != Some(expected.as_str())).then(|| {
+                format!(
+                    "{} is {}, expected {}",
+                    hash_type,
+                    actual.unwrap_or_default(),
+                    expected
+                )
+            })
+        }
+        // Without a whole-file hash, every piece has to match
+        (_, None) => match file.checkable_pieces() {
+            Some(pieces) => {
+                let verified =
+                    verified_len(pieces, &outcome.path, outcome.bytes, file.size).await?;
+                (verified < outcome.bytes).then(|| {
+                    format!(
+                        "the {} piece at byte {} doesn't match",
+                        pieces.hash_type, verified
+                    )
+                })
+            }
+            None => {
+                if !file.hashes.is_empty() || file.pieces.is_some() {
+                    log::warn!("None of the hash types of {} can be checked", file.name);
+                }
+                None
+            }
+        },
+    };
+
+    match problem {
+        Some(problem) => {
+            discard_unverified(file, &outcome.path).await?;
+            Err(NgetError::FileChecksumMismatch(format!(
+                "{}: {}",
+                outcome.path.display(),
+                problem
+            )))
+        }
+        None => Ok(outcome),
+    }
+}
+
+/// Creates a file's directory and checks what an earlier run or mirror left of it, so the
+/// download resumes from the part that can be trusted. With piece hashes, the file is cut
+/// back to its first bad piece. Without them, a complete file with the wrong hash is
+/// removed.
+async fn prepare(file: &MetalinkFile, path: &Path) -> Result<(), NgetError> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| NgetError::FileError(format!("Failed to create directory: {}", e)))?;
+    }
+    let Ok(metadata) = tokio::fs::metadata(path).await else {
+        return Ok(());
+    };
+
+    if file.checkable_pieces().is_some() {
+        return discard_unverified(file, path).await;
+    }
+    if let (Some(size), Some((hash_type, expected))) = (file.size, file.strongest_hash()) {
+        if metadata.len() >= size
+            && hash_range(path, hash_type, 0, None).await?.as_ref() != Some(expected)
+        {
+            return discard_unverified(file, path).await;
+        }
+    }
+    Ok(())
+}
+
+/// Cuts a file back to the pieces whose hashes match, or removes it if there are no piece
+/// hashes to check. The resume state is removed too, as the verified part can be resumed
+/// from any mirror.
+async fn discard_unverified(file: &MetalinkFile, path: &Path) -> Result<(), NgetError> {
+    ResumeState::remove(path).await;
+    let Some(pieces) = file.checkable_pieces() else {
+        return tokio::fs::remove_file(path)
+            .await
+            .map_err(|e| NgetError::FileError(format!("Failed to remove file: {}", e)));
+    };
+
+    let len = tokio::fs::metadata(path)
+        .await
+        .map_err(|e| NgetError::FileError(format!("Failed to read file: {}", e)))?
+        .len();
+
+    let verified = verified_len(pieces, path, len, file.size).await?;
+    if verified < len {
+        log::info!(
+            "Keeping the first {} verified bytes of {}",
+            verified,
+            path.display()
+        );
+        let file = OpenOptions::new()
+            .write(true)
+            .open(path)
+            .await
+            .map_err(|e| NgetError::FileError(format!("Failed to open file: {}", e)))?;
+        file.set_len(verified)
+            .await
+            .map_err(|e| NgetError::FileError(format!("Failed to truncate file: {}", e)))?;
+    }
+    Ok(())
+}
+
+/// Gets how many bytes from the start of a file of `len` bytes are covered by matching
+/// pieces. A piece past the end of the file, or past `size`, doesn't count.
+async fn verified_len(
+    pieces: &Pieces,
+    path: &Path,
+    len: u64,
+    size: Option<u64>,
+) -> Result<u64, NgetError> {
+    let size = size.unwrap_or(len);
+    let mut verified = 0;
+    for expected in &pieces.hashes {
+        let end = (verified + pieces.length).min(size);
+        if end <= verified || end > len {
+            break;
+        }
+        let actual = hash_range(path, &pieces.hash_type, verified, Some(end - verified)).await?;
+        if actual.as_ref() != Some(expected) {
+            break;
+        }
+        verified = end;
+    }
+    Ok(verified)
+}
+
+/// Hashes `len` bytes of a file from `start`, or everything after it, as lowercase hex.
+/// Returns None for hash types that can't be checked.
+async fn hash_range(
+    path: &Path,
+    hash_type: &str,
+    start: u64,
+    len: Option<u64>,
+) -> Result<Option<String>, NgetError> {
+    let Some(mut hasher) = hasher(hash_type) else {
+        return Ok(None);
+    };
+
+    let read_error =
+        |e: std::io::Error| NgetError::FileError(format!("Failed to read file: {}", e));
+    let mut file = File::open(path).await.map_err(read_error)?;
+    file.seek(SeekFrom::Start(start))
+        .await
+        .map_err(read_error)?;
+    let mut reader = file.take(len.unwrap_or(u64::MAX));
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = reader.read(&mut buf).await.map_err(read_error)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+
+    Ok(Some(to_hex(&hasher.finalize())))
+}
+
+/// Creates a hasher for a hash type, if it can be checked.
+fn hasher(hash_type: &str) -> Option<Box<dyn DynDigest + Send>> {
+    match hash_type {
+        "sha-1" => Some(Box::new(Sha1::new())),
+        "sha-256" => Some(Box::new(Sha256::new())),
+        "sha-384" => Some(Box::new(Sha384::new())),
+        "sha-512" => Some(Box::new(Sha512::new())),
+        _ => None,
+    }
+}
+
+/// Normalizes a hash type to its IANA name, as Metalink 3 writes `sha256` for `sha-256`.
+fn hash_type(name: &str) -> String {
+    let name = name.trim().to_ascii_lowercase();
+    match name.strip_prefix("sha") {
+        Some(bits) if !bits.is_empty() && bits.bytes().all(|byte| byte.is_ascii_digit()) => {
+            format!("sha-{}", bits)
+        }
+        _ => name,
+    }
+}
+
+fn is_downloadable_scheme(scheme: &str) -> bool {
+    matches!(scheme, "http" | "https" | "ftp" | "ftps")
+}
+
+/// Returns true if a file name stays inside the output directory.
+fn is_safe_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains('\\')
+        && Path::new(name)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+fn attributes(start: &BytesStart) -> Result<HashMap<String, String>, quick_xml::Error> {
+    let mut attributes = HashMap::new();
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(quick_xml::Error::from)?;
+        let key = String::from_utf8_lossy(attribute.key.local_name().as_ref()).to_string();
+        attributes.insert(key, attribute.unescape_value()?.trim().to_string());
+    }
+    Ok(attributes)
+}
+
+fn attribute_number(attributes: &HashMap<String, String>, name: &str) -> Option<u64> {
+    attributes.get(name)?.parse().ok()
+}
@@ -0,0 +1,459 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::enums::Compression;
+use crate::error::NgetError;
+use crate::utils::crawler::is_web_url;
+use crate::utils::downloader::{DownloadOutcome, Downloader};
+use crate::utils::file_utils::{sha256_prefix, to_hex};
+use crate::utils::http::{
+    cancellable, check_response, probe, request_headers, send_request, within,
+};
+use crate::utils::progress::{DownloadEvent, ProgressObserver};
+use crate::utils::resume_utils::ResumeState;
+
+use futures_util::future::join_all;
+use futures_util::StreamExt;
+
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::StatusCode;
+
+use sha2::Digest;
+
+use std::io::SeekFrom;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+use tokio_util::sync::CancellationToken;
+
+use url::Url;
+
+/// Smallest segment a download is split into, so small files are fetched in one piece.
+const MIN_SEGMENT_SIZE: u64 = 256 * 1024;
+
+/// Reads a list of URLs, one file per line, e.g. for `--input-file`. Tab-separated URLs on
+/// a line are mirrors of the same file. Blank lines and lines starting with `#` are skipped.
+pub fn parse_url_list(content: &str) -> Vec<Vec<String>> {
+    content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .map(|line| {
+            line.split('\t')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .filter(|urls| !urls.is_empty())
+        .collect()
+}
+
+/// Orders mirrors by how quickly they answer a request, timed for all of them at once.
+/// Only HTTP(S) mirrors are timed, and the others follow them in the order given, as do
+/// mirrors that fail.
+pub(crate) async fn order_by_latency(downloader: &Downloader, urls: Vec<String>) -> Vec<String> {
+    let timings = join_all(urls.iter().map(|url| async move {
+        if !Url::parse(url).is_ok_and(|parsed| is_web_url(&parsed)) {
+            return None;
+        }
+        let started = Instant::now();
+        match probe(downloader, url).await {
+            Ok(probe) if probe.error.is_none() => Some(started.elapsed()),
+            _ => None,
+        }
+    }))
+    .await;
+
+    for (url, timing) in urls.iter().zip(&timings) {
+        match timing {
+            Some(latency) => log::info!("Mirror {} answered in {:?}", url, latency),
+            None => log::info!("Mirror {} wasn't timed", url),
+        }
+    }
+
+    let mut ordered: Vec<(Option<Duration>, String)> = timings.into_iter().zip(urls).collect();
+    ordered.sort_by_key(|(latency, _)| (latency.is_none(), *latency));
+    ordered.into_iter().map(|(_, url)| url).collect()
+}
+
+/// Lets `url` resume what another of the same file's mirrors saved. The resume state's
+/// ETag belongs to the other mirror, so it is dropped rather than sent to this one.
+pub(crate) async fn hand_over(
+    file_path: &Path,
+    sources: &[String],
+    url: &str,
+) -> Result<(), NgetError> {
+    let Some(state) = ResumeState::load(file_path).await else {
+        return Ok(());
+    };
+    if state.url == url || !sources.contains(&state.url) {
+        return Ok(());
+    }
+
+    log::info!(
+        "Resuming {} bytes saved from {} with {}",
+        state.bytes,
+        state.url,
+        url
+    );
+    ResumeState {
+        url: url.to_string(),
+        etag: None,
+        ..state
+    }
+    .save(file_path)
+    .await
+}
+
+/// Downloads a new file in segments spread across its HTTP(S) mirrors, each fetched with
+/// a byte range at the same time. A segment whose mirror fails carries on from another,
+/// as does one whose mirror has a different size, ETag or Last-Modified time than `url`.
+/// Returns None if the download can't be split: the file exists already, there are too
+/// few mirrors, the size is unknown or too small, or `url` doesn't support byte ranges.
+/// The resume state keeps the part of the file downloaded without gaps as it grows, so
+/// the next attempt resumes it if segments fail on every mirror or nget is stopped.
+pub(crate) async fn fetch_segments(
+    downloader: &Downloader,
+    url: &str,
+    sources: &[String],
+    headers: &HeaderMap,
+    file_path: &Path,
+    observer: &dyn ProgressObserver,
+    cancel: &CancellationToken,
+) -> Result<Option<DownloadOutcome>, NgetError> {
+    let started = Instant::now();
+    let web: Vec<&str> = sources
+        .iter()
+        .filter(|source| Url::parse(source).is_ok_and(|parsed| is_web_url(&parsed)))
+        .map(String::as_str)
+        .collect();
+    if web.len() < 2 || tokio::fs::metadata(file_path).await.is_ok() {
+        return Ok(None);
+    }
+
+    // A one-byte range tells the size, and that the server can send parts of the file
+    let parsed_url = Url::parse(url)?;
+    let first_byte = range_headers(headers, 0, 0);
+    let response = send_request(downloader, &parsed_url, url, first_byte, cancel).await?;
+    check_response(url, &response, &downloader.http_version)?;
+    let size = match response
+        .header(header::CONTENT_RANGE)
+        .and_then(content_range)
+    {
+        Some((0, Some(size))) if response.status == StatusCode::PARTIAL_CONTENT => size,
+        _ => return Ok(None),
+    };
+    let final_url = response.url.clone();
+    let etag = response.header(header::ETAG).map(str::to_string);
+    // Mirrors must send the same version of the file, so the first one's is pinned
+    let validator = etag
+        .as_deref()
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| response.header(header::LAST_MODIFIED))
+        .map(str::to_string);
+    let content_type = response.header(header::CONTENT_TYPE).map(str::to_string);
+    drop(response);
+
+    let count = (size / MIN_SEGMENT_SIZE).min(web.len() as u64);
+    if count < 2 {
+        return Ok(None);
+    }
+
+    // The first segment starts from `url`, and the others from the mirrors after it
+    let first = web.iter().position(|source| *source == url).unwrap_or(0);
+    let order: Vec<&str> = (0..web.len())
+        .map(|index| web[(first + index) % web.len()])
+        .collect();
+    log::info!(
+        "Downloading {} bytes in {} segments from {} mirrors",
+        size,
+        count,
+        order.len()
+    );
+
+    observer.on_event(&DownloadEvent::HeadersReceived {
+        final_url: final_url.clone(),
+        status: StatusCode::OK,
+        content_length: Some(size),
+        resume_from: 0,
+    });
+
+    let file = File::create(file_path)
+        .await
+        .map_err(|e| NgetError::FileError(format!("Failed to create file: {}", e)))?;
+    file.set_len(size)
+        .await
+        .map_err(|e| NgetError::FileError(format!("Failed to create file: {}", e)))?;
+    drop(file);
+
+    // Until a segment is done, none of the file can be trusted
+    let state = ResumeState {
+        url: url.to_string(),
+        etag: validator.clone(),
+        bytes: 0,
+        decoded: false,
+    };
+    state.save(file_path).await?;
+
+    let bounds: Vec<(u64, u64)> = (0..count)
+        .map(|index| (size * index / count, size * (index + 1) / count))
+        .collect();
+    let segments = Segments {
+        downloader,
+        url,
+        headers,
+        file_path,
+        observer,
+        cancel,
+        size,
+        validator,
+        progress: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+        bounds,
+        saved: Mutex::new(0),
+    };
+    let results = join_all((0..segments.bounds.len()).map(|index| {
+        let mirrors = order.iter().cycle().skip(index).take(order.len());
+        segments.fetch(index, mirrors.copied().collect())
+    }))
+    .await;
+
+    let mut errors: Vec<NgetError> = results.into_iter().filter_map(Result::err).collect();
+    if !errors.is_empty() {
+        // Cancelling stops every segment, and is what the download reports
+        let cancelled = errors
+            .iter()
+            .position(|e| matches!(e, NgetError::Cancelled(_)));
+        return Err(errors.swap_remove(cancelled.unwrap_or(0)));
+    }
+
+    ResumeState::remove(file_path).await;
+    let sha256 = match downloader.checksum {
+        true => Some(to_hex(&sha256_prefix(file_path, size).await?.finalize())),
+        false => None,
+    };
+    Ok(Some(DownloadOutcome {
+        path: file_path.to_path_buf(),
+        final_url,
+        bytes: size,
+        received: size,
+        content_encoding: None,
+        sha256,
+        status: StatusCode::OK,
+        etag,
+        content_type,
+        elapsed: started.elapsed(),
+    }))
+}
+
+/// What every segment of a download shares.
+struct Segments<'a> {
+    downloader: &'a Downloader,
+    /// URL the resume state is saved for
+    url: &'a str,
+    headers: &'a HeaderMap,
+    file_path: &'a Path,
+    observer: &'a dyn ProgressObserver,
+    cancel: &'a CancellationToken,
+    /// Size of the whole file
+    size: u64,
+    /// ETag or Last-Modified time of the file, sent as `If-Range` to every mirror
+    validator: Option<String>,
+    /// Where each segment starts and ends
+    bounds: Vec<(u64, u64)>,
+    /// Bytes saved of each segment
+    progress: Vec<AtomicU64>,
+    /// Bytes kept by the resume state
+    saved: Mutex<u64>,
+}
+
+impl Segments<'_> {
+    /// Downloads a segment into the file, trying each mirror in turn from where the last
+    /// one stopped.
+    async fn fetch(&self, index: usize, mirrors: Vec<&str>) -> Result<(), NgetError> {
+        let (start, end) = self.bounds[index];
+        let done = &self.progress[index];
+        let mut last_error = None;
+        for url in mirrors {
+            let result = self.fetch_range(url, start, end, done).await;
+            self.save_prefix().await?;
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e @ NgetError::Cancelled(_)) => return Err(e),
+                Err(e) => {
+                    log::warn!(
+                        "Segment {}-{} failed from {} after {} bytes: {}",
+                        start,
+                        end,
+                        url,
+                        done.load(Ordering::Relaxed),
+                        e
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or(NgetError::Unknown))
+    }
+
+    /// Moves the resume state up to the first gap in the file, if it has grown.
+    async fn save_prefix(&self) -> Result<(), NgetError> {
+        let mut prefix = 0;
+        for (&(start, end), done) in self.bounds.iter().zip(&self.progress) {
+            prefix = start + done.load(Ordering::Relaxed);
+            if prefix < end {
+                break;
+            }
+        }
+
+        let mut saved = self.saved.lock().await;
+        if prefix > *saved {
+            let state = ResumeState {
+                url: self.url.to_string(),
+                etag: self.validator.clone(),
+                bytes: prefix,
+                decoded: false,
+            };
+            state.save(self.file_path).await?;
+            *saved = prefix;
+        }
+        Ok(())
+    }
+
+    /// Downloads what is left of a segment from one mirror.
+    async fn fetch_range(
+        &self,
+        url: &str,
+        start: u64,
+        end: u64,
+        done: &AtomicU64,
+    ) -> Result<(), NgetError> {
+        let offset = start + done.load(Ordering::Relaxed);
+        if offset >= end {
+            return Ok(());
+        }
+
+        let parsed_url = Url::parse(url)?;
+        let mut headers = range_headers(self.headers, offset, end - 1);
+        if let Some(value) = self
+            .validator
+            .as_deref()
+            .and_then(|validator| HeaderValue::from_str(validator).ok())
+        {
+            headers.insert(header::IF_RANGE, value);
+        }
+        let response =
+            send_request(self.downloader, &parsed_url, url, headers, self.cancel).await?;
+        check_response(url, &response, &self.downloader.http_version)?;
+        let range = response
+            .header(header::CONTENT_RANGE)
+            .and_then(content_range);
+        if response.status != StatusCode::PARTIAL_CONTENT
+            || range.map(|(from, _)| from) != Some(offset)
+        {
+            return Err(NgetError::HttpRequest(format!(
+                "{} did not send bytes {}-{}",
+                url,
+                offset,
+                end - 1
+            )));
+        }
+        // Bytes of another version of the file would corrupt it
+        if range.and_then(|(_, size)| size) != Some(self.size) {
+            return Err(NgetError::HttpRequest(format!(
+                "{} has a different version of the file",
+                url
+            )));
+        }
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(self.file_path)
+            .await
+            .map_err(|e| NgetError::FileError(format!("Failed to open file: {}", e)))?;
+        file.seek(SeekFrom::Start(offset))
+            .await
+            .map_err(|e| NgetError::FileError(format!("Failed to open file: {}", e)))?;
+
+        let mut stream = response.body;
+        let mut position = offset;
+        while position < end {
+            let chunk = cancellable(
+                self.cancel,
+                url,
+                within(
+                    self.downloader.read_timeout,
+                    async { Ok(stream.next().await) },
+                    || NgetError::ReadTimeout(url.to_string()),
+                ),
+            )
+            .await?;
+            let Some(chunk) = chunk else {
+                break;
+            };
+
+            // Anything past the end of the segment belongs to another one
+            let data = chunk?;
+            let data = &data[..data.len().min((end - position) as usize)];
+            file.write_all(data)
+                .await
+                .map_err(|e| NgetError::FileError(format!("Failed to write file: {}", e)))?;
+            position += data.len() as u64;
+            done.fetch_add(data.len() as u64, Ordering::Relaxed);
+            self.observer.on_event(&DownloadEvent::Bytes {
+                received: data.len() as u64,
+            });
+        }
+
+        file.flush()
+            .await
+            .map_err(|e| NgetError::FileError(format!("Failed to write file: {}", e)))?;
+        if position < end {
+            return Err(NgetError::NetworkError(format!(
+                "{} closed the connection {} bytes into a segment",
+                url,
+                position - offset
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Builds the headers asking for bytes `first` to `last` of the file, as it is stored.
+fn range_headers(headers: &HeaderMap, first: u64, last: u64) -> HeaderMap {
+    let mut range = request_headers(0, &Compression::None);
+    range.insert(
+        header::RANGE,
+        HeaderValue::from_str(&format!("bytes={}-{}", first, last)).unwrap(),
+    );
+    // Headers given for the request are kept, except those the range depends on
+    for (name, value) in headers {
+        if name != header::RANGE && name != header::ACCEPT_ENCODING {
+            range.insert(name.clone(), value.clone());
+        }
+    }
+    range
+}
+
+/// Parses a `Content-Range: bytes <first>-<last>/<size>` header into the first byte and
+/// the size, if it is known.
+fn content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, size) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let first = range.split_once('-')?.0.trim().parse().ok()?;
+    Some((first, size.trim().parse().ok()))
+}
@@ -14,10 +14,34 @@
 
 pub mod cli;
 pub mod client_utils;
+pub mod compression_utils;
+pub mod crawler;
+pub mod css_utils;
+pub mod dir_listing;
+pub mod download_log;
+pub mod downloader;
 pub mod enums;
 pub mod error;
 pub mod file_utils;
+pub mod ftp;
+pub mod html_utils;
 pub mod http;
+pub mod http3;
+pub mod json_output;
+pub mod link_converter;
+pub mod local_url;
+pub mod metalink;
+pub mod mirrors;
+pub mod progress;
 pub mod proxy_utils;
+pub mod report;
 pub mod resolver_utils;
+pub mod resume_utils;
+pub mod robots;
+pub mod scheduler;
+pub mod sitemap;
+pub mod tls_utils;
+pub mod transport;
+pub mod url_filter;
 pub mod url_utils;
+pub mod webdav;
@@ -0,0 +1,84 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::utils::downloader::DownloadOutcome;
+
+use reqwest::StatusCode;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Something that happened during a download, reported to a [`ProgressObserver`].
+#[derive(Debug, Clone)]
+pub enum DownloadEvent {
+    /// An attempt to download `url` into `path` started
+    Started {
+        url: String,
+        path: PathBuf,
+        attempt: u32,
+    },
+    /// The response headers arrived, from `final_url` after any redirects. `content_length`
+    /// is the size of the body still to come and `resume_from` is how much of the file was
+    /// already saved. Sent before the status is checked, so failed responses are reported too
+    HeadersReceived {
+        final_url: String,
+        status: StatusCode,
+        content_length: Option<u64>,
+        resume_from: u64,
+    },
+    /// A chunk of the body arrived, counted as received on the wire
+    Bytes { received: u64 },
+    /// An attempt failed and the download will be retried after `delay`.
+    /// `kind` is the name of the [`NgetError`](crate::error::NgetError) variant
+    Retrying {
+        attempt: u32,
+        error: String,
+        kind: &'static str,
+        delay: Duration,
+    },
+    /// The download completed
+    Finished { outcome: DownloadOutcome },
+    /// The download failed and won't be retried
+    Failed { error: String, kind: &'static str },
+    /// The download was cancelled, keeping what was saved so far for resuming
+    Cancelled,
+}
+
+/// Receives the events of a download, e.g. to draw a progress bar.
+pub trait ProgressObserver: Send + Sync {
+    fn on_event(&self, event: &DownloadEvent);
+}
+
+impl<F: Fn(&DownloadEvent) + Send + Sync> ProgressObserver for F {
+    fn on_event(&self, event: &DownloadEvent) {
+        self(event)
+    }
+}
+
+/// An observer that ignores every event.
+pub struct SilentObserver;
+
+impl ProgressObserver for SilentObserver {
+    fn on_event(&self, _event: &DownloadEvent) {}
+}
+
+/// Passes every event on to each observer in turn.
+impl ProgressObserver for Vec<Arc<dyn ProgressObserver>> {
+    fn on_event(&self, event: &DownloadEvent) {
+        for observer in self {
+            observer.on_event(event);
+        }
+    }
+}
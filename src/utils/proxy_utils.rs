@@ -12,7 +12,10 @@
 // MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
 // GNU General Public License for more details.
 
-#[derive(Default)]
+use crate::enums::ProxyType;
+use crate::error::NgetError;
+
+#[derive(Default, Clone)]
 pub struct ProxyConfig {
     pub proxy_url: String,
     pub proxy_user: String,
@@ -28,4 +31,39 @@ impl ProxyConfig {
             proxy_password: String::new(),
         }
     }
+
+    /// Returns true if a proxy server has been configured.
+    pub fn is_enabled(&self) -> bool {
+        !self.proxy_url.is_empty()
+    }
+
+    /// Returns true if a proxy username or password has been configured.
+    pub fn has_credentials(&self) -> bool {
+        !self.proxy_user.is_empty() || !self.proxy_password.is_empty()
+    }
+
+    /// Gets the proxy type from the scheme of the proxy URL.
+    /// A URL without a scheme is treated as an HTTP proxy.
+    pub fn proxy_type(&self) -> Result<ProxyType, NgetError> {
+        let scheme = match self.proxy_url.split_once("://") {
+            Some((scheme, _)) => scheme.to_ascii_lowercase(),
+            None => return Ok(ProxyType::Http),
+        };
+
+        match scheme.as_str() {
+            "http" => Ok(ProxyType::Http),
+            "https" => Ok(ProxyType::Https),
+            "socks5" => Ok(ProxyType::Socks5),
+            "socks5h" => Ok(ProxyType::Socks5h),
+            other => Err(NgetError::ConfigurationError(format!(
+                "Unsupported proxy scheme: {}",
+                other
+            ))),
+        }
+    }
+
+    /// Returns true if host names should be resolved by the proxy instead of locally.
+    pub fn resolves_remotely(&self) -> bool {
+        self.is_enabled() && matches!(self.proxy_type(), Ok(ProxyType::Socks5h))
+    }
 }
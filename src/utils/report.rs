@@ -0,0 +1,271 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::error::NgetError;
+use crate::utils::progress::{DownloadEvent, ProgressObserver};
+
+use serde_json::{json, Value};
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How a download in the report ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportStatus {
+    /// The download never finished, e.g. because its task panicked
+    Pending,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl ReportStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportStatus::Pending => "pending",
+            ReportStatus::Completed => "completed",
+            ReportStatus::Failed => "failed",
+            ReportStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// The result of one download, as recorded in the report.
+#[derive(Debug, Clone)]
+pub struct ReportEntry {
+    pub url: String,
+    pub status: ReportStatus,
+    /// Status of the last response received, if any
+    pub http_status: Option<u16>,
+    /// URL of the last response received, after redirects
+    pub final_url: Option<String>,
+    pub path: Option<PathBuf>,
+    /// Size of the saved file, including any part saved by an earlier run
+    pub size: u64,
+    /// Bytes received on the wire during this run
+    pub received: u64,
+    /// Time from the first attempt until the download ended, including retries
+    pub duration: Duration,
+    pub error: Option<String>,
+}
+
+impl ReportEntry {
+    fn new(url: &str) -> Self {
+        ReportEntry {
+            url: url.to_string(),
+            status: ReportStatus::Pending,
+            http_status: None,
+            final_url: None,
+            path: None,
+            size: 0,
+            received: 0,
+            duration: Duration::ZERO,
+            error: None,
+        }
+    }
+
+    /// Average bytes per second received during this run.
+    pub fn average_speed(&self) -> f64 {
+        match self.duration.as_secs_f64() {
+            secs if secs > 0.0 => self.received as f64 / secs,
+            _ => 0.0,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "url": self.url,
+            "status": self.status.as_str(),
+            "http_status": self.http_status,
+            "final_url": self.final_url,
+            "path": self.path.as_ref().map(|path| path.display().to_string()),
+            "size": self.size,
+            "duration_ms": self.duration.as_millis() as u64,
+            "average_speed": self.average_speed().round() as u64,
+            "error": self.error,
+        })
+    }
+
+    fn to_csv(&self) -> String {
+        [
+            csv_field(&self.url),
+            self.status.as_str().to_string(),
+            self.http_status.map(|s| s.to_string()).unwrap_or_default(),
+            csv_field(self.final_url.as_deref().unwrap_or_default()),
+            csv_field(
+                &self
+                    .path
+                    .as_ref()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_default(),
+            ),
+            self.size.to_string(),
+            self.duration.as_millis().to_string(),
+            (self.average_speed().round() as u64).to_string(),
+            csv_field(self.error.as_deref().unwrap_or_default()),
+        ]
+        .join(",")
+    }
+}
+
+/// Column names of a CSV report.
+const CSV_HEADER: &str =
+    "url,status,http_status,final_url,path,size,duration_ms,average_speed,error";
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// File format of a report, chosen by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+}
+
+impl ReportFormat {
+    /// Picks the format for a report file, which must end in `.json` or `.csv`.
+    pub fn from_path(path: &Path) -> Result<Self, NgetError> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("json") => Ok(ReportFormat::Json),
+            Some("csv") => Ok(ReportFormat::Csv),
+            _ => Err(NgetError::ConfigurationError(format!(
+                "Report file must end in .json or .csv: {}",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// Collects the result of every download for an end-of-run report, in the order they were added.
+#[derive(Clone, Default)]
+pub struct Report {
+    entries: Arc<Mutex<Vec<ReportEntry>>>,
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a download of `url` to the report, returning the observer that records its result.
+    pub fn observer(&self, url: &str) -> ReportObserver {
+        let mut entries = self.entries.lock().unwrap();
+        entries.push(ReportEntry::new(url));
+
+        ReportObserver {
+            entries: self.entries.clone(),
+            index: entries.len() - 1,
+            started: Mutex::new(None),
+        }
+    }
+
+    /// Gets the entries recorded so far.
+    pub fn entries(&self) -> Vec<ReportEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    /// Renders the report in the given format.
+    pub fn render(&self, format: ReportFormat) -> String {
+        let entries = self.entries.lock().unwrap();
+
+        match format {
+            ReportFormat::Json => {
+                let values: Vec<Value> = entries.iter().map(ReportEntry::to_json).collect();
+                format!("{:#}\n", Value::Array(values))
+            }
+            ReportFormat::Csv => {
+                let mut csv = format!("{}\n", CSV_HEADER);
+                for entry in entries.iter() {
+                    csv.push_str(&entry.to_csv());
+                    csv.push('\n');
+                }
+                csv
+            }
+        }
+    }
+
+    /// Writes the report to a `.json` or `.csv` file.
+    pub async fn write(&self, path: &Path) -> Result<(), NgetError> {
+        let format = ReportFormat::from_path(path)?;
+
+        tokio::fs::write(path, self.render(format))
+            .await
+            .map_err(|e| {
+                NgetError::FileError(format!("Failed to write report {}: {}", path.display(), e))
+            })
+    }
+}
+
+/// Records the result of one download into a [`Report`].
+pub struct ReportObserver {
+    entries: Arc<Mutex<Vec<ReportEntry>>>,
+    index: usize,
+    started: Mutex<Option<Instant>>,
+}
+
+impl ProgressObserver for ReportObserver {
+    fn on_event(&self, event: &DownloadEvent) {
+        let started = *self
+            .started
+            .lock()
+            .unwrap()
+            .get_or_insert_with(Instant::now);
+        let mut entries = self.entries.lock().unwrap();
+        let entry = &mut entries[self.index];
+
+        match event {
+            DownloadEvent::Started { path, .. } => entry.path = Some(path.clone()),
+            DownloadEvent::HeadersReceived {
+                final_url,
+                status,
+                resume_from,
+                ..
+            } => {
+                entry.final_url = Some(final_url.clone());
+                entry.http_status = Some(status.as_u16());
+                entry.size = *resume_from;
+            }
+            DownloadEvent::Bytes { received } => {
+                entry.size += received;
+                entry.received += received;
+            }
+            DownloadEvent::Retrying { error, .. } => entry.error = Some(error.clone()),
+            DownloadEvent::Finished { outcome } => {
+                entry.status = ReportStatus::Completed;
+                entry.path = Some(outcome.path.clone());
+                entry.final_url = Some(outcome.final_url.clone());
+                entry.http_status = Some(outcome.status.as_u16());
+                entry.size = outcome.bytes;
+                entry.error = None;
+            }
+            DownloadEvent::Failed { error, .. } => {
+                entry.status = ReportStatus::Failed;
+                entry.error = Some(error.clone());
+            }
+            DownloadEvent::Cancelled => entry.status = ReportStatus::Cancelled,
+        }
+        entry.duration = started.elapsed();
+    }
+}
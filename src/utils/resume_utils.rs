@@ -0,0 +1,83 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::error::NgetError;
+
+use std::path::{Path, PathBuf};
+
+/// Extension of the sidecar file saved next to a partially downloaded file.
+const STATE_EXTENSION: &str = "nget-resume";
+
+/// What is known about a partially downloaded file, saved next to it when a download stops early.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ResumeState {
+    /// URL the file was being downloaded from
+    pub url: String,
+    /// ETag of the content, used to check it hasn't changed before resuming
+    pub etag: Option<String>,
+    /// Number of bytes of the file that were saved
+    pub bytes: u64,
+    /// True if the body was being decoded, so byte ranges can't be used to resume it
+    pub decoded: bool,
+}
+
+impl ResumeState {
+    /// Gets the path of the sidecar file for a download.
+    pub fn path(file_path: &Path) -> PathBuf {
+        let mut name = file_path.as_os_str().to_os_string();
+        name.push(".");
+        name.push(STATE_EXTENSION);
+        PathBuf::from(name)
+    }
+
+    /// Loads the resume state saved next to a file, if there is a valid one.
+    pub async fn load(file_path: &Path) -> Option<Self> {
+        let content = tokio::fs::read_to_string(Self::path(file_path))
+            .await
+            .ok()?;
+        let mut state = ResumeState::default();
+
+        for line in content.lines() {
+            match line.split_once('=')? {
+                ("url", url) => state.url = url.to_string(),
+                ("etag", etag) => state.etag = Some(etag.to_string()),
+                ("bytes", bytes) => state.bytes = bytes.parse().ok()?,
+                ("decoded", decoded) => state.decoded = decoded.parse().ok()?,
+                _ => {}
+            }
+        }
+
+        Some(state)
+    }
+
+    /// Saves the resume state next to a file.
+    pub async fn save(&self, file_path: &Path) -> Result<(), NgetError> {
+        let mut content = format!(
+            "url={}\nbytes={}\ndecoded={}\n",
+            self.url, self.bytes, self.decoded
+        );
+        if let Some(etag) = &self.etag {
+            content.push_str(&format!("etag={}\n", etag));
+        }
+
+        tokio::fs::write(Self::path(file_path), content)
+            .await
+            .map_err(|e| NgetError::FileError(format!("Failed to save resume state: {}", e)))
+    }
+
+    /// Removes the resume state of a completed download.
+    pub async fn remove(file_path: &Path) {
+        let _ = tokio::fs::remove_file(Self::path(file_path)).await;
+    }
+}
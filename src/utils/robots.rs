@@ -0,0 +1,269 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::utils::downloader::Downloader;
+use crate::utils::http::fetch_status_body;
+
+use reqwest::header;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+
+use url::Url;
+
+/// The name nget looks for in `User-agent` lines, and sends in its `User-Agent` header.
+pub const USER_AGENT_TOKEN: &str = "nget";
+
+/// Largest `robots.txt` that is read. Anything bigger counts as unreachable.
+const ROBOTS_SIZE_LIMIT: usize = 512 * 1024;
+
+/// An `Allow` or `Disallow` rule.
+#[derive(Debug, Clone)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+/// The rules of a `robots.txt` that apply to one user agent.
+#[derive(Debug, Clone, Default)]
+pub struct RobotsTxt {
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+    sitemaps: Vec<String>,
+}
+
+/// The lines of one group of a `robots.txt`, started by its `User-agent` lines.
+#[derive(Default)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+impl RobotsTxt {
+    /// Rules that allow everything, used when a site has no `robots.txt`.
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Rules that disallow everything, used when a site's `robots.txt` is unreachable.
+    pub fn disallow_all() -> Self {
+        Self {
+            rules: vec![Rule {
+                allow: false,
+                pattern: "/".to_string(),
+            }],
+            ..Self::default()
+        }
+    }
+
+    /// Parses a `robots.txt`, keeping the groups for `user_agent`, or the `*` groups if
+    /// none name it.
+    pub fn parse(text: &str, user_agent: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut sitemaps = Vec::new();
+        let mut in_agent_lines = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim().to_ascii_lowercase(), value.trim());
+
+            // Sitemap lines apply to every agent, wherever they appear
+            if key == "sitemap" {
+                if !value.is_empty() {
+                    sitemaps.push(value.to_string());
+                }
+                continue;
+            }
+
+            if key == "user-agent" {
+                // Consecutive User-agent lines share a group
+                if !in_agent_lines {
+                    groups.push(Group::default());
+                    in_agent_lines = true;
+                }
+                if let Some(group) = groups.last_mut() {
+                    group.agents.push(value.to_ascii_lowercase());
+                }
+                continue;
+            }
+            in_agent_lines = false;
+
+            let Some(group) = groups.last_mut() else {
+                continue;
+            };
+            match key.as_str() {
+                "allow" | "disallow" if !value.is_empty() => group.rules.push(Rule {
+                    allow: key == "allow",
+                    pattern: value.to_string(),
+                }),
+                "crawl-delay" => {
+                    group.crawl_delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|delay| delay.is_finite() && *delay >= 0.0)
+                        .map(Duration::from_secs_f64);
+                }
+                _ => {}
+            }
+        }
+
+        let user_agent = user_agent.to_ascii_lowercase();
+        let named = groups
+            .iter()
+            .any(|group| group.agents.contains(&user_agent));
+        let wanted = if named { user_agent } else { "*".to_string() };
+
+        // Groups for the same agent are combined
+        let mut robots = RobotsTxt {
+            sitemaps,
+            ..RobotsTxt::default()
+        };
+        for group in groups
+            .into_iter()
+            .filter(|group| group.agents.contains(&wanted))
+        {
+            robots.rules.extend(group.rules);
+            robots.crawl_delay = robots.crawl_delay.or(group.crawl_delay);
+        }
+        robots
+    }
+
+    /// Returns true if a URL may be fetched. The most specific matching rule wins, and
+    /// `Allow` wins a tie.
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+
+        self.rules
+            .iter()
+            .filter(|rule| pattern_matches(&rule.pattern, &path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+
+    /// Gets how long to wait between requests, if the site asked for a delay.
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+
+    /// Gets the sitemap URLs listed with `Sitemap:` lines.
+    pub fn sitemaps(&self) -> &[String] {
+        &self.sitemaps
+    }
+}
+
+/// Matches a path against a rule, which matches any path it is a prefix of.
+/// `*` matches any characters and a trailing `$` anchors the end.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let pattern = pattern.as_bytes();
+    let path = path.as_bytes();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    loop {
+        if p == pattern.len() && (!anchored || t == path.len()) {
+            return true;
+        }
+        if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, t));
+            p += 1;
+            continue;
+        }
+        if p < pattern.len() && t < path.len() && pattern[p] == path[t] {
+            p += 1;
+            t += 1;
+            continue;
+        }
+        match backtrack {
+            Some((star, start)) if start < path.len() => {
+                p = star + 1;
+                t = start + 1;
+                backtrack = Some((star, start + 1));
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// The `robots.txt` of each site visited, fetched once per origin.
+#[derive(Clone, Default)]
+pub struct RobotsCache {
+    sites: Arc<Mutex<HashMap<String, Arc<RobotsTxt>>>>,
+}
+
+impl RobotsCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the rules for a URL's site, fetching its `robots.txt` the first time.
+    /// As in RFC 9309, a `robots.txt` that is missing (4xx) allows everything, and one
+    /// that is unreachable (5xx or a network error) disallows everything.
+    pub async fn get(&self, downloader: &Downloader, url: &Url) -> Arc<RobotsTxt> {
+        let origin = url.origin().ascii_serialization();
+        let mut sites = self.sites.lock().await;
+        if let Some(robots) = sites.get(&origin) {
+            return robots.clone();
+        }
+
+        let robots_url = format!("{}/robots.txt", origin);
+        let robots = match fetch_status_body(downloader, &robots_url, ROBOTS_SIZE_LIMIT).await {
+            Ok((_, Some(body))) => RobotsTxt::parse(
+                &String::from_utf8_lossy(&body),
+                user_agent_token(downloader),
+            ),
+            Ok((status, None)) if status.is_client_error() => {
+                log::info!("No robots.txt for {}: {}", origin, status);
+                RobotsTxt::allow_all()
+            }
+            Ok((status, None)) => {
+                log::warn!("robots.txt for {} is unavailable: {}", origin, status);
+                RobotsTxt::disallow_all()
+            }
+            Err(e) => {
+                log::warn!("robots.txt for {} is unreachable: {}", origin, e);
+                RobotsTxt::disallow_all()
+            }
+        };
+
+        let robots = Arc::new(robots);
+        sites.insert(origin, robots.clone());
+        robots
+    }
+}
+
+/// Gets the name to match `User-agent` lines against: the product token of a User-Agent
+/// header the downloader sends, e.g. `mybot` for `mybot/1.0`, or nget's own.
+fn user_agent_token(downloader: &Downloader) -> &str {
+    downloader
+        .headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|agent| agent.split(['/', ' ']).next())
+        .filter(|token| !token.is_empty())
+        .unwrap_or(USER_AGENT_TOKEN)
+}
@@ -0,0 +1,120 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::error::NgetError;
+use crate::utils::downloader::{DownloadOutcome, DownloadRequest};
+
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use url::Url;
+
+/// Spaces out requests to each host, waiting between them as asked.
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    wait: Duration,
+    random_wait: bool,
+    /// When the next request to each host may be sent
+    next_slot: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how long to wait between requests to the same host. Defaults to no wait.
+    pub fn wait(mut self, wait: Duration) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// Varies each wait between 0.5 and 1.5 times the set wait. Defaults to false.
+    pub fn random_wait(mut self, random_wait: bool) -> Self {
+        self.random_wait = random_wait;
+        self
+    }
+
+    /// Waits until a request to the URL's host may be sent. Requests to a host are spaced
+    /// by the wait, or by `min_delay` if that is longer, e.g. a robots.txt `Crawl-delay`.
+    pub async fn wait_turn(&self, url: &Url, min_delay: Duration) {
+        let host = match url.port_or_known_default() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let wait = match self.random_wait {
+            true => self.wait.mul_f64(0.5 + random_fraction()),
+            false => self.wait,
+        };
+        let spacing = wait.max(min_delay);
+
+        // Reserve a slot, so concurrent requests to a host queue up behind each other
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let now = Instant::now();
+            let slot = next_slot.get(&host).map_or(now, |&next| next.max(now));
+            next_slot.insert(host, slot + spacing);
+            slot
+        };
+
+        tokio::time::sleep_until(slot).await;
+    }
+
+    /// Downloads every request concurrently, each waiting for its turn with its host.
+    /// Results are in the order of the requests.
+    pub async fn run(
+        &self,
+        requests: Vec<DownloadRequest>,
+    ) -> Vec<Result<DownloadOutcome, NgetError>> {
+        let tasks: Vec<_> = requests
+            .into_iter()
+            .map(|request| {
+                let scheduler = self.clone();
+                tokio::spawn(async move {
+                    if let Ok(url) = Url::parse(request.url()) {
+                        let cancel = request.cancellation_token();
+                        tokio::select! {
+                            _ = cancel.cancelled() => {}
+                            _ = scheduler.wait_turn(&url, Duration::ZERO) => {}
+                        }
+                    }
+                    request.send().await
+                })
+            })
+            .collect();
+
+        futures::future::join_all(tasks)
+            .await
+            .into_iter()
+            .enumerate()
+            .map(|(i, result)| {
+                result.unwrap_or_else(|err| {
+                    log::error!("Task {} panicked: {:?}", i, err);
+                    Err(NgetError::Unknown)
+                })
+            })
+            .collect()
+    }
+}
+
+/// Gets a random number from 0 up to 1, good enough for spreading out waits.
+fn random_fraction() -> f64 {
+    let random = RandomState::new().hash_one(Instant::now().elapsed());
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
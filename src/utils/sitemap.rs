@@ -0,0 +1,317 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::enums::Compression;
+use crate::error::NgetError;
+use crate::utils::compression_utils::ContentDecoder;
+use crate::utils::downloader::Downloader;
+use crate::utils::http::fetch_body;
+use crate::utils::robots::RobotsCache;
+
+use bytes::Bytes;
+
+use quick_xml::events::Event;
+use quick_xml::name::{Namespace, ResolveResult};
+use quick_xml::NsReader;
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use url::Url;
+
+/// Largest sitemap that is read, once decompressed. The protocol allows 50MB.
+const SITEMAP_SIZE_LIMIT: usize = 50 * 1024 * 1024;
+
+/// Size of the pieces a gzipped sitemap is decompressed in, so the size limit is checked
+/// before a small body can expand very far.
+const GUNZIP_CHUNK_SIZE: usize = 8 * 1024;
+
+/// Namespace of the sitemap protocol's elements.
+const SITEMAP_NAMESPACE: &[u8] = b"http://www.sitemaps.org/schemas/sitemap/0.9";
+
+/// How many levels of sitemap indexes are followed.
+const MAX_INDEX_DEPTH: u32 = 3;
+
+/// A URL listed in a sitemap, or a sitemap listed in a sitemap index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SitemapEntry {
+    pub url: String,
+    /// When the page last changed, if the sitemap says
+    pub lastmod: Option<SystemTime>,
+}
+
+impl SitemapEntry {
+    /// Returns true if the page hasn't changed since the local copy at `path` was saved.
+    /// Entries without a `lastmod`, or without a local copy, are never up to date.
+    pub fn is_unmodified(&self, path: &Path) -> bool {
+        let Some(lastmod) = self.lastmod else {
+            return false;
+        };
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| lastmod <= modified)
+    }
+}
+
+/// A parsed sitemap: either a list of pages or an index of further sitemaps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sitemap {
+    Urls(Vec<SitemapEntry>),
+    Index(Vec<SitemapEntry>),
+}
+
+impl Sitemap {
+    /// Parses a `<urlset>` or `<sitemapindex>` document. Only `<loc>` and `<lastmod>`
+    /// directly inside a `<url>` or `<sitemap>` are read, so extensions such as image
+    /// sitemaps, with their own `<image:loc>`, are ignored.
+    pub fn parse(content: &[u8]) -> Result<Self, NgetError> {
+        let invalid =
+            |e: &dyn std::fmt::Display| NgetError::HttpRequest(format!("Invalid sitemap: {}", e));
+
+        let mut reader = NsReader::from_reader(content);
+        let mut buf = Vec::new();
+        let mut depth = 0;
+        let mut root: Option<String> = None;
+        let mut entries = Vec::new();
+        let mut entry: Option<(String, String)> = None;
+        let mut field: Option<String> = None;
+
+        loop {
+            let (namespace, event) = reader
+                .read_resolved_event_into(&mut buf)
+                .map_err(|e| invalid(&e))?;
+            // Elements of other namespaces belong to extensions
+            let protocol = match namespace {
+                ResolveResult::Bound(Namespace(namespace)) => namespace == SITEMAP_NAMESPACE,
+                ResolveResult::Unbound => true,
+                ResolveResult::Unknown(_) => false,
+            };
+            match event {
+                Event::Start(start) => {
+                    depth += 1;
+                    let name = String::from_utf8_lossy(start.local_name().as_ref()).to_string();
+                    match (depth, name.as_str()) {
+                        (1, _) => root = Some(name),
+                        (2, "url" | "sitemap") if protocol => entry = Some(Default::default()),
+                        (3, "loc" | "lastmod") if protocol && entry.is_some() => field = Some(name),
+                        _ => {}
+                    }
+                }
+                Event::End(end) => {
+                    match (depth, end.local_name().as_ref()) {
+                        (2, b"url" | b"sitemap") if protocol => {
+                            if let Some((loc, lastmod)) = entry.take() {
+                                if !loc.trim().is_empty() {
+                                    entries.push(SitemapEntry {
+                                        url: loc.trim().to_string(),
+                                        lastmod: parse_w3c_datetime(lastmod.trim()),
+                                    });
+                                }
+                            }
+                        }
+                        _ => field = None,
+                    }
+                    depth -= 1;
+                }
+                Event::Text(text) => {
+                    let text = text.unescape().map_err(|e| invalid(&e))?;
+                    append_field(&mut entry, &field, &text);
+                }
+                Event::CData(data) => {
+                    append_field(&mut entry, &field, &String::from_utf8_lossy(&data));
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        match root.as_deref() {
+            Some("urlset") => Ok(Sitemap::Urls(entries)),
+            Some("sitemapindex") => Ok(Sitemap::Index(entries)),
+            Some(other) => Err(invalid(&format!("unexpected root element <{}>", other))),
+            None => Err(invalid(&"no root element")),
+        }
+    }
+}
+
+/// Adds text to the `<loc>` or `<lastmod>` of the entry being read.
+fn append_field(entry: &mut Option<(String, String)>, field: &Option<String>, text: &str) {
+    match (entry, field.as_deref()) {
+        (Some((loc, _)), Some("loc")) => loc.push_str(text),
+        (Some((_, lastmod)), Some("lastmod")) => lastmod.push_str(text),
+        _ => {}
+    }
+}
+
+/// Fetches and parses a sitemap, decompressing it if it is gzipped.
+pub async fn fetch_sitemap(downloader: &Downloader, url: &str) -> Result<Sitemap, NgetError> {
+    let body = fetch_body(downloader, url, SITEMAP_SIZE_LIMIT).await?;
+    let content = match body.starts_with(&[0x1f, 0x8b]) {
+        true => gunzip(body).await?,
+        false => body,
+    };
+
+    Sitemap::parse(&content)
+        .map_err(|e| NgetError::HttpRequest(format!("Failed to read sitemap {}: {}", url, e)))
+}
+
+/// Gets every page listed in a sitemap, following sitemap indexes. Sitemaps listed in an
+/// index that can't be read are skipped with a warning.
+pub async fn collect_urls(
+    downloader: &Downloader,
+    url: &str,
+) -> Result<Vec<SitemapEntry>, NgetError> {
+    let mut pages = Vec::new();
+    let mut seen = HashSet::from([url.to_string()]);
+    let mut pending = match fetch_sitemap(downloader, url).await? {
+        Sitemap::Urls(entries) => return Ok(entries),
+        Sitemap::Index(sitemaps) => sitemaps
+            .into_iter()
+            .map(|sitemap| (sitemap.url, 1))
+            .collect::<Vec<_>>(),
+    };
+
+    while let Some((sitemap, depth)) = pending.pop() {
+        if !seen.insert(sitemap.clone()) || downloader.is_cancelled() {
+            continue;
+        }
+
+        match fetch_sitemap(downloader, &sitemap).await {
+            Ok(Sitemap::Urls(entries)) => pages.extend(entries),
+            Ok(Sitemap::Index(sitemaps)) if depth < MAX_INDEX_DEPTH => {
+                pending.extend(sitemaps.into_iter().map(|entry| (entry.url, depth + 1)));
+            }
+            Ok(Sitemap::Index(_)) => {
+                log::warn!(
+                    "Skipping {} as sitemap indexes are nested too deeply",
+                    sitemap
+                );
+            }
+            Err(e) => log::warn!("Skipping sitemap {}: {}", sitemap, e),
+        }
+    }
+
+    Ok(pages)
+}
+
+/// Decompresses a gzipped sitemap, such as `sitemap.xml.gz`.
+/// Decompression stops as soon as the sitemap passes the size limit.
+async fn gunzip(body: Vec<u8>) -> Result<Vec<u8>, NgetError> {
+    let too_large = || {
+        NgetError::HttpRequest(format!(
+            "Sitemap is larger than {} bytes once decompressed",
+            SITEMAP_SIZE_LIMIT
+        ))
+    };
+
+    let mut decoder = ContentDecoder::new(Some("gzip"), &Compression::Auto)?;
+    let mut content = Vec::new();
+    for chunk in body.chunks(GUNZIP_CHUNK_SIZE) {
+        content.extend_from_slice(&decoder.decode(Bytes::copy_from_slice(chunk)).await?);
+        if content.len() > SITEMAP_SIZE_LIMIT {
+            return Err(too_large());
+        }
+    }
+    content.extend_from_slice(&decoder.finish().await?);
+
+    if content.len() > SITEMAP_SIZE_LIMIT {
+        return Err(too_large());
+    }
+    Ok(content)
+}
+
+/// Parses a W3C datetime as used by `<lastmod>`: a date, optionally followed by a time
+/// with a time zone, e.g. `2024-05-01` or `2024-05-01T10:30:00+02:00`.
+pub fn parse_w3c_datetime(value: &str) -> Option<SystemTime> {
+    let (date, time) = match value.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+
+    let mut parts = date.split('-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next().map_or(Some(1), |month| month.parse().ok())?;
+    let day: u32 = parts.next().map_or(Some(1), |day| day.parse().ok())?;
+    if parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let mut seconds = days_from_civil(year, month, day) * 86400;
+    if let Some(time) = time {
+        // The zone is required with a time: Z or +hh:mm/-hh:mm
+        let (clock, offset) = match time.strip_suffix('Z') {
+            Some(clock) => (clock, 0),
+            None => {
+                let split = time.rfind(['+', '-'])?;
+                let (clock, zone) = time.split_at(split);
+                let (hours, minutes) = zone[1..].split_once(':')?;
+                let offset = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+                (
+                    clock,
+                    if zone.starts_with('-') {
+                        -offset
+                    } else {
+                        offset
+                    },
+                )
+            }
+        };
+
+        let mut fields = clock.split(':');
+        let hours: i64 = fields.next()?.parse().ok()?;
+        let minutes: i64 = fields.next()?.parse().ok()?;
+        let secs: f64 = fields.next().map_or(Some(0.0), |secs| secs.parse().ok())?;
+        if fields.next().is_some() || hours > 23 || minutes > 59 || !(0.0..61.0).contains(&secs) {
+            return None;
+        }
+        seconds += hours * 3600 + minutes * 60 + secs as i64 - offset;
+    }
+
+    let seconds = u64::try_from(seconds).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// Counts the days from 1970-01-01 to a date in the proleptic Gregorian calendar.
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Finds the sitemaps that the sites of the given URLs list in their `robots.txt`.
+pub async fn discover_sitemaps(downloader: &Downloader, urls: &[Url]) -> Vec<String> {
+    let robots_cache = RobotsCache::new();
+    let mut origins = HashSet::new();
+    let mut sitemaps = Vec::new();
+
+    for url in urls {
+        if !origins.insert(url.origin().ascii_serialization()) {
+            continue;
+        }
+        let robots = robots_cache.get(downloader, url).await;
+        for sitemap in robots.sitemaps() {
+            if !sitemaps.contains(sitemap) {
+                sitemaps.push(sitemap.clone());
+            }
+        }
+    }
+
+    sitemaps
+}
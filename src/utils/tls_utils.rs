@@ -0,0 +1,431 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::enums::HttpVersion;
+use crate::error::NgetError;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use reqwest::{Certificate, ClientBuilder, Identity};
+
+use sha2::{Digest, Sha256};
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::client::WebPkiServerVerifier;
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use tokio_rustls::rustls::server::ParsedCertificate;
+use tokio_rustls::rustls::{
+    self, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+
+#[derive(Default, Clone)]
+pub struct TlsConfig {
+    /// PEM file with extra CA certificates to trust
+    pub ca_certificate: Option<String>,
+    /// Directory of PEM files with extra CA certificates to trust
+    pub ca_directory: Option<String>,
+    /// PEM client certificate for mutual TLS
+    pub certificate: Option<String>,
+    /// PEM (PKCS#8) private key for the client certificate
+    pub private_key: Option<String>,
+    /// Skip server certificate and host name validation
+    pub no_check_certificate: bool,
+    /// Public key pins in the form `sha256//<base64>`, one of which must match the server chain
+    pub pinned_pubkeys: Vec<String>,
+}
+
+impl TlsConfig {
+    /// Creates a new empty instance of TlsConfig. Good for test use
+    pub fn empty() -> Self {
+        TlsConfig::default()
+    }
+}
+
+/// Applies the TLS options to a client builder.
+/// Public key pinning needs a custom certificate verifier, so pinned connections use rustls.
+pub fn configure_tls(
+    mut builder: ClientBuilder,
+    tls_config: &TlsConfig,
+    http_version: &HttpVersion,
+) -> Result<ClientBuilder, NgetError> {
+    if !tls_config.pinned_pubkeys.is_empty() {
+        let alpn_protocols = match http_version {
+            HttpVersion::Auto | HttpVersion::Http2 => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+            HttpVersion::H2c => vec![b"h2".to_vec()],
+            HttpVersion::Http11 | HttpVersion::Http3 => vec![b"http/1.1".to_vec()],
+        };
+        let rustls_config = build_rustls_config(tls_config, alpn_protocols)?;
+        return Ok(builder.use_preconfigured_tls(rustls_config));
+    }
+
+    for cert in load_ca_certificates(tls_config)? {
+        builder = builder.add_root_certificate(cert);
+    }
+
+    if let Some(identity) = load_identity(tls_config)? {
+        builder = builder.identity(identity);
+    }
+
+    if tls_config.no_check_certificate {
+        log::warn!("Server certificate validation is disabled.");
+        builder = builder
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true);
+    }
+
+    Ok(builder)
+}
+
+/// Loads the extra CA certificates from the CA file and CA directory.
+pub fn load_ca_certificates(tls_config: &TlsConfig) -> Result<Vec<Certificate>, NgetError> {
+    let mut certs = Vec::new();
+
+    if let Some(ca_file) = &tls_config.ca_certificate {
+        certs.extend(read_pem_bundle(Path::new(ca_file))?);
+    }
+
+    if let Some(ca_dir) = &tls_config.ca_directory {
+        for path in ca_directory_files(ca_dir)? {
+            certs.extend(read_pem_bundle(&path)?);
+        }
+    }
+
+    Ok(certs)
+}
+
+/// Loads the client certificate and private key for mutual TLS.
+/// The private key is read from the certificate file if no key file is given.
+pub fn load_identity(tls_config: &TlsConfig) -> Result<Option<Identity>, NgetError> {
+    let cert_file = match (&tls_config.certificate, &tls_config.private_key) {
+        (Some(cert_file), _) => cert_file,
+        (None, Some(_)) => {
+            return Err(NgetError::TlsError(
+                "A private key was given without a client certificate.".to_string(),
+            ))
+        }
+        (None, None) => return Ok(None),
+    };
+    let key_file = tls_config.private_key.as_ref().unwrap_or(cert_file);
+
+    let cert = read_file(Path::new(cert_file))?;
+    let key = read_file(Path::new(key_file))?;
+
+    Identity::from_pkcs8_pem(&cert, &key)
+        .map(Some)
+        .map_err(|e| NgetError::TlsError(format!("Invalid client certificate or key: {}", e)))
+}
+
+/// Gets the underlying TLS failure message if a request error was caused by the TLS handshake,
+/// found by walking the error's sources for a rustls or native-tls error.
+pub fn tls_error_message(err: &reqwest::Error) -> Option<String> {
+    let mut source = std::error::Error::source(err);
+    while let Some(inner) = source {
+        if let Some(message) = tls_failure(inner) {
+            return Some(message);
+        }
+        source = inner.source();
+    }
+    None
+}
+
+/// Gets the message of a TLS error, including one wrapped in an I/O error.
+fn tls_failure(err: &(dyn std::error::Error + 'static)) -> Option<String> {
+    if let Some(err) = err.downcast_ref::<rustls::Error>() {
+        return Some(err.to_string());
+    }
+    if let Some(err) = err.downcast_ref::<native_tls::Error>() {
+        return Some(err.to_string());
+    }
+    // An I/O error's sources skip the error it wraps, which is how rustls reports failures
+    err.downcast_ref::<std::io::Error>()
+        .and_then(std::io::Error::get_ref)
+        .and_then(|inner| tls_failure(inner))
+}
+
+/// Gets the `sha256//<base64>` pin of a DER encoded certificate's public key.
+pub fn spki_sha256_pin(cert_der: &[u8]) -> Result<String, NgetError> {
+    Ok(format!("sha256//{}", BASE64.encode(spki_sha256(cert_der)?)))
+}
+
+/// Parses public key pins, either one per value or several separated by `;`.
+pub fn parse_pins(pins: &[String]) -> Result<Vec<[u8; 32]>, NgetError> {
+    pins.iter()
+        .flat_map(|pin| pin.split(';'))
+        .map(|pin| {
+            let encoded = pin.trim().strip_prefix("sha256//").ok_or_else(|| {
+                NgetError::TlsError(format!(
+                    "Invalid public key pin '{}', expected sha256//<base64>",
+                    pin
+                ))
+            })?;
+
+            BASE64
+                .decode(encoded)
+                .ok()
+                .and_then(|hash| <[u8; 32]>::try_from(hash).ok())
+                .ok_or_else(|| {
+                    NgetError::TlsError(format!(
+                        "Invalid public key pin '{}', expected a base64 SHA-256 hash",
+                        pin
+                    ))
+                })
+        })
+        .collect()
+}
+
+fn spki_sha256(cert_der: &[u8]) -> Result<[u8; 32], NgetError> {
+    let cert = CertificateDer::from(cert_der);
+    let parsed = ParsedCertificate::try_from(&cert)
+        .map_err(|e| NgetError::TlsError(format!("Invalid certificate: {}", e)))?;
+
+    Ok(Sha256::digest(parsed.subject_public_key_info().as_ref()).into())
+}
+
+/// Builds a rustls client config honouring the TLS options, for connections that
+/// can't use the default TLS backend (public key pinning and QUIC).
+/// Trusts the platform's root certificates plus any extra CA certificates, like the
+/// default backend. The bundled webpki roots are used if the platform has none.
+pub fn build_rustls_config(
+    tls_config: &TlsConfig,
+    alpn_protocols: Vec<Vec<u8>>,
+) -> Result<ClientConfig, NgetError> {
+    let provider = Arc::new(crypto::aws_lc_rs::default_provider());
+    let pins = parse_pins(&tls_config.pinned_pubkeys)?;
+
+    let inner = if tls_config.no_check_certificate {
+        None
+    } else {
+        let mut roots = platform_roots();
+        for pem in read_ca_files(tls_config)? {
+            for cert in CertificateDer::pem_slice_iter(&pem) {
+                let cert = cert
+                    .map_err(|e| NgetError::TlsError(format!("Invalid CA certificate: {}", e)))?;
+                roots
+                    .add(cert)
+                    .map_err(|e| NgetError::TlsError(format!("Invalid CA certificate: {}", e)))?;
+            }
+        }
+
+        let verifier =
+            WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                .build()
+                .map_err(|e| NgetError::TlsError(format!("Failed to build verifier: {}", e)))?;
+        Some(verifier)
+    };
+
+    let verifier = Arc::new(CertVerifier {
+        pins,
+        inner,
+        provider: provider.clone(),
+    });
+
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| NgetError::TlsError(format!("Failed to configure TLS: {}", e)))?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier);
+
+    let mut config = match &tls_config.certificate {
+        Some(cert_file) => {
+            let key_file = tls_config.private_key.as_ref().unwrap_or(cert_file);
+            let cert_pem = read_file(Path::new(cert_file))?;
+            let certs = CertificateDer::pem_slice_iter(&cert_pem)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| NgetError::TlsError(format!("Invalid client certificate: {}", e)))?;
+            let key = PrivateKeyDer::from_pem_slice(&read_file(Path::new(key_file))?)
+                .map_err(|e| NgetError::TlsError(format!("Invalid private key: {}", e)))?;
+
+            builder.with_client_auth_cert(certs, key).map_err(|e| {
+                NgetError::TlsError(format!("Invalid client certificate or key: {}", e))
+            })?
+        }
+        None if tls_config.private_key.is_some() => {
+            return Err(NgetError::TlsError(
+                "A private key was given without a client certificate.".to_string(),
+            ))
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    config.alpn_protocols = alpn_protocols;
+
+    Ok(config)
+}
+
+/// Loads the platform's root certificates, falling back to the bundled webpki roots.
+fn platform_roots() -> RootCertStore {
+    let native = rustls_native_certs::load_native_certs();
+    for error in &native.errors {
+        log::warn!("Failed to load a platform root certificate: {}", error);
+    }
+
+    let mut roots = RootCertStore::empty();
+    roots.add_parsable_certificates(native.certs);
+    if roots.is_empty() {
+        log::warn!("No platform root certificates were found, using the bundled ones");
+        roots.roots = webpki_roots::TLS_SERVER_ROOTS.to_vec();
+    }
+    roots
+}
+
+/// Verifies the server chain as usual, then requires one of its public keys to match a pin
+/// if any are configured. Chain verification is skipped in insecure mode, but the pins are
+/// still enforced.
+#[derive(Debug)]
+struct CertVerifier {
+    pins: Vec<[u8; 32]>,
+    inner: Option<Arc<WebPkiServerVerifier>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for CertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(inner) = &self.inner {
+            inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        }
+
+        if self.pins.is_empty() {
+            return Ok(ServerCertVerified::assertion());
+        }
+
+        let pinned = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(|cert| spki_sha256(cert).ok())
+            .any(|hash| self.pins.contains(&hash));
+
+        if pinned {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(
+                "None of the certificate chain's public keys match a pinned public key".to_string(),
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Reads the PEM contents of the CA file and every certificate file in the CA directory.
+fn read_ca_files(tls_config: &TlsConfig) -> Result<Vec<Vec<u8>>, NgetError> {
+    let mut files = Vec::new();
+
+    if let Some(ca_file) = &tls_config.ca_certificate {
+        files.push(read_file(Path::new(ca_file))?);
+    }
+
+    if let Some(ca_dir) = &tls_config.ca_directory {
+        for path in ca_directory_files(ca_dir)? {
+            files.push(read_file(&path)?);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Lists the certificate files (`.pem`, `.crt` and `.cer`) in a CA directory.
+fn ca_directory_files(ca_dir: &str) -> Result<Vec<PathBuf>, NgetError> {
+    let entries = std::fs::read_dir(ca_dir).map_err(|e| {
+        NgetError::TlsError(format!("Failed to read CA directory {}: {}", ca_dir, e))
+    })?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| NgetError::TlsError(format!("Failed to read CA directory: {}", e)))?
+            .path();
+
+        let is_cert = matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("pem" | "crt" | "cer")
+        );
+        if path.is_file() && is_cert {
+            files.push(path);
+        }
+    }
+
+    if files.is_empty() {
+        return Err(NgetError::TlsError(format!(
+            "No CA certificates found in directory: {}",
+            ca_dir
+        )));
+    }
+    Ok(files)
+}
+
+fn read_pem_bundle(path: &Path) -> Result<Vec<Certificate>, NgetError> {
+    let pem = read_file(path)?;
+    let certs = Certificate::from_pem_bundle(&pem).map_err(|e| {
+        NgetError::TlsError(format!("Invalid CA certificate {}: {}", path.display(), e))
+    })?;
+
+    if certs.is_empty() {
+        return Err(NgetError::TlsError(format!(
+            "No certificates found in {}",
+            path.display()
+        )));
+    }
+    Ok(certs)
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, NgetError> {
+    std::fs::read(path)
+        .map_err(|e| NgetError::TlsError(format!("Failed to read {}: {}", path.display(), e)))
+}
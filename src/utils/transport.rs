@@ -0,0 +1,168 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::enums::HttpVersion;
+use crate::error::NgetError;
+use crate::utils::client_utils::get_client;
+use crate::utils::http3::{connect_http3, Http3Connection};
+use crate::utils::proxy_utils::ProxyConfig;
+use crate::utils::resolver_utils::build_resolver;
+use crate::utils::tls_utils::{tls_error_message, TlsConfig};
+
+use bytes::Bytes;
+
+use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+
+use reqwest::header::{self, HeaderMap};
+use reqwest::{Client, Method, StatusCode, Version};
+
+use std::time::Duration;
+
+use url::Url;
+
+/// A response whose body is read in chunks, whichever HTTP version carried it.
+pub struct TransportResponse {
+    /// URL of the response, after any redirects
+    pub url: String,
+    /// Status code of the response
+    pub status: StatusCode,
+    /// HTTP version the response was received over
+    pub version: Version,
+    /// Response headers
+    pub headers: HeaderMap,
+    /// Body chunks as received on the wire, before any content decoding
+    pub body: BoxStream<'static, Result<Bytes, NgetError>>,
+}
+
+impl TransportResponse {
+    /// Gets a header value as a string, if present and valid.
+    pub fn header(&self, name: header::HeaderName) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// Gets the length of the body from the Content-Length header.
+    pub fn content_length(&self) -> Option<u64> {
+        self.header(header::CONTENT_LENGTH)
+            .and_then(|value| value.parse().ok())
+    }
+}
+
+/// The connection a download's request is sent over, chosen by the HTTP version.
+pub enum Transport {
+    /// HTTP/1.1 and HTTP/2 over TCP
+    Tcp(Client),
+    /// HTTP/3 over QUIC
+    Quic(Http3Connection),
+}
+
+impl Transport {
+    /// Prepares a transport for the given URL, checking the HTTP version can be used with it.
+    pub async fn connect(
+        parsed_url: &Url,
+        http_version: &HttpVersion,
+        config: &ProxyConfig,
+        tls_config: &TlsConfig,
+        connect_timeout: Option<Duration>,
+    ) -> Result<Self, NgetError> {
+        if *http_version == HttpVersion::Http3 {
+            let connection = connect_http3(parsed_url, config, tls_config, connect_timeout).await?;
+            return Ok(Transport::Quic(connection));
+        }
+
+        // ALPN is part of the TLS handshake, so cleartext servers need prior knowledge
+        if *http_version == HttpVersion::Http2 && parsed_url.scheme() != "https" {
+            return Err(NgetError::UnsupportedHTTPVersion(format!(
+                "HTTP/2 via ALPN requires an https:// URL, use h2c for cleartext servers: {}",
+                parsed_url
+            )));
+        }
+
+        let client = get_client(config, tls_config, http_version)?;
+
+        // Make sure the URL actually exists using DNS lookup
+        lookup_host(parsed_url, config).await?;
+
+        Ok(Transport::Tcp(client))
+    }
+
+    /// Sends a GET request with the given headers, returning once the response headers arrive.
+    pub async fn send(self, url: &str, headers: HeaderMap) -> Result<TransportResponse, NgetError> {
+        self.send_method(Method::GET, url, headers, Bytes::new())
+            .await
+    }
+
+    /// Sends a request with any method and body, e.g. a WebDAV `PROPFIND`.
+    /// Only GET requests can be sent over HTTP/3.
+    pub async fn send_method(
+        self,
+        method: Method,
+        url: &str,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Result<TransportResponse, NgetError> {
+        match self {
+            Transport::Tcp(client) => {
+                let response = client
+                    .request(method, url)
+                    .headers(headers)
+                    .body(body)
+                    .send()
+                    .await
+                    .map_err(|e| match tls_error_message(&e) {
+                        Some(message) => NgetError::TlsError(format!(
+                            "TLS handshake failed for {}: {}",
+                            url, message
+                        )),
+                        None => NgetError::HttpRequest(format!("Request error for {}: {}", url, e)),
+                    })?;
+
+                Ok(TransportResponse {
+                    url: response.url().to_string(),
+                    status: response.status(),
+                    version: response.version(),
+                    headers: response.headers().clone(),
+                    body: response
+                        .bytes_stream()
+                        .map(|chunk| {
+                            chunk.map_err(|e| {
+                                NgetError::HttpRequest(format!("Failed to read chunk: {}", e))
+                            })
+                        })
+                        .boxed(),
+                })
+            }
+            Transport::Quic(_) if method != Method::GET => Err(NgetError::UnsupportedHTTPVersion(
+                format!("{} requests can't be sent over HTTP/3: {}", method, url),
+            )),
+            Transport::Quic(connection) => connection.send(url, headers).await,
+        }
+    }
+}
+
+/// Looks up the host of a URL using the local DNS resolver.
+/// Skipped when a socks5h proxy is in use, as the proxy resolves the host itself.
+async fn lookup_host(parsed_url: &Url, config: &ProxyConfig) -> Result<(), NgetError> {
+    if config.resolves_remotely() {
+        return Ok(());
+    }
+
+    let resolver = build_resolver();
+
+    let _dns_response = resolver
+        .lookup_ip(parsed_url.host_str().unwrap_or_default())
+        .await?;
+
+    Ok(())
+}
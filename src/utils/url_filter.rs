@@ -0,0 +1,315 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::error::NgetError;
+
+use regex::Regex;
+
+use url::Url;
+
+/// Extensions of files that may be HTML pages, which are downloaded for their links even
+/// when their names are rejected.
+const HTML_EXTENSIONS: [&str; 9] = [
+    "html", "htm", "shtml", "xhtml", "php", "asp", "aspx", "jsp", "cgi",
+];
+
+/// Decides which URLs recursive and batch downloads fetch.
+///
+/// All rules must pass for a URL to be allowed. Lists that are empty don't restrict anything.
+#[derive(Debug, Clone, Default)]
+pub struct UrlFilter {
+    accept: Vec<String>,
+    reject: Vec<String>,
+    accept_regex: Option<Regex>,
+    reject_regex: Option<Regex>,
+    domains: Vec<String>,
+    exclude_domains: Vec<String>,
+    include_directories: Vec<String>,
+    exclude_directories: Vec<String>,
+    no_parent: bool,
+}
+
+impl UrlFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only accepts file names matching one of these globs. A pattern without wildcards
+    /// matches the end of the name, so `pdf` accepts `report.pdf`.
+    pub fn accept(mut self, patterns: Vec<String>) -> Self {
+        self.accept = patterns;
+        self
+    }
+
+    /// Rejects file names matching any of these globs, written as for [`UrlFilter::accept`].
+    pub fn reject(mut self, patterns: Vec<String>) -> Self {
+        self.reject = patterns;
+        self
+    }
+
+    /// Only accepts URLs matching this regular expression.
+    pub fn accept_regex(mut self, pattern: &str) -> Result<Self, NgetError> {
+        self.accept_regex = Some(compile(pattern)?);
+        Ok(self)
+    }
+
+    /// Rejects URLs matching this regular expression.
+    pub fn reject_regex(mut self, pattern: &str) -> Result<Self, NgetError> {
+        self.reject_regex = Some(compile(pattern)?);
+        Ok(self)
+    }
+
+    /// Only accepts hosts in these domains, which crawls may then follow links to.
+    pub fn domains(mut self, domains: Vec<String>) -> Self {
+        self.domains = domains;
+        self
+    }
+
+    /// Rejects hosts in these domains.
+    pub fn exclude_domains(mut self, domains: Vec<String>) -> Self {
+        self.exclude_domains = domains;
+        self
+    }
+
+    /// Only accepts URLs inside one of these directories, which may contain wildcards.
+    pub fn include_directories(mut self, directories: Vec<String>) -> Self {
+        self.include_directories = directories;
+        self
+    }
+
+    /// Rejects URLs inside any of these directories, which may contain wildcards.
+    pub fn exclude_directories(mut self, directories: Vec<String>) -> Self {
+        self.exclude_directories = directories;
+        self
+    }
+
+    /// Rejects URLs above the directory of the start URL on the same host.
+    pub fn no_parent(mut self, no_parent: bool) -> Self {
+        self.no_parent = no_parent;
+        self
+    }
+
+    /// Checks a URL against every rule except the file name ones, which are checked by
+    /// [`UrlFilter::accepts_file_name`]. With `--no-parent`, URLs must be inside the
+    /// directory of one of `parents` on the same host.
+    pub fn allows(&self, url: &Url, parents: &[Url]) -> bool {
+        let host = url.host_str().unwrap_or_default();
+        if self
+            .exclude_domains
+            .iter()
+            .any(|domain| in_domain(host, domain))
+        {
+            return false;
+        }
+        if !self.domains.is_empty() && !self.domains.iter().any(|domain| in_domain(host, domain)) {
+            return false;
+        }
+
+        let directory = &url.path()[..url.path().rfind('/').unwrap_or(0)];
+        if !self.include_directories.is_empty()
+            && !self
+                .include_directories
+                .iter()
+                .any(|pattern| in_directory(directory, pattern))
+        {
+            return false;
+        }
+        if self
+            .exclude_directories
+            .iter()
+            .any(|pattern| in_directory(directory, pattern))
+        {
+            return false;
+        }
+
+        if self.no_parent && !self.below_parent(url, parents) {
+            return false;
+        }
+
+        let accepted = self
+            .accept_regex
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(url.as_str()));
+        let rejected = self
+            .reject_regex
+            .as_ref()
+            .is_some_and(|regex| regex.is_match(url.as_str()));
+        accepted && !rejected
+    }
+
+    /// Checks the file name of a URL against the accept and reject globs.
+    pub fn accepts_file_name(&self, url: &Url) -> bool {
+        let name = file_name(url);
+        let accepted = self.accept.is_empty()
+            || self
+                .accept
+                .iter()
+                .any(|pattern| name_matches(pattern, name));
+        let rejected = self
+            .reject
+            .iter()
+            .any(|pattern| name_matches(pattern, name));
+        accepted && !rejected
+    }
+
+    /// Returns true if a crawl can follow a link to this URL's host: one of `hosts`, or
+    /// anywhere allowed by [`UrlFilter::allows`] if domains were given.
+    pub fn spans_to(&self, url: &Url, hosts: &[String]) -> bool {
+        !self.domains.is_empty()
+            || url
+                .host_str()
+                .is_some_and(|host| hosts.iter().any(|known| known == host))
+    }
+
+    /// Returns true if a URL is inside the directory of a parent on the same host, or no
+    /// parent is on its host.
+    fn below_parent(&self, url: &Url, parents: &[Url]) -> bool {
+        let mut same_host = parents
+            .iter()
+            .filter(|parent| parent.host_str() == url.host_str() && parent.port() == url.port())
+            .peekable();
+        if same_host.peek().is_none() {
+            return true;
+        }
+
+        same_host.any(|parent| {
+            let directory = &parent.path()[..parent.path().rfind('/').unwrap_or(0) + 1];
+            url.path().starts_with(directory)
+        })
+    }
+}
+
+/// Returns true if a URL's file name suggests it may be an HTML page, e.g. a directory
+/// index, `page.html` or `script.php`.
+pub fn may_be_html(url: &Url) -> bool {
+    match file_name(url).rsplit_once('.') {
+        Some((_, extension)) => HTML_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()),
+        None => true,
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, NgetError> {
+    Regex::new(pattern).map_err(|e| {
+        NgetError::ConfigurationError(format!("Invalid regular expression '{}': {}", pattern, e))
+    })
+}
+
+/// Gets the last path segment of a URL, which is empty for a directory.
+fn file_name(url: &Url) -> &str {
+    url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or_default()
+}
+
+/// Returns true if a host is a domain or one of its subdomains.
+fn in_domain(host: &str, domain: &str) -> bool {
+    let domain = domain.trim_start_matches('.');
+    host.eq_ignore_ascii_case(domain)
+        || (host.len() > domain.len()
+            && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+            && host[host.len() - domain.len()..].eq_ignore_ascii_case(domain))
+}
+
+/// Returns true if a directory path (without trailing slash) is inside a directory pattern.
+fn in_directory(directory: &str, pattern: &str) -> bool {
+    let pattern = format!("/{}", pattern.trim_matches('/'));
+    if pattern == "/" {
+        return true;
+    }
+
+    // Match the pattern against the directory itself and each of its ancestors
+    directory
+        .match_indices('/')
+        .map(|(index, _)| index)
+        .skip(1)
+        .chain(std::iter::once(directory.len()))
+        .any(|end| glob_match(&pattern, &directory[..end]))
+}
+
+/// Matches a file name against an accept or reject pattern.
+fn name_matches(pattern: &str, name: &str) -> bool {
+    if pattern.contains(['*', '?', '[']) {
+        glob_match(pattern, name)
+    } else {
+        name.ends_with(pattern)
+    }
+}
+
+/// Matches text against a shell glob with `*`, `?` and `[...]` classes.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*` if the rest doesn't match
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(p + 1),
+            Some('[') => match_class(&pattern, p, text[t]),
+            Some(&c) => (c == text[t]).then_some(p + 1),
+            None => None,
+        };
+
+        match (step, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                t += 1;
+            }
+            (None, Some((star, start))) => {
+                // Let the `*` swallow one more character
+                p = star + 1;
+                t = start + 1;
+                backtrack = Some((star, start + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches a character against the `[...]` class starting at `start`, returning the
+/// position after the class if it matches.
+fn match_class(pattern: &[char], start: usize, c: char) -> Option<usize> {
+    let mut i = start + 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() && (pattern[i] != ']' || first) {
+        first = false;
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&end| end != ']') {
+            matched |= (pattern[i]..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= pattern[i] == c;
+            i += 1;
+        }
+    }
+
+    // An unclosed class matches a literal `[`
+    if i >= pattern.len() {
+        return (c == '[').then_some(start + 1);
+    }
+    (matched != negated).then_some(i + 1)
+}
@@ -14,26 +14,57 @@
 
 use crate::error::NgetError;
 
+use std::path::PathBuf;
+
 use url::Url;
 
 /// Parses a given URL with NgetError mapping.
-pub fn parse_url(url: &str) -> Url {
+pub fn parse_url(url: &str) -> Result<Url, NgetError> {
     Url::parse(url)
-        .map_err(|e| {
-            log::error!("Failed to parse URL '{}': {}", url, e);
-            NgetError::InvalidUrl(format!("Failed to parse URL: {}", e))
-        })
-        .expect("Failed to parse URL.")
+        .map_err(|e| NgetError::InvalidUrl(format!("Failed to parse URL '{}': {}", url, e)))
 }
 
 /// Gets the file name from a given URL
 pub fn get_file_name(url: Url) -> String {
-    if url.path() == "/" {
+    // data: URLs have no path to name the file after
+    if url.scheme() == "data" {
+        "data".to_string()
+    } else if url.path() == "/" {
         "index.html".to_string()
     } else {
         url.path_segments()
-            .and_then(|segments| segments.last())
+            .and_then(|mut segments| segments.next_back())
             .unwrap_or("index")
             .to_string()
     }
 }
+
+/// Gets the path a URL is saved to when mirroring a site: the host, then the URL's path.
+/// A directory is saved as its `index.html`, and any query string is kept in the file name.
+pub fn mirror_path(url: &Url) -> PathBuf {
+    let host = url.host_str().unwrap_or("localhost");
+    let mut path = match url.port() {
+        Some(port) => PathBuf::from(format!("{}:{}", host, port)),
+        None => PathBuf::from(host),
+    };
+
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.collect())
+        .unwrap_or_default();
+    let (file_name, directories) = segments.split_last().unwrap_or((&"", &[]));
+    for directory in directories.iter().filter(|directory| !directory.is_empty()) {
+        path.push(directory);
+    }
+
+    let file_name = match *file_name {
+        "" => "index.html",
+        name => name,
+    };
+    match url.query() {
+        Some(query) => path.push(format!("{}?{}", file_name, query)),
+        None => path.push(file_name),
+    }
+
+    path
+}
@@ -0,0 +1,225 @@
+// This file is part of nget
+//
+// Copyright (C) 2024 Peggun
+//
+// nget is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// nget is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+use crate::error::NgetError;
+use crate::utils::dir_listing::{parse_listing_date, ListingEntry};
+use crate::utils::downloader::Downloader;
+use crate::utils::http::request_body;
+
+use bytes::Bytes;
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{Method, StatusCode};
+
+use std::time::SystemTime;
+
+use url::Url;
+
+/// Largest `PROPFIND` response that is read.
+const MULTISTATUS_SIZE_LIMIT: usize = 16 * 1024 * 1024;
+
+/// The properties asked for when listing a collection.
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:propfind xmlns:D="DAV:">
+  <D:prop>
+    <D:resourcetype/>
+    <D:getcontentlength/>
+    <D:getlastmodified/>
+  </D:prop>
+</D:propfind>"#;
+
+/// A file or collection on a WebDAV server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DavResource {
+    /// Absolute URL of the resource, ending with `/` for a collection
+    pub url: Url,
+    pub is_collection: bool,
+    pub size: Option<u64>,
+    pub modified: Option<SystemTime>,
+}
+
+impl From<DavResource> for ListingEntry {
+    fn from(resource: DavResource) -> Self {
+        ListingEntry {
+            url: resource.url,
+            is_dir: resource.is_collection,
+            modified: resource.modified,
+            size: resource.size,
+        }
+    }
+}
+
+/// Returns true for `dav://` and `davs://` URLs.
+pub fn is_dav_url(url: &str) -> bool {
+    let url = url.to_ascii_lowercase();
+    url.starts_with("dav://") || url.starts_with("davs://")
+}
+
+/// Turns a `dav://` or `davs://` URL into the `http://` or `https://` URL it is served at.
+/// Other URLs are returned unchanged.
+pub fn to_http_url(url: &str) -> String {
+    let lower = url.to_ascii_lowercase();
+    if lower.starts_with("davs://") {
+        format!("https{}", &url[4..])
+    } else if lower.starts_with("dav://") {
+        format!("http{}", &url[3..])
+    } else {
+        url.to_string()
+    }
+}
+
+/// Lists the members of a collection with a `PROPFIND` of depth 1. The collection itself
+/// isn't included. Anything but a `207 Multi-Status` fails, e.g. when a redirect turned
+/// the request into a `GET`.
+pub async fn list_collection(
+    downloader: &Downloader,
+    url: &Url,
+) -> Result<Vec<DavResource>, NgetError> {
+    // Servers redirect a collection's URL without its trailing slash
+    let mut url = url.clone();
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+
+    let mut headers = HeaderMap::new();
+    headers.insert("Depth", HeaderValue::from_static("1"));
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/xml; charset=utf-8"),
+    );
+    let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+
+    let body = request_body(
+        downloader,
+        propfind,
+        url.as_str(),
+        headers,
+        Bytes::from_static(PROPFIND_BODY.as_bytes()),
+        Some(StatusCode::MULTI_STATUS),
+        MULTISTATUS_SIZE_LIMIT,
+    )
+    .await?;
+
+    let resources = parse_multistatus(&body, &url)?;
+    Ok(resources
+        .into_iter()
+        .filter(|resource| !same_path(&resource.url, &url))
+        .collect())
+}
+
+/// Parses a `207 Multi-Status` response, resolving each `href` against `base`.
+pub fn parse_multistatus(content: &[u8], base: &Url) -> Result<Vec<DavResource>, NgetError> {
+    let invalid =
+        |e: &dyn std::fmt::Display| NgetError::HttpRequest(format!("Invalid multistatus: {}", e));
+
+    let mut reader = Reader::from_reader(content);
+    let mut buf = Vec::new();
+    let mut resources = Vec::new();
+    let mut response: Option<Response> = None;
+    let mut field: Option<String> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf).map_err(|e| invalid(&e))? {
+            Event::Start(start) => {
+                let name = String::from_utf8_lossy(start.local_name().as_ref()).to_string();
+                match name.as_str() {
+                    "response" => response = Some(Response::default()),
+                    "collection" => mark_collection(&mut response),
+                    "href" | "getcontentlength" | "getlastmodified" => field = Some(name),
+                    _ => {}
+                }
+            }
+            Event::Empty(empty) if empty.local_name().as_ref() == b"collection" => {
+                mark_collection(&mut response)
+            }
+            Event::End(end) => match end.local_name().as_ref() {
+                b"response" => {
+                    if let Some(resource) = response.take().and_then(|r| r.resource(base)) {
+                        resources.push(resource);
+                    }
+                }
+                _ => field = None,
+            },
+            Event::Text(text) => {
+                let text = text.unescape().map_err(|e| invalid(&e))?;
+                if let (Some(response), Some(field)) = (&mut response, &field) {
+                    response.append(field, &text);
+                }
+            }
+            Event::CData(data) => {
+                if let (Some(response), Some(field)) = (&mut response, &field) {
+                    response.append(field, &String::from_utf8_lossy(&data));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(resources)
+}
+
+/// Records that a `<collection/>` resource type was found in a response.
+fn mark_collection(response: &mut Option<Response>) {
+    if let Some(response) = response {
+        response.is_collection = true;
+    }
+}
+
+/// The text of one `<response>` of a multistatus, as it is read.
+#[derive(Default)]
+struct Response {
+    href: String,
+    content_length: String,
+    last_modified: String,
+    is_collection: bool,
+}
+
+impl Response {
+    fn append(&mut self, field: &str, text: &str) {
+        let value = match field {
+            "href" => &mut self.href,
+            "getcontentlength" => &mut self.content_length,
+            "getlastmodified" => &mut self.last_modified,
+            _ => return,
+        };
+        value.push_str(text);
+    }
+
+    fn resource(self, base: &Url) -> Option<DavResource> {
+        let mut url = base.join(self.href.trim()).ok()?;
+        // Collections are sometimes listed without their trailing slash
+        if self.is_collection && !url.path().ends_with('/') {
+            let path = format!("{}/", url.path());
+            url.set_path(&path);
+        }
+
+        Some(DavResource {
+            url,
+            is_collection: self.is_collection,
+            size: self.content_length.trim().parse().ok(),
+            modified: parse_listing_date(self.last_modified.trim()),
+        })
+    }
+}
+
+/// Returns true if two URLs name the same resource, ignoring a trailing slash.
+fn same_path(a: &Url, b: &Url) -> bool {
+    a.origin() == b.origin() && a.path().trim_end_matches('/') == b.path().trim_end_matches('/')
+}
@@ -0,0 +1,138 @@
+mod common;
+
+#[cfg(test)]
+mod alpn_tests {
+    use crate::common::{generate_pki, start_h2_server, start_https_server};
+
+    use nget::enums::{Compression, HttpVersion};
+    use nget::error::NgetError;
+    use nget::http::download_file;
+    use nget::progress::SilentObserver;
+    use nget::proxy_utils::ProxyConfig;
+    use nget::tls_utils::TlsConfig;
+
+    use std::path::Path;
+    use std::sync::Arc;
+
+    async fn download(
+        url: &str,
+        save_dir: &str,
+        ca_file: &Path,
+        http_version: &HttpVersion,
+    ) -> Result<(), NgetError> {
+        let output_file_name = None;
+        let tls_config = TlsConfig {
+            ca_certificate: Some(ca_file.display().to_string()),
+            ..Default::default()
+        };
+
+        download_file(
+            url,
+            save_dir,
+            &output_file_name,
+            Arc::new(SilentObserver),
+            http_version,
+            &ProxyConfig::empty(),
+            &tls_config,
+            &Compression::Auto,
+        )
+        .await
+    }
+
+    /// Creates the output directory with the test CA certificate in it.
+    async fn setup(save_dir: &str, ca_pem: &str) -> std::path::PathBuf {
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+        let ca_file = Path::new(save_dir).join("ca.pem");
+        tokio::fs::write(&ca_file, ca_pem).await.unwrap();
+        ca_file
+    }
+
+    #[tokio::test]
+    async fn test_auto_negotiates_http2() {
+        let pki = generate_pki();
+        let addr = start_h2_server(&pki, "HTTP/2 content").await;
+        let save_dir = "./test_output_alpn_auto_h2";
+        let ca_file = setup(save_dir, &pki.ca_pem).await;
+
+        // The server only speaks HTTP/2, so this only works if ALPN picked h2
+        let url = format!("https://localhost:{}/h2file", addr.port());
+        let result = download(&url, save_dir, &ca_file, &HttpVersion::Auto).await;
+        assert!(result.is_ok(), "Auto request failed: {:?}", result.err());
+
+        let content = tokio::fs::read_to_string(Path::new(save_dir).join("h2file"))
+            .await
+            .unwrap();
+        assert_eq!(content, "HTTP/2 content");
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_auto_falls_back_to_http11() {
+        let pki = generate_pki();
+        let addr = start_https_server(&pki, "HTTP/1.1 content", false).await;
+        let save_dir = "./test_output_alpn_auto_h1";
+        let ca_file = setup(save_dir, &pki.ca_pem).await;
+
+        let url = format!("https://localhost:{}/h1file", addr.port());
+        let result = download(&url, save_dir, &ca_file, &HttpVersion::Auto).await;
+        assert!(result.is_ok(), "Auto request failed: {:?}", result.err());
+
+        let content = tokio::fs::read_to_string(Path::new(save_dir).join("h1file"))
+            .await
+            .unwrap();
+        assert_eq!(content, "HTTP/1.1 content");
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_http2_via_alpn() {
+        let pki = generate_pki();
+        let addr = start_h2_server(&pki, "HTTP/2 content").await;
+        let save_dir = "./test_output_alpn_http2";
+        let ca_file = setup(save_dir, &pki.ca_pem).await;
+
+        let url = format!("https://localhost:{}/h2file", addr.port());
+        let result = download(&url, save_dir, &ca_file, &HttpVersion::Http2).await;
+        assert!(result.is_ok(), "HTTP/2 request failed: {:?}", result.err());
+
+        let content = tokio::fs::read_to_string(Path::new(save_dir).join("h2file"))
+            .await
+            .unwrap();
+        assert_eq!(content, "HTTP/2 content");
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_http2_required_but_not_negotiated() {
+        let pki = generate_pki();
+        let addr = start_https_server(&pki, "HTTP/1.1 content", false).await;
+        let save_dir = "./test_output_alpn_http2_missing";
+        let ca_file = setup(save_dir, &pki.ca_pem).await;
+
+        let url = format!("https://localhost:{}/h1file", addr.port());
+        let result = download(&url, save_dir, &ca_file, &HttpVersion::Http2).await;
+        assert!(
+            matches!(result, Err(NgetError::UnsupportedHTTPVersion(_))),
+            "Expected an unsupported version error, got {:?}",
+            result
+        );
+        assert!(!Path::new(save_dir).join("h1file").exists());
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_http2_requires_tls() {
+        let result = download(
+            "http://127.0.0.1:9/file",
+            "./test_output_alpn_cleartext",
+            Path::new("./ca.pem"),
+            &HttpVersion::Http2,
+        )
+        .await;
+        assert!(matches!(result, Err(NgetError::UnsupportedHTTPVersion(_))));
+    }
+}
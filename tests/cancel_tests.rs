@@ -0,0 +1,261 @@
+mod common;
+
+#[cfg(test)]
+mod cancel_tests {
+    use crate::common::RecordingObserver;
+
+    use nget::error::NgetError;
+    use nget::resume_utils::ResumeState;
+    use nget::{DownloadEvent, Downloader};
+
+    use std::net::SocketAddr;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use tokio::fs;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Starts a server that sends the first half of a 20 byte body, then stalls.
+    async fn start_stalling_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    let _ = stream.read(&mut buf).await;
+                    let _ = stream
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\nContent-Length: 20\r\nETag: \"v1\"\r\n\r\nFirst half",
+                        )
+                        .await;
+                    let _ = stream.flush().await;
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                });
+            }
+        });
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_cancel_in_flight_saves_resume_state() {
+        let addr = start_stalling_server().await;
+        let url = format!("http://127.0.0.1:{}/stalled.bin", addr.port());
+        let save_dir = "./test_output_cancel_in_flight";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        let downloader = Downloader::new();
+        let recorder = Arc::new(RecordingObserver::default());
+        let request = downloader
+            .request(&url)
+            .output_dir(save_dir)
+            .observer(recorder.clone());
+        let download = tokio::spawn(request.send());
+
+        // Wait until the first half has arrived
+        for _ in 0..50 {
+            let received = recorder
+                .events()
+                .iter()
+                .any(|event| matches!(event, DownloadEvent::Bytes { .. }));
+            if received {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        downloader.cancel();
+
+        let result = download.await.unwrap();
+        assert!(matches!(result, Err(NgetError::Cancelled(_))));
+        assert!(matches!(
+            recorder.events().last(),
+            Some(DownloadEvent::Cancelled)
+        ));
+
+        // What was received is kept, along with the state needed to resume it
+        let file_path = Path::new(save_dir).join("stalled.bin");
+        assert_eq!(fs::read_to_string(&file_path).await.unwrap(), "First half");
+        let state = ResumeState::load(&file_path).await.unwrap();
+        assert_eq!(state.bytes, 10);
+        assert_eq!(state.etag.as_deref(), Some("\"v1\""));
+        assert!(!state.decoded);
+
+        // Resuming asks for the rest, as long as the content is unchanged
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/stalled.bin"))
+            .and(header("Range", "bytes=10-"))
+            .and(header("If-Range", "\"v1\""))
+            .respond_with(
+                ResponseTemplate::new(206)
+                    .set_body_string(", resumed")
+                    .insert_header("Content-Range", "bytes 10-18/19"),
+            )
+            .mount(&mock_server)
+            .await;
+
+        // Only a partial file saved from the same URL is resumed
+        let url = format!("{}/stalled.bin", &mock_server.uri());
+        let state = ResumeState {
+            url: url.clone(),
+            ..state
+        };
+        state.save(&file_path).await.unwrap();
+        Downloader::new()
+            .request(&url)
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&file_path).await.unwrap(),
+            "First half, resumed"
+        );
+        assert!(ResumeState::load(&file_path).await.is_none());
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cancel_during_retry_delay() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/unavailable"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/unavailable", &mock_server.uri());
+
+        let downloader = Downloader::new()
+            .retries(3)
+            .retry_delay(Duration::from_secs(30));
+        let request = downloader
+            .request(&url)
+            .output_dir("./test_output_cancel_retry");
+        let started = Instant::now();
+        let download = tokio::spawn(request.send());
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        downloader.cancel();
+
+        let result = download.await.unwrap();
+        assert!(matches!(result, Err(NgetError::Cancelled(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_single_request() {
+        let addr = start_stalling_server().await;
+        let save_dir = "./test_output_cancel_single";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        let downloader = Downloader::new();
+        let request = downloader
+            .request(&format!("http://127.0.0.1:{}/single.bin", addr.port()))
+            .output_dir(save_dir);
+        let token = request.cancellation_token();
+        let download = tokio::spawn(request.send());
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        token.cancel();
+
+        let result = download.await.unwrap();
+        assert!(matches!(result, Err(NgetError::Cancelled(_))));
+
+        // Other downloads from the same downloader are unaffected
+        assert!(!downloader.is_cancelled());
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_decoded_resume_state_restarts() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/decoded.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Whole file"))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/decoded.txt", &mock_server.uri());
+        let save_dir = "./test_output_decoded_resume";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        // A partial file written while decoding can't be resumed with a byte range
+        let file_path = Path::new(save_dir).join("decoded.txt");
+        fs::write(&file_path, "Whole").await.unwrap();
+        let state = ResumeState {
+            url: url.clone(),
+            etag: None,
+            bytes: 5,
+            decoded: true,
+        };
+        state.save(&file_path).await.unwrap();
+
+        Downloader::new()
+            .request(&url)
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap();
+
+        let requests = mock_server.received_requests().await.unwrap();
+        assert!(requests[0].headers.get("Range").is_none());
+        assert_eq!(fs::read_to_string(&file_path).await.unwrap(), "Whole file");
+        assert!(ResumeState::load(&file_path).await.is_none());
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_state_from_other_url_restarts() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/new.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Whole file"))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/new.txt", &mock_server.uri());
+        let save_dir = "./test_output_other_url_resume";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        // The partial file was saved from a different URL, so none of it is kept
+        let file_path = Path::new(save_dir).join("new.txt");
+        fs::write(&file_path, "Other").await.unwrap();
+        let state = ResumeState {
+            url: format!("{}/old.txt", &mock_server.uri()),
+            etag: Some("\"old\"".to_string()),
+            bytes: 5,
+            decoded: false,
+        };
+        state.save(&file_path).await.unwrap();
+
+        Downloader::new()
+            .request(&url)
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap();
+
+        let requests = mock_server.received_requests().await.unwrap();
+        assert!(requests[0].headers.get("Range").is_none());
+        assert!(requests[0].headers.get("If-Range").is_none());
+        assert_eq!(fs::read_to_string(&file_path).await.unwrap(), "Whole file");
+        assert!(ResumeState::load(&file_path).await.is_none());
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+}
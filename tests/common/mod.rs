@@ -0,0 +1,267 @@
+//! Test helpers shared by the integration tests: a private PKI, local HTTPS servers, mock
+//! server pages and byte ranges, and a recording progress observer.
+#![allow(dead_code)]
+
+use rcgen::{BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+
+use bytes::Bytes;
+
+use nget::progress::{DownloadEvent, ProgressObserver};
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{self, RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+/// A private CA with a server certificate for `localhost` and `127.0.0.1`, and a client certificate.
+pub struct TestPki {
+    pub ca_pem: String,
+    pub ca_der: CertificateDer<'static>,
+    pub server_cert: CertificateDer<'static>,
+    pub server_key: Vec<u8>,
+    pub client_cert_pem: String,
+    pub client_key_pem: String,
+}
+
+pub fn generate_pki() -> TestPki {
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "nget test CA");
+    let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+
+    let server_key = KeyPair::generate().unwrap();
+    let server_params =
+        CertificateParams::new(vec!["localhost".to_string(), "127.0.0.1".to_string()]).unwrap();
+    let server_cert = server_params
+        .signed_by(&server_key, &ca_cert, &ca_key)
+        .unwrap();
+
+    let client_key = KeyPair::generate().unwrap();
+    let mut client_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    client_params
+        .distinguished_name
+        .push(DnType::CommonName, "nget test client");
+    client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let client_cert = client_params
+        .signed_by(&client_key, &ca_cert, &ca_key)
+        .unwrap();
+
+    TestPki {
+        ca_pem: ca_cert.pem(),
+        ca_der: ca_cert.der().clone(),
+        server_cert: server_cert.der().clone(),
+        server_key: server_key.serialize_der(),
+        client_cert_pem: client_cert.pem(),
+        client_key_pem: client_key.serialize_pem(),
+    }
+}
+
+/// Starts an HTTPS server on localhost that answers every request with `body`.
+/// The server sends its certificate chain including the CA certificate.
+/// Requires a client certificate signed by the test CA when `require_client_cert` is set.
+pub async fn start_https_server(
+    pki: &TestPki,
+    body: &'static str,
+    require_client_cert: bool,
+) -> SocketAddr {
+    start_https_server_with_headers(pki, body, require_client_cert, String::new()).await
+}
+
+/// Starts an HTTPS server like `start_https_server`, adding `extra_headers`
+/// (each ending in `\r\n`) to every response.
+pub async fn start_https_server_with_headers(
+    pki: &TestPki,
+    body: &'static str,
+    require_client_cert: bool,
+    extra_headers: String,
+) -> SocketAddr {
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .unwrap();
+
+    let builder = if require_client_cert {
+        let mut roots = RootCertStore::empty();
+        roots.add(pki.ca_der.clone()).unwrap();
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+            .build()
+            .unwrap();
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+
+    let mut config = builder
+        .with_single_cert(
+            vec![pki.server_cert.clone(), pki.ca_der.clone()],
+            PrivatePkcs8KeyDer::from(pki.server_key.clone()).into(),
+        )
+        .unwrap();
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            let extra_headers = extra_headers.clone();
+            tokio::spawn(async move {
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+
+                // Read the request headers, the body is always empty for GET
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+
+                // HEAD responses carry the headers only
+                let payload = if request.starts_with(b"HEAD ") {
+                    ""
+                } else {
+                    body
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+                    body.len(),
+                    extra_headers,
+                    payload
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+
+    addr
+}
+
+/// Starts an HTTPS server on localhost that only speaks HTTP/2, negotiated via ALPN,
+/// and answers every request with `body`.
+pub async fn start_h2_server(pki: &TestPki, body: &'static str) -> SocketAddr {
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![pki.server_cert.clone(), pki.ca_der.clone()],
+            PrivatePkcs8KeyDer::from(pki.server_key.clone()).into(),
+        )
+        .unwrap();
+    config.alpn_protocols = vec![b"h2".to_vec()];
+
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let Ok(mut connection) = h2::server::handshake(stream).await else {
+                    return;
+                };
+
+                while let Some(Ok((_request, mut respond))) = connection.accept().await {
+                    let response = http::Response::builder()
+                        .status(200)
+                        .header("content-length", body.len())
+                        .body(())
+                        .unwrap();
+                    if let Ok(mut send) = respond.send_response(response, false) {
+                        let _ = send.send_data(Bytes::from_static(body.as_bytes()), true);
+                    }
+                }
+            });
+        }
+    });
+
+    addr
+}
+
+/// Serves `body` with the given content type for GET requests to `route`.
+pub async fn mount_page(
+    mock_server: &MockServer,
+    route: &str,
+    body: impl AsRef<[u8]>,
+    content_type: &str,
+) {
+    Mock::given(method("GET"))
+        .and(path(route))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(body.as_ref().to_vec(), content_type))
+        .mount(mock_server)
+        .await;
+}
+
+/// Answers mock server requests with a body, or the part of it a `Range: bytes=N-` or
+/// `Range: bytes=N-M` header asks for.
+pub struct RangeResponder(pub Vec<u8>);
+
+impl Respond for RangeResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let len = self.0.len();
+        let range = request
+            .headers
+            .get("range")
+            .and_then(|range| range.to_str().ok())
+            .and_then(|range| range.strip_prefix("bytes="))
+            .and_then(|range| range.split_once('-'))
+            .and_then(|(first, last)| {
+                let first: usize = first.parse().ok()?;
+                let last = match last {
+                    "" => len - 1,
+                    last => last.parse::<usize>().ok()?.min(len - 1),
+                };
+                Some((first, last))
+            });
+
+        match range {
+            Some((first, last)) if first <= last => ResponseTemplate::new(206)
+                .insert_header("content-range", format!("bytes {}-{}/{}", first, last, len))
+                .set_body_bytes(&self.0[first..=last]),
+            Some(_) => ResponseTemplate::new(416)
+                .insert_header("content-range", format!("bytes */{}", len)),
+            None => ResponseTemplate::new(200).set_body_bytes(self.0.clone()),
+        }
+    }
+}
+
+/// A progress observer that keeps every event it receives.
+#[derive(Default)]
+pub struct RecordingObserver {
+    events: Mutex<Vec<DownloadEvent>>,
+}
+
+impl RecordingObserver {
+    pub fn events(&self) -> Vec<DownloadEvent> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl ProgressObserver for RecordingObserver {
+    fn on_event(&self, event: &DownloadEvent) {
+        self.events.lock().unwrap().push(event.clone());
+    }
+}
@@ -0,0 +1,223 @@
+#[cfg(test)]
+mod compression_tests {
+    use async_compression::tokio::write::{BrotliEncoder, GzipEncoder, ZstdEncoder};
+
+    use nget::enums::{Compression, HttpVersion};
+    use nget::http::download_file;
+    use nget::progress::SilentObserver;
+    use nget::proxy_utils::ProxyConfig;
+    use nget::tls_utils::TlsConfig;
+
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use tokio::fs;
+    use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+    use wiremock::matchers::{header, headers, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const CONTENT: &str = "Compressed file content, repeated. Compressed file content, repeated.";
+
+    async fn encode<E: AsyncWrite + Unpin>(mut encoder: E) -> E {
+        encoder.write_all(CONTENT.as_bytes()).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        encoder
+    }
+
+    async fn download(
+        url: &str,
+        save_dir: &str,
+        http_version: &HttpVersion,
+        compression: &Compression,
+    ) -> Result<(), nget::error::NgetError> {
+        let output_file_name = None;
+
+        download_file(
+            url,
+            save_dir,
+            &output_file_name,
+            Arc::new(SilentObserver),
+            http_version,
+            &ProxyConfig::empty(),
+            &TlsConfig::empty(),
+            compression,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_gzip_auto_decoding() {
+        let mock_server = MockServer::start().await;
+        let body = encode(GzipEncoder::new(Vec::new())).await.into_inner();
+
+        Mock::given(method("GET"))
+            .and(path("/gzipfile"))
+            .and(headers(
+                "Accept-Encoding",
+                vec!["gzip", "deflate", "br", "zstd"],
+            ))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Encoding", "gzip")
+                    .set_body_bytes(body),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let save_dir = "./test_output_gzip";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        for http_version in [HttpVersion::Auto, HttpVersion::Http11, HttpVersion::H2c] {
+            let url = format!("{}/gzipfile", mock_server.uri());
+            let result = download(&url, save_dir, &http_version, &Compression::Auto).await;
+            assert!(result.is_ok(), "Gzip request failed: {:?}", result.err());
+
+            let saved_file_path = Path::new(save_dir).join("gzipfile");
+            let content = fs::read_to_string(&saved_file_path).await.unwrap();
+            assert_eq!(content, CONTENT);
+            fs::remove_file(saved_file_path).await.unwrap();
+        }
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_brotli_and_zstd_decoding() {
+        let mock_server = MockServer::start().await;
+        let brotli = encode(BrotliEncoder::new(Vec::new())).await.into_inner();
+        let zstd = encode(ZstdEncoder::new(Vec::new())).await.into_inner();
+
+        Mock::given(method("GET"))
+            .and(path("/brfile"))
+            .and(header("Accept-Encoding", "br"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Encoding", "br")
+                    .set_body_bytes(brotli),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/zstdfile"))
+            .and(header("Accept-Encoding", "zstd"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Encoding", "zstd")
+                    .set_body_bytes(zstd),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let save_dir = "./test_output_br_zstd";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        for (file, compression) in [("brfile", Compression::Br), ("zstdfile", Compression::Zstd)] {
+            let url = format!("{}/{}", mock_server.uri(), file);
+            let result = download(&url, save_dir, &HttpVersion::Http11, &compression).await;
+            assert!(
+                result.is_ok(),
+                "{} request failed: {:?}",
+                file,
+                result.err()
+            );
+
+            let content = fs::read_to_string(Path::new(save_dir).join(file))
+                .await
+                .unwrap();
+            assert_eq!(content, CONTENT);
+        }
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_no_compression_saves_body_verbatim() {
+        let mock_server = MockServer::start().await;
+        let body = encode(GzipEncoder::new(Vec::new())).await.into_inner();
+
+        // The server ignores Accept-Encoding: identity and compresses anyway
+        Mock::given(method("GET"))
+            .and(path("/rawfile.gz"))
+            .and(header("Accept-Encoding", "identity"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Encoding", "gzip")
+                    .set_body_bytes(body.clone()),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let save_dir = "./test_output_no_compression";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        let url = format!("{}/rawfile.gz", mock_server.uri());
+        let result = download(&url, save_dir, &HttpVersion::Http11, &Compression::None).await;
+        assert!(result.is_ok(), "Request failed: {:?}", result.err());
+
+        let content = fs::read(Path::new(save_dir).join("rawfile.gz"))
+            .await
+            .unwrap();
+        assert_eq!(content, body);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resumed_download_is_not_decoded() {
+        let mock_server = MockServer::start().await;
+
+        // Resumed requests ask for the remaining bytes of the unencoded body
+        Mock::given(method("GET"))
+            .and(path("/resumefile"))
+            .and(header("Range", "bytes=7-"))
+            .and(header("Accept-Encoding", "identity"))
+            .respond_with(
+                ResponseTemplate::new(206)
+                    .insert_header("Content-Range", "bytes 7-13/14")
+                    .set_body_string(" resume"),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let save_dir = "./test_output_resume_compression";
+        fs::create_dir_all(save_dir).await.unwrap();
+        let saved_file_path = Path::new(save_dir).join("resumefile");
+        fs::write(&saved_file_path, "Partial").await.unwrap();
+
+        let url = format!("{}/resumefile", mock_server.uri());
+        let result = download(&url, save_dir, &HttpVersion::Http11, &Compression::Auto).await;
+        assert!(result.is_ok(), "Resume request failed: {:?}", result.err());
+
+        let content = fs::read_to_string(&saved_file_path).await.unwrap();
+        assert_eq!(content, "Partial resume");
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_truncated_gzip_body() {
+        let mock_server = MockServer::start().await;
+        let mut body = encode(GzipEncoder::new(Vec::new())).await.into_inner();
+        body.truncate(body.len() / 2);
+
+        Mock::given(method("GET"))
+            .and(path("/truncated"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Encoding", "gzip")
+                    .set_body_bytes(body),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let save_dir = "./test_output_truncated_gzip";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        let url = format!("{}/truncated", mock_server.uri());
+        let result = download(&url, save_dir, &HttpVersion::Http11, &Compression::Auto).await;
+        assert!(result.is_err());
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+}
@@ -0,0 +1,340 @@
+mod common;
+
+#[cfg(test)]
+mod crawler_tests {
+    use crate::common::mount_page;
+
+    use nget::crawler::{Crawler, Frontier};
+    use nget::css_utils::extract_css_links;
+    use nget::html_utils::{base_href, extract_links, LinkKind};
+    use nget::url_utils::mirror_path;
+    use nget::Downloader;
+
+    use std::path::{Path, PathBuf};
+
+    use tokio::fs;
+
+    use url::Url;
+
+    use wiremock::MockServer;
+
+    /// Gets the directory a mock server's files are mirrored into.
+    fn host_dir(save_dir: &str, mock_server: &MockServer) -> PathBuf {
+        let url = Url::parse(&mock_server.uri()).unwrap();
+        Path::new(save_dir).join(format!("127.0.0.1:{}", url.port().unwrap()))
+    }
+
+    #[tokio::test]
+    async fn test_recursive_crawl() {
+        let mock_server = MockServer::start().await;
+
+        mount_page(
+            &mock_server,
+            "/",
+            r#"<html><head><link rel="stylesheet" href="/css/site.css"></head>
+            <body><a href="docs/page.html#top">Docs</a>
+            <a href="https://other.example/away.html">Elsewhere</a>
+            <img src="/img/logo.png" srcset="/img/logo-2x.png 2x, /img/logo-3x.png 3x">
+            <script src="app.js"></script></body></html>"#,
+            "text/html; charset=utf-8",
+        )
+        .await;
+        mount_page(
+            &mock_server,
+            "/docs/page.html",
+            r#"<a href="../">Home</a> <a href="deeper.html">Deeper</a>"#,
+            "text/html",
+        )
+        .await;
+        mount_page(&mock_server, "/docs/deeper.html", "Too deep", "text/html").await;
+        mount_page(&mock_server, "/css/site.css", "body {}", "text/css").await;
+        mount_page(&mock_server, "/img/logo.png", "png", "image/png").await;
+        mount_page(&mock_server, "/img/logo-2x.png", "png2", "image/png").await;
+        mount_page(&mock_server, "/img/logo-3x.png", "png3", "image/png").await;
+        mount_page(&mock_server, "/app.js", "js", "text/javascript").await;
+
+        let save_dir = "./test_output_crawl";
+        let entries = Crawler::new(Downloader::new())
+            .output_dir(save_dir)
+            .level(Some(1))
+            .crawl(&[format!("{}/", mock_server.uri())])
+            .await;
+
+        assert!(entries.iter().all(|entry| entry.result.is_ok()));
+        let urls: Vec<&str> = entries
+            .iter()
+            .map(|entry| entry.url.trim_start_matches(&mock_server.uri()))
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "/",
+                "/css/site.css",
+                "/docs/page.html",
+                "/img/logo.png",
+                "/img/logo-2x.png",
+                "/img/logo-3x.png",
+                "/app.js",
+            ]
+        );
+
+        // Links found on a page record where they came from
+        let page = &entries[2];
+        assert_eq!(page.depth, 1);
+        assert_eq!(page.referrer, Some(format!("{}/", mock_server.uri())));
+
+        let host_dir = host_dir(save_dir, &mock_server);
+        assert!(host_dir.join("index.html").exists());
+        assert_eq!(
+            fs::read_to_string(host_dir.join("docs/page.html"))
+                .await
+                .unwrap(),
+            r#"<a href="../">Home</a> <a href="deeper.html">Deeper</a>"#
+        );
+        assert!(host_dir.join("img/logo-3x.png").exists());
+        assert!(!host_dir.join("docs/deeper.html").exists());
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_crawl_records_failures() {
+        let mock_server = MockServer::start().await;
+
+        mount_page(
+            &mock_server,
+            "/start.html",
+            r#"<a href="/missing.html">Gone</a>"#,
+            "text/html",
+        )
+        .await;
+
+        let save_dir = "./test_output_crawl_failures";
+        let entries = Crawler::new(Downloader::new())
+            .output_dir(save_dir)
+            .crawl(&[format!("{}/start.html", mock_server.uri())])
+            .await;
+
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].result.is_ok());
+        assert!(entries[1].result.is_err());
+        assert_eq!(
+            entries[1].referrer,
+            Some(format!("{}/start.html", mock_server.uri()))
+        );
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_page_requisites() {
+        let mock_server = MockServer::start().await;
+        let cdn_server = MockServer::start().await;
+
+        mount_page(
+            &mock_server,
+            "/article.html",
+            &format!(
+                r#"<link rel="stylesheet" href="{}/theme.css">
+                <style>header {{ background: url('banner.png') }}</style>
+                <div style="background-image: url(&quot;tile.png&quot;)"></div>
+                <a href="next.html">Next</a>"#,
+                cdn_server.uri()
+            ),
+            "text/html",
+        )
+        .await;
+        mount_page(&mock_server, "/banner.png", "banner", "image/png").await;
+        mount_page(&mock_server, "/tile.png", "tile", "image/png").await;
+        mount_page(&mock_server, "/next.html", "Not a requisite", "text/html").await;
+        mount_page(
+            &cdn_server,
+            "/theme.css",
+            r#"@import "fonts.css"; body { color: black }"#,
+            "text/css",
+        )
+        .await;
+        mount_page(
+            &cdn_server,
+            "/fonts.css",
+            "@font-face { src: url(/fonts/body.woff2) format('woff2') }",
+            "text/css",
+        )
+        .await;
+        mount_page(&cdn_server, "/fonts/body.woff2", "font", "font/woff2").await;
+
+        let save_dir = "./test_output_page_requisites";
+        let entries = Crawler::new(Downloader::new())
+            .output_dir(save_dir)
+            .level(Some(0))
+            .page_requisites(true)
+            .crawl(&[format!("{}/article.html", mock_server.uri())])
+            .await;
+
+        assert!(entries.iter().all(|entry| entry.result.is_ok()));
+        let urls: Vec<&str> = entries.iter().map(|entry| entry.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                format!("{}/article.html", mock_server.uri()),
+                format!("{}/theme.css", cdn_server.uri()),
+                format!("{}/banner.png", mock_server.uri()),
+                format!("{}/tile.png", mock_server.uri()),
+                format!("{}/fonts.css", cdn_server.uri()),
+                format!("{}/fonts/body.woff2", cdn_server.uri()),
+            ]
+        );
+
+        // Requisites from other hosts are mirrored under their own host
+        assert!(host_dir(save_dir, &cdn_server)
+            .join("fonts/body.woff2")
+            .exists());
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_embedded_page_requisites() {
+        let mock_server = MockServer::start().await;
+        let ads_server = MockServer::start().await;
+
+        mount_page(
+            &mock_server,
+            "/article.html",
+            &format!(r#"<iframe src="{}/frame.html"></iframe>"#, ads_server.uri()),
+            "text/html",
+        )
+        .await;
+        mount_page(
+            &ads_server,
+            "/frame.html",
+            r#"<img src="banner.png"><iframe src="next.html"></iframe><a href="more.html">More</a>"#,
+            "text/html",
+        )
+        .await;
+        mount_page(&ads_server, "/banner.png", "banner", "image/png").await;
+        mount_page(&ads_server, "/next.html", "Next frame", "text/html").await;
+
+        let save_dir = "./test_output_embedded_page_requisites";
+        let entries = Crawler::new(Downloader::new())
+            .output_dir(save_dir)
+            .level(Some(0))
+            .page_requisites(true)
+            .crawl(&[format!("{}/article.html", mock_server.uri())])
+            .await;
+
+        // The frame from another host is downloaded, but nothing it leads to
+        let urls: Vec<&str> = entries.iter().map(|entry| entry.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                format!("{}/article.html", mock_server.uri()),
+                format!("{}/frame.html", ads_server.uri()),
+            ]
+        );
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[test]
+    fn test_extract_css_links() {
+        let css = br#"/* url(commented.png) */
+            @import "print.css";
+            @IMPORT url(screen.css) screen;
+            .a { background: URL( 'a.png' ) }
+            .b { background: url(b.png) }
+            .c::before { content: "url(not-a-link)" }
+            .d { mask: myurl(x) }"#;
+
+        let links = extract_css_links(css);
+        let values: Vec<&str> = links.iter().map(|link| link.value.as_str()).collect();
+        assert_eq!(values, ["print.css", "screen.css", "a.png", "b.png"]);
+        assert_eq!(&css[links[2].span.clone()], b"a.png".as_slice());
+    }
+
+    #[test]
+    fn test_extract_links() {
+        let html = br#"<!-- <a href="commented.html"> -->
+            <A HREF='upper.html'>x</A>
+            <a href=unquoted.html?a=1&amp;b=2>y</a>
+            <link rel="alternate" href="feed.xml">
+            <link rel="icon" href="favicon.ico">
+            <script>var s = '<a href="in-script.html">';</script>
+            <iframe src="frame.html"></iframe>
+            <img alt="no link">"#;
+
+        let links = extract_links(html);
+        let values: Vec<(&str, LinkKind)> = links
+            .iter()
+            .map(|link| (link.value.as_str(), link.kind))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("upper.html", LinkKind::Hyperlink),
+                ("unquoted.html?a=1&b=2", LinkKind::Hyperlink),
+                ("feed.xml", LinkKind::Hyperlink),
+                ("favicon.ico", LinkKind::Requisite),
+                ("frame.html", LinkKind::Requisite),
+            ]
+        );
+
+        // Spans cover the link as written
+        let unquoted = &links[1];
+        assert_eq!(
+            &html[unquoted.span.clone()],
+            b"unquoted.html?a=1&amp;b=2".as_slice()
+        );
+    }
+
+    #[test]
+    fn test_srcset_and_base() {
+        let html = br#"<base href="/static/"><img srcset="a.png 1x,b.png 2x, c.png">"#;
+
+        let links = extract_links(html);
+        let values: Vec<&str> = links.iter().map(|link| link.value.as_str()).collect();
+        assert_eq!(values, ["a.png", "b.png", "c.png"]);
+        assert_eq!(&html[links[1].span.clone()], b"b.png".as_slice());
+        assert_eq!(base_href(html), Some("/static/".to_string()));
+    }
+
+    #[test]
+    fn test_mirror_path() {
+        let path = |url: &str| mirror_path(&Url::parse(url).unwrap());
+
+        assert_eq!(
+            path("https://example.com/"),
+            Path::new("example.com/index.html")
+        );
+        assert_eq!(
+            path("https://example.com/docs/"),
+            Path::new("example.com/docs/index.html")
+        );
+        assert_eq!(
+            path("http://example.com:8080/a/b.css"),
+            Path::new("example.com:8080/a/b.css")
+        );
+        assert_eq!(
+            path("https://example.com/search?q=1"),
+            Path::new("example.com/search?q=1")
+        );
+    }
+
+    #[test]
+    fn test_frontier_deduplicates() {
+        let mut frontier = Frontier::new();
+        let url = |url: &str| Url::parse(url).unwrap();
+
+        assert!(frontier.push(url("https://example.com/a"), 0, None));
+        assert!(!frontier.push(url("https://example.com/a#section"), 1, None));
+        assert!(frontier.push(url("https://example.com/b"), 1, None));
+        assert_eq!(frontier.len(), 2);
+
+        assert_eq!(frontier.pop().unwrap().url.path(), "/a");
+        assert_eq!(frontier.pop().unwrap().url.path(), "/b");
+        assert!(frontier.pop().is_none());
+
+        // Popped URLs are still remembered
+        assert!(!frontier.push(url("https://example.com/a"), 2, None));
+    }
+}
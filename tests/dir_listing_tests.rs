@@ -0,0 +1,215 @@
+#[cfg(test)]
+mod dir_listing_tests {
+    use nget::crawler::Crawler;
+    use nget::dir_listing::{parse_listing, parse_listing_date, ListingEntry};
+    use nget::url_filter::UrlFilter;
+    use nget::Downloader;
+
+    use std::path::Path;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use tokio::fs;
+
+    use url::Url;
+
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// 2024-05-01 10:30 UTC
+    const MAY_FIRST: u64 = 1714559400;
+    /// 2024-04-30 08:15 UTC
+    const APRIL_LAST: u64 = 1714464900;
+
+    fn at(seconds: u64) -> Option<SystemTime> {
+        Some(UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    /// Parses a listing of https://example.com/pub/, returning each entry's name.
+    fn parse(content: &str) -> Vec<(String, ListingEntry)> {
+        let base = Url::parse("https://example.com/pub/").unwrap();
+        parse_listing(content.as_bytes(), &base)
+            .into_iter()
+            .map(|entry| {
+                let name = entry.url.path().trim_start_matches("/pub/").to_string();
+                (name, entry)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_apache_listing() {
+        let entries = parse(
+            r#"<table>
+            <tr><th><img src="/icons/blank.gif" alt="[ICO]"></th><th><a href="?C=N;O=D">Name</a></th>
+            <th><a href="?C=M;O=A">Last modified</a></th><th><a href="?C=S;O=A">Size</a></th></tr>
+            <tr><td><img src="/icons/back.gif" alt="[PARENTDIR]"></td><td><a href="/">Parent Directory</a></td>
+            <td>&nbsp;</td><td align="right">  - </td></tr>
+            <tr><td><img src="/icons/folder.gif" alt="[DIR]"></td><td><a href="docs/">docs/</a></td>
+            <td align="right">2024-05-01 10:30  </td><td align="right">  - </td></tr>
+            <tr><td><img src="/icons/compressed.gif" alt="[   ]"></td><td><a href="app-1.0.tar.gz">app-1.0.tar.gz</a></td>
+            <td align="right">2024-04-30 08:15  </td><td align="right">1.2M</td></tr>
+            </table>
+            <pre><a href="notes%20v2.txt">notes v2.txt</a>        30-Apr-2024 08:15  345</pre>"#,
+        );
+
+        let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["docs/", "app-1.0.tar.gz", "notes%20v2.txt"]);
+
+        let (_, docs) = &entries[0];
+        assert!(docs.is_dir);
+        assert_eq!(docs.modified, at(MAY_FIRST));
+        assert_eq!(docs.size, None);
+
+        // Rounded sizes aren't exact enough to compare
+        let (_, app) = &entries[1];
+        assert!(!app.is_dir);
+        assert_eq!(app.modified, at(APRIL_LAST));
+        assert_eq!(app.size, None);
+
+        let (_, notes) = &entries[2];
+        assert_eq!(notes.modified, at(APRIL_LAST));
+        assert_eq!(notes.size, Some(345));
+    }
+
+    #[test]
+    fn test_parse_nginx_listing() {
+        let entries = parse(
+            "<html><head><title>Index of /pub/</title></head><body><h1>Index of /pub/</h1><hr><pre>\
+            <a href=\"../\">../</a>\n\
+            <a href=\"docs/\">docs/</a>                          01-May-2024 10:30                   -\n\
+            <a href=\"app-1.0.tar.gz\">app-1.0.tar.gz</a>        30-Apr-2024 08:15             1234567\n\
+            </pre><hr></body></html>",
+        );
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, "docs/");
+        assert_eq!(entries[0].1.modified, at(MAY_FIRST));
+        assert_eq!(entries[1].0, "app-1.0.tar.gz");
+        assert_eq!(entries[1].1.modified, at(APRIL_LAST));
+        assert_eq!(entries[1].1.size, Some(1234567));
+
+        // nginx's JSON autoindex
+        let entries = parse(
+            r#"[
+            { "name":"docs", "type":"directory", "mtime":"Wed, 01 May 2024 10:30:00 GMT" },
+            { "name":"app #1.tar.gz", "type":"file", "mtime":"Tue, 30 Apr 2024 08:15:00 GMT", "size":1234567 }
+            ]"#,
+        );
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, "docs/");
+        assert!(entries[0].1.is_dir);
+        assert_eq!(entries[0].1.modified, at(MAY_FIRST));
+        assert_eq!(entries[1].0, "app%20%231.tar.gz");
+        assert_eq!(entries[1].1.size, Some(1234567));
+        assert_eq!(entries[1].1.modified, at(APRIL_LAST));
+    }
+
+    #[test]
+    fn test_parse_lighttpd_and_python_listings() {
+        let entries = parse(
+            r#"<table><tbody>
+            <tr class="d"><td class="n"><a href="../">Parent Directory</a>/</td><td class="m">&nbsp;</td><td class="s">- &nbsp;</td><td class="t">Directory</td></tr>
+            <tr class="d"><td class="n"><a href="docs/">docs</a>/</td><td class="m">2024-May-01 10:30:00</td><td class="s">- &nbsp;</td><td class="t">Directory</td></tr>
+            <tr><td class="n"><a href="app.bin">app.bin</a></td><td class="m">2024-Apr-30 08:15:00</td><td class="s">12</td><td class="t">application/octet-stream</td></tr>
+            </tbody></table>"#,
+        );
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].1.modified, at(MAY_FIRST));
+        assert_eq!(entries[1].1.modified, at(APRIL_LAST));
+        assert_eq!(entries[1].1.size, Some(12));
+
+        // Python's http.server shows names only
+        let entries = parse(
+            r#"<h1>Directory listing for /pub/</h1><hr><ul>
+            <li><a href="docs/">docs/</a></li>
+            <li><a href="app.bin">app.bin</a></li>
+            </ul><hr>"#,
+        );
+
+        let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["docs/", "app.bin"]);
+        assert!(entries.iter().all(|(_, entry)| entry.modified.is_none()));
+    }
+
+    #[test]
+    fn test_parse_listing_date() {
+        assert_eq!(parse_listing_date("01-May-2024 10:30"), at(MAY_FIRST));
+        assert_eq!(parse_listing_date("2024-05-01 10:30:00"), at(MAY_FIRST));
+        assert_eq!(
+            parse_listing_date("Wed, 01 May 2024 10:30:00 GMT"),
+            at(MAY_FIRST)
+        );
+        assert_eq!(parse_listing_date("yesterday"), None);
+    }
+
+    #[tokio::test]
+    async fn test_crawl_listing_with_timestamping() {
+        let mock_server = MockServer::start().await;
+
+        let listing = r#"[
+            { "name":"docs", "type":"directory", "mtime":"Wed, 01 May 2024 10:30:00 GMT" },
+            { "name":"app.bin", "type":"file", "mtime":"Tue, 30 Apr 2024 08:15:00 GMT", "size":3 },
+            { "name":"skip.log", "type":"file", "mtime":"Tue, 30 Apr 2024 08:15:00 GMT", "size":3 }
+        ]"#;
+        Mock::given(method("GET"))
+            .and(path("/pub/"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(listing, "application/json"))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/pub/docs/"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                "<pre><a href=\"../\">../</a>\n\
+                <a href=\"readme.txt\">readme.txt</a>   01-May-2024 10:30   6\n</pre>",
+                "text/html",
+            ))
+            .mount(&mock_server)
+            .await;
+        for (route, body) in [
+            ("/pub/app.bin", "new"),
+            ("/pub/skip.log", "log"),
+            ("/pub/docs/readme.txt", "Readme"),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_string(body))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let save_dir = "./test_output_listing";
+        let host_dir =
+            Path::new(save_dir).join(format!("127.0.0.1:{}", mock_server.address().port()));
+        fs::create_dir_all(host_dir.join("pub")).await.unwrap();
+
+        // The saved copy is newer than the listing shows, so it is kept
+        let app = host_dir.join("pub/app.bin");
+        fs::write(&app, "old").await.unwrap();
+
+        let entries = Crawler::new(Downloader::new())
+            .output_dir(save_dir)
+            .filter(UrlFilter::new().reject(vec!["*.log".to_string()]))
+            .timestamping(true)
+            .crawl_listing(&[format!("{}/pub", mock_server.uri())])
+            .await;
+
+        let urls: Vec<&str> = entries
+            .iter()
+            .map(|entry| entry.url.trim_start_matches(&mock_server.uri()))
+            .collect();
+        assert_eq!(urls, ["/pub/docs/readme.txt"]);
+        assert!(entries[0].result.is_ok());
+
+        assert_eq!(fs::read_to_string(&app).await.unwrap(), "old");
+        let readme = host_dir.join("pub/docs/readme.txt");
+        assert_eq!(fs::read_to_string(&readme).await.unwrap(), "Readme");
+        let modified = fs::metadata(&readme).await.unwrap().modified().unwrap();
+        assert_eq!(Some(modified), at(MAY_FIRST));
+        assert!(!host_dir.join("pub/skip.log").exists());
+        assert!(!host_dir.join("pub/index.html").exists());
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+#[cfg(test)]
+mod downloader_tests {
+    use nget::downloader::parse_header;
+    use nget::error::NgetError;
+    use nget::Downloader;
+
+    use reqwest::header::{HeaderName, HeaderValue};
+    use reqwest::StatusCode;
+
+    use std::path::Path;
+    use std::time::Duration;
+
+    use tokio::fs;
+
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_download_outcome() {
+        let mock_server = MockServer::start().await;
+
+        let mock_response = ResponseTemplate::new(200)
+            .set_body_string("Outcome content")
+            .insert_header("ETag", "\"v1\"");
+        Mock::given(method("GET"))
+            .and(path("/outcome.txt"))
+            .respond_with(mock_response)
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/outcome.txt", &mock_server.uri());
+        let save_dir = "./test_output_outcome";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        let outcome = Downloader::new()
+            .request(&url)
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.path, Path::new(save_dir).join("outcome.txt"));
+        assert_eq!(outcome.bytes, 15);
+        assert_eq!(outcome.status, StatusCode::OK);
+        assert_eq!(outcome.etag.as_deref(), Some("\"v1\""));
+
+        let content = fs::read_to_string(&outcome.path).await.unwrap();
+        assert_eq!(content, "Outcome content");
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_output_path_and_headers() {
+        let mock_server = MockServer::start().await;
+
+        // Only answer requests carrying both the shared and the per-request header
+        Mock::given(method("GET"))
+            .and(path("/headers"))
+            .and(header("Authorization", "Bearer token"))
+            .and(header("X-Request", "one"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("With headers"))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/headers", &mock_server.uri());
+        let save_dir = "./test_output_builder_headers";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        let (name, value) = parse_header("Authorization: Bearer token").unwrap();
+        let outcome = Downloader::new()
+            .header(name, value)
+            .request(&url)
+            .header(
+                HeaderName::from_static("x-request"),
+                HeaderValue::from_static("one"),
+            )
+            .output_path(Path::new(save_dir).join("renamed.txt"))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.path, Path::new(save_dir).join("renamed.txt"));
+        let content = fs::read_to_string(&outcome.path).await.unwrap();
+        assert_eq!(content, "With headers");
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_retries() {
+        let mock_server = MockServer::start().await;
+
+        // Fail twice, then succeed
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Eventually"))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/flaky", &mock_server.uri());
+        let save_dir = "./test_output_retries";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        // Without retries the first failure is returned
+        let result = Downloader::new()
+            .request(&url)
+            .output_dir(save_dir)
+            .send()
+            .await;
+        assert!(matches!(result, Err(NgetError::HttpRequest(_))));
+
+        let outcome = Downloader::new()
+            .retries(2)
+            .retry_delay(Duration::from_millis(10))
+            .request(&url)
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.status, StatusCode::OK);
+        let content = fs::read_to_string(&outcome.path).await.unwrap();
+        assert_eq!(content, "Eventually");
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_connect_timeout() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/slow"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("Too late")
+                    .set_delay(Duration::from_secs(5)),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/slow", &mock_server.uri());
+
+        let result = Downloader::new()
+            .connect_timeout(Duration::from_millis(200))
+            .request(&url)
+            .output_dir("./test_output_connect_timeout")
+            .send()
+            .await;
+
+        assert!(matches!(result, Err(NgetError::ConnectionTimeout(_))));
+    }
+
+    #[tokio::test]
+    async fn test_parse_header() {
+        let (name, value) = parse_header("X-Custom:  spaced value ").unwrap();
+        assert_eq!(name.as_str(), "x-custom");
+        assert_eq!(value, "spaced value");
+
+        assert!(matches!(
+            parse_header("no separator"),
+            Err(NgetError::ConfigurationError(_))
+        ));
+        assert!(matches!(
+            parse_header("Bad Name: value"),
+            Err(NgetError::ConfigurationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_url() {
+        let request = Downloader::new().request("notaurl");
+        assert!(matches!(request.path(), Err(NgetError::InvalidUrl(_))));
+        assert!(matches!(
+            request.send().await,
+            Err(NgetError::InvalidUrl(_))
+        ));
+
+        // The command line reports it as a failed download
+        let status = std::process::Command::new(env!("CARGO_BIN_EXE_nget"))
+            .args(["-u", "notaurl", "-P", "./test_output_invalid_url"])
+            .status()
+            .unwrap();
+        assert_eq!(status.code(), Some(1));
+        let _ = fs::remove_dir_all("./test_output_invalid_url").await;
+    }
+}
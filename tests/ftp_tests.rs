@@ -0,0 +1,620 @@
+mod common;
+
+#[cfg(test)]
+mod ftp_tests {
+    use crate::common::{generate_pki, RecordingObserver, TestPki};
+
+    use nget::crawler::Crawler;
+    use nget::enums::{Compression, HttpVersion};
+    use nget::ftp::{expand_glob, has_wildcards, is_ftp_url, parse_list, parse_mdtm, parse_mlsd};
+    use nget::http::download_file;
+    use nget::progress::SilentObserver;
+    use nget::proxy_utils::ProxyConfig;
+    use nget::tls_utils::TlsConfig;
+    use nget::{DownloadEvent, Downloader};
+
+    use reqwest::StatusCode;
+
+    use std::net::SocketAddr;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use tokio::fs;
+    use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::rustls::pki_types::PrivatePkcs8KeyDer;
+    use tokio_rustls::rustls::{self, ServerConfig};
+    use tokio_rustls::TlsAcceptor;
+
+    /// 2024-03-01 12:00:00 UTC, the time the test server gives every file.
+    const MODIFIED: u64 = 1709294400;
+
+    const FILES: &[(&str, &str)] = &[
+        ("pub/app.bin", "FTP content"),
+        ("pub/notes.txt", "Notes"),
+        ("pub/docs/readme.txt", "Readme"),
+        ("pub/docs/guide.txt", "Guide"),
+    ];
+
+    fn at(seconds: u64) -> Option<SystemTime> {
+        Some(UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+    impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+    #[derive(Clone, Default)]
+    struct Options {
+        /// Lists directories with MLSD rather than LIST
+        mlsd: bool,
+        /// Supports EPSV, otherwise clients have to fall back to PASV
+        epsv: bool,
+        /// Supports AUTH TLS
+        tls: Option<TlsAcceptor>,
+    }
+
+    /// A minimal FTP server for the files in `FILES`, recording the commands it receives.
+    struct FtpServer {
+        addr: SocketAddr,
+        commands: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl FtpServer {
+        fn url(&self, path: &str) -> String {
+            format!("ftp://127.0.0.1:{}/{}", self.addr.port(), path)
+        }
+
+        fn commands(&self) -> Vec<String> {
+            self.commands.lock().unwrap().clone()
+        }
+
+        fn count(&self, verb: &str) -> usize {
+            self.commands()
+                .iter()
+                .filter(|command| command.starts_with(verb))
+                .count()
+        }
+    }
+
+    /// Where the next transfer's data connection comes from.
+    enum Data {
+        Passive(TcpListener),
+        Active(SocketAddr),
+    }
+
+    async fn start_ftp_server(options: Options) -> FtpServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let commands = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = commands.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, options.clone(), recorded.clone()));
+            }
+        });
+
+        FtpServer { addr, commands }
+    }
+
+    fn tls_acceptor(pki: &TestPki) -> TlsAcceptor {
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![pki.server_cert.clone(), pki.ca_der.clone()],
+                PrivatePkcs8KeyDer::from(pki.server_key.clone()).into(),
+            )
+            .unwrap();
+        TlsAcceptor::from(Arc::new(config))
+    }
+
+    async fn reply(control: &mut BufReader<Box<dyn Stream>>, line: &str) {
+        let stream = control.get_mut();
+        let _ = stream.write_all(format!("{}\r\n", line).as_bytes()).await;
+        let _ = stream.flush().await;
+    }
+
+    /// Opens the data connection set up by the last EPSV, PASV, PORT or EPRT.
+    async fn open_data(
+        data: Option<Data>,
+        protected: bool,
+        options: &Options,
+    ) -> Option<Box<dyn Stream>> {
+        let stream = match data? {
+            Data::Passive(listener) => listener.accept().await.ok()?.0,
+            Data::Active(addr) => TcpStream::connect(addr).await.ok()?,
+        };
+        match (&options.tls, protected) {
+            (Some(acceptor), true) => Some(Box::new(acceptor.accept(stream).await.ok()?)),
+            _ => Some(Box::new(stream)),
+        }
+    }
+
+    /// Lists the files and directories directly inside `directory`.
+    fn listing(directory: &str, mlsd: bool) -> String {
+        let prefix = match directory.trim_end_matches('/') {
+            "" => String::new(),
+            directory => format!("{}/", directory),
+        };
+        let mut names: Vec<(String, Option<usize>)> = Vec::new();
+        for (path, content) in FILES {
+            let Some(rest) = path.strip_prefix(&prefix) else {
+                continue;
+            };
+            let entry = match rest.split_once('/') {
+                Some((dir, _)) => (dir.to_string(), None),
+                None => (rest.to_string(), Some(content.len())),
+            };
+            if !names.contains(&entry) {
+                names.push(entry);
+            }
+        }
+
+        let mut lines = match mlsd {
+            true => vec!["type=cdir;modify=20240301120000; .".to_string()],
+            false => vec!["total 4".to_string()],
+        };
+        for (name, size) in names {
+            lines.push(match (mlsd, size) {
+                (true, Some(size)) => {
+                    format!("type=file;size={};modify=20240301120000; {}", size, name)
+                }
+                (true, None) => format!("type=dir;modify=20240301120000; {}", name),
+                (false, Some(size)) => {
+                    format!("-rw-r--r--   1 ftp ftp {:>8} Mar 01  2024 {}", size, name)
+                }
+                (false, None) => format!("drwxr-xr-x   2 ftp ftp     4096 Mar 01  2024 {}", name),
+            });
+        }
+        lines.join("\r\n") + "\r\n"
+    }
+
+    async fn serve(stream: TcpStream, options: Options, commands: Arc<Mutex<Vec<String>>>) {
+        let mut control: BufReader<Box<dyn Stream>> = BufReader::new(Box::new(stream));
+        let mut data = None;
+        let mut rest = 0;
+        let mut protected = false;
+        reply(&mut control, "220 nget test server").await;
+
+        loop {
+            let mut line = String::new();
+            if control.read_line(&mut line).await.unwrap_or(0) == 0 {
+                return;
+            }
+            let line = line.trim_end().to_string();
+            commands.lock().unwrap().push(line.clone());
+            let (verb, argument) = line.split_once(' ').unwrap_or((&line, ""));
+            let file = FILES.iter().find(|(path, _)| *path == argument);
+
+            let response = match verb {
+                "AUTH" => match &options.tls {
+                    Some(acceptor) => {
+                        reply(&mut control, "234 Proceed with negotiation").await;
+                        let Ok(stream) = acceptor.accept(control.into_inner()).await else {
+                            return;
+                        };
+                        control = BufReader::new(Box::new(stream));
+                        continue;
+                    }
+                    None => "502 TLS not supported".to_string(),
+                },
+                "PROT" => {
+                    protected = argument == "P";
+                    "200 Protection level set".to_string()
+                }
+                "USER" => "331 Password required".to_string(),
+                "PASS" => "230 Logged in".to_string(),
+                "FEAT" => match options.mlsd {
+                    true => "211-Features:\r\n MLST type*;size*;modify*;\r\n SIZE\r\n211 End",
+                    false => "211-Features:\r\n SIZE\r\n211 End",
+                }
+                .to_string(),
+                "TYPE" | "PBSZ" => "200 OK".to_string(),
+                "SIZE" => match file {
+                    Some((_, content)) => format!("213 {}", content.len()),
+                    None => "550 No such file".to_string(),
+                },
+                "MDTM" => match file {
+                    Some(_) => "213 20240301120000".to_string(),
+                    None => "550 No such file".to_string(),
+                },
+                "REST" => {
+                    rest = argument.parse().unwrap();
+                    format!("350 Restarting at {}", rest)
+                }
+                "EPSV" | "PASV" if verb == "PASV" || options.epsv => {
+                    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                    let port = listener.local_addr().unwrap().port();
+                    data = Some(Data::Passive(listener));
+                    match verb {
+                        "EPSV" => format!("229 Entering Extended Passive Mode (|||{}|)", port),
+                        _ => format!(
+                            "227 Entering Passive Mode (127,0,0,1,{},{})",
+                            port >> 8,
+                            port & 0xff
+                        ),
+                    }
+                }
+                "PORT" => {
+                    let numbers: Vec<u16> =
+                        argument.split(',').map(|n| n.parse().unwrap()).collect();
+                    let port = numbers[4] * 256 + numbers[5];
+                    data = Some(Data::Active(SocketAddr::from(([127, 0, 0, 1], port))));
+                    "200 PORT command successful".to_string()
+                }
+                "RETR" | "LIST" | "MLSD" => {
+                    let content = match (verb, file) {
+                        ("RETR", Some((_, content))) => content[rest..].to_string(),
+                        ("RETR", None) => {
+                            reply(&mut control, "550 No such file").await;
+                            continue;
+                        }
+                        _ => listing(argument, verb == "MLSD"),
+                    };
+                    rest = 0;
+
+                    reply(&mut control, "150 Opening data connection").await;
+                    let Some(mut stream) = open_data(data.take(), protected, &options).await else {
+                        reply(&mut control, "425 Can't open data connection").await;
+                        continue;
+                    };
+                    let _ = stream.write_all(content.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                    drop(stream);
+                    "226 Transfer complete".to_string()
+                }
+                "QUIT" => {
+                    reply(&mut control, "221 Goodbye").await;
+                    return;
+                }
+                _ => "502 Command not implemented".to_string(),
+            };
+            reply(&mut control, &response).await;
+        }
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let files = parse_list(
+            "total 8\r\n\
+            drwxr-xr-x   2 ftp ftp      4096 Mar 01  2024 docs\r\n\
+            -rw-r--r--   1 ftp          1234 Mar 01  2024 app 1.0.bin\r\n\
+            lrwxrwxrwx   1 ftp ftp         7 Mar 01  2024 latest -> app.bin\r\n\
+            -rw-r--r--   1 ftp ftp        10 Jan 05 09:30 recent.txt\r\n\
+            03-01-24  12:00PM       <DIR>          old\r\n\
+            03-01-24  12:00PM                  42 setup.exe\r\n",
+        );
+
+        let names: Vec<&str> = files.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "docs",
+                "app 1.0.bin",
+                "latest",
+                "recent.txt",
+                "old",
+                "setup.exe"
+            ]
+        );
+        assert!(files[0].is_dir);
+        assert_eq!(files[0].modified, at(MODIFIED - 12 * 3600));
+
+        // The group may be missing
+        assert!(!files[1].is_dir);
+        assert_eq!(files[1].size, Some(1234));
+
+        // Times without a year are from the last year
+        let recent = files[3].modified.unwrap();
+        assert!(recent <= SystemTime::now() + Duration::from_secs(86400));
+        assert!(recent > SystemTime::now() - Duration::from_secs(366 * 86400));
+
+        // DOS listings from IIS
+        assert!(files[4].is_dir);
+        assert_eq!(files[5].size, Some(42));
+        assert_eq!(files[5].modified, at(MODIFIED));
+    }
+
+    #[test]
+    fn test_parse_mlsd() {
+        let files = parse_mlsd(
+            "type=cdir;modify=20240301120000; .\r\n\
+            type=pdir;modify=20240301120000; ..\r\n\
+            type=dir;modify=20240301120000; docs\r\n\
+            Type=file;Size=11;Modify=20240301120000.123; app 1.0.bin\r\n",
+        );
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].name, "docs");
+        assert!(files[0].is_dir);
+        assert_eq!(files[1].name, "app 1.0.bin");
+        assert_eq!(files[1].size, Some(11));
+        assert_eq!(files[1].modified, at(MODIFIED));
+
+        assert_eq!(parse_mdtm("20240301120000"), at(MODIFIED));
+        assert_eq!(parse_mdtm("2024-03-01"), None);
+    }
+
+    #[test]
+    fn test_ftp_urls() {
+        assert!(is_ftp_url("ftp://example.com/file"));
+        assert!(is_ftp_url("FTPS://example.com/file"));
+        assert!(!is_ftp_url("https://example.com/file"));
+
+        assert!(has_wildcards("ftp://example.com/pub/*.tar.gz"));
+        assert!(has_wildcards("ftp://example.com/pub/file?.txt"));
+        assert!(!has_wildcards("ftp://[::1]"));
+        assert!(!has_wildcards("https://example.com/*.tar.gz"));
+    }
+
+    #[tokio::test]
+    async fn test_passive_download() {
+        // EPSV, then PASV for servers without it
+        for epsv in [true, false] {
+            let server = start_ftp_server(Options {
+                epsv,
+                ..Default::default()
+            })
+            .await;
+
+            let save_dir = format!("./test_output_ftp_passive_{}", epsv);
+            fs::create_dir_all(&save_dir).await.unwrap();
+            download_file(
+                &server.url("pub/app.bin"),
+                &save_dir,
+                &None,
+                Arc::new(SilentObserver),
+                &HttpVersion::Auto,
+                &ProxyConfig::default(),
+                &TlsConfig::default(),
+                &Compression::Auto,
+            )
+            .await
+            .unwrap();
+
+            let path = Path::new(&save_dir).join("app.bin");
+            assert_eq!(fs::read_to_string(&path).await.unwrap(), "FTP content");
+
+            // The saved file has the MDTM time
+            let modified = fs::metadata(&path).await.unwrap().modified().unwrap();
+            assert_eq!(Some(modified), at(MODIFIED));
+
+            let commands = server.commands();
+            assert!(commands.contains(&"USER anonymous".to_string()));
+            assert!(commands.contains(&"SIZE pub/app.bin".to_string()));
+            assert!(commands.contains(&"RETR pub/app.bin".to_string()));
+            assert_eq!(server.count("PASV"), usize::from(!epsv));
+
+            fs::remove_dir_all(&save_dir).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_active_download() {
+        let server = start_ftp_server(Options::default()).await;
+
+        let save_dir = "./test_output_ftp_active";
+        fs::create_dir_all(save_dir).await.unwrap();
+        let outcome = Downloader::new()
+            .ftp_active(true)
+            .request(&server.url("pub/notes.txt"))
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.bytes, 5);
+        assert_eq!(outcome.status, StatusCode::OK);
+        assert_eq!(fs::read_to_string(&outcome.path).await.unwrap(), "Notes");
+        assert_eq!(server.count("PORT 127,0,0,1,"), 1);
+        assert_eq!(server.count("EPSV"), 0);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_with_rest() {
+        let server = start_ftp_server(Options {
+            epsv: true,
+            ..Default::default()
+        })
+        .await;
+
+        let save_dir = "./test_output_ftp_resume";
+        fs::create_dir_all(save_dir).await.unwrap();
+        fs::write(Path::new(save_dir).join("app.bin"), "FTP ")
+            .await
+            .unwrap();
+
+        let observer = Arc::new(RecordingObserver::default());
+        let outcome = Downloader::new()
+            .request(&server.url("pub/app.bin"))
+            .output_dir(save_dir)
+            .observer(observer.clone())
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(outcome.bytes, 11);
+        assert_eq!(outcome.received, 7);
+        assert_eq!(
+            fs::read_to_string(&outcome.path).await.unwrap(),
+            "FTP content"
+        );
+        assert!(server.commands().contains(&"REST 4".to_string()));
+        assert!(observer.events().iter().any(|event| matches!(
+            event,
+            DownloadEvent::HeadersReceived {
+                content_length: Some(7),
+                resume_from: 4,
+                ..
+            }
+        )));
+
+        // A complete file isn't transferred again
+        let outcome = Downloader::new()
+            .request(&server.url("pub/app.bin"))
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(outcome.status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(outcome.bytes, 11);
+        assert_eq!(server.count("RETR"), 1);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_missing_file() {
+        let server = start_ftp_server(Options::default()).await;
+
+        let save_dir = "./test_output_ftp_missing";
+        let error = Downloader::new()
+            .request(&server.url("pub/missing.bin"))
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.kind(), "InvalidUrl");
+        assert!(error.to_string().contains("550"));
+
+        // Credentials can't smuggle in another command
+        for credentials in ["a%0D%0ADELE%20x", "a:b%0ADELE%20x"] {
+            let url = server
+                .url("pub/file.txt")
+                .replace("//", &format!("//{}@", credentials));
+            let error = Downloader::new()
+                .request(&url)
+                .output_dir(save_dir)
+                .send()
+                .await
+                .unwrap_err();
+            assert_eq!(error.kind(), "InvalidUrl", "{}", url);
+        }
+        assert_eq!(server.count("DELE"), 0);
+        assert_eq!(server.count("USER"), 1);
+
+        let _ = fs::remove_dir_all(save_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_ftps_download() {
+        let pki = generate_pki();
+        let server = start_ftp_server(Options {
+            epsv: true,
+            tls: Some(tls_acceptor(&pki)),
+            ..Default::default()
+        })
+        .await;
+        let url = format!("ftps://localhost:{}/pub/app.bin", server.addr.port());
+
+        let save_dir = "./test_output_ftps";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        // The server's certificate isn't trusted without the test CA
+        let error = Downloader::new()
+            .request(&url)
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), "TlsError");
+
+        let ca_file = Path::new(save_dir).join("ca.pem");
+        fs::write(&ca_file, &pki.ca_pem).await.unwrap();
+        let outcome = Downloader::new()
+            .tls(TlsConfig {
+                ca_certificate: Some(ca_file.display().to_string()),
+                ..Default::default()
+            })
+            .request(&url)
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&outcome.path).await.unwrap(),
+            "FTP content"
+        );
+        let commands = server.commands();
+        for command in ["AUTH TLS", "PBSZ 0", "PROT P", "RETR pub/app.bin"] {
+            assert!(commands.contains(&command.to_string()), "{}", command);
+        }
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expand_glob() {
+        let server = start_ftp_server(Options {
+            epsv: true,
+            ..Default::default()
+        })
+        .await;
+
+        let urls = expand_glob(&Downloader::new(), &server.url("pub/*.txt"))
+            .await
+            .unwrap();
+        assert_eq!(urls, [server.url("pub/notes.txt")]);
+
+        let urls = expand_glob(&Downloader::new(), &server.url("pub/[an]*"))
+            .await
+            .unwrap();
+        assert_eq!(
+            urls,
+            [server.url("pub/app.bin"), server.url("pub/notes.txt")]
+        );
+
+        let error = expand_glob(&Downloader::new(), &server.url("pub/*.iso"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), "InvalidUrl");
+    }
+
+    #[tokio::test]
+    async fn test_crawl_ftp_tree() {
+        // Servers that list with MLSD, and older ones with LIST only
+        for mlsd in [true, false] {
+            let server = start_ftp_server(Options {
+                mlsd,
+                epsv: true,
+                ..Default::default()
+            })
+            .await;
+
+            let save_dir = format!("./test_output_ftp_tree_{}", mlsd);
+            let crawler = Crawler::new(Downloader::new())
+                .output_dir(&save_dir)
+                .timestamping(true);
+            let entries = crawler.crawl_listing(&[server.url("pub")]).await;
+
+            assert_eq!(entries.len(), 4);
+            assert!(entries.iter().all(|entry| entry.result.is_ok()));
+            assert_eq!(server.count(if mlsd { "MLSD" } else { "LIST" }), 2);
+
+            let host_dir = Path::new(&save_dir).join(format!("127.0.0.1:{}", server.addr.port()));
+            for (path, content) in FILES {
+                assert_eq!(
+                    fs::read_to_string(host_dir.join(path)).await.unwrap(),
+                    *content
+                );
+            }
+
+            // Nothing has changed since, so nothing is transferred again
+            let entries = crawler.crawl_listing(&[server.url("pub")]).await;
+            assert!(entries.is_empty());
+            assert_eq!(server.count("RETR"), 4);
+
+            fs::remove_dir_all(&save_dir).await.unwrap();
+        }
+    }
+}
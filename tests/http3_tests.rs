@@ -0,0 +1,250 @@
+mod common;
+
+#[cfg(test)]
+mod http3_tests {
+    use crate::common::{
+        generate_pki, start_https_server, start_https_server_with_headers, TestPki,
+    };
+
+    use bytes::Bytes;
+
+    use nget::enums::{Compression, HttpVersion};
+    use nget::error::NgetError;
+    use nget::http::download_file;
+    use nget::http3::{cached_alt_svc, parse_alt_svc, AltSvc};
+    use nget::progress::SilentObserver;
+    use nget::proxy_utils::ProxyConfig;
+    use nget::tls_utils::TlsConfig;
+    use nget::url_utils::parse_url;
+
+    use quinn::crypto::rustls::QuicServerConfig;
+
+    use std::net::SocketAddr;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use tokio_rustls::rustls::pki_types::PrivatePkcs8KeyDer;
+    use tokio_rustls::rustls::{self, ServerConfig};
+
+    /// Starts an HTTP/3 server on 127.0.0.1 that answers every request with `body`.
+    fn start_h3_server(pki: &TestPki, body: &'static str) -> SocketAddr {
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        let mut tls = ServerConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![pki.server_cert.clone(), pki.ca_der.clone()],
+                PrivatePkcs8KeyDer::from(pki.server_key.clone()).into(),
+            )
+            .unwrap();
+        tls.alpn_protocols = vec![b"h3".to_vec()];
+
+        let server_config =
+            quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls).unwrap()));
+        let endpoint =
+            quinn::Endpoint::server(server_config, "127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = endpoint.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Some(incoming) = endpoint.accept().await {
+                tokio::spawn(async move {
+                    let Ok(connection) = incoming.await else {
+                        return;
+                    };
+                    let Ok(mut h3_conn) = h3::server::Connection::<_, Bytes>::new(
+                        h3_quinn::Connection::new(connection),
+                    )
+                    .await
+                    else {
+                        return;
+                    };
+
+                    while let Ok(Some(resolver)) = h3_conn.accept().await {
+                        let Ok((_request, mut stream)) = resolver.resolve_request().await else {
+                            continue;
+                        };
+
+                        let response = http::Response::builder()
+                            .status(200)
+                            .header("content-length", body.len())
+                            .body(())
+                            .unwrap();
+                        let _ = stream.send_response(response).await;
+                        let _ = stream.send_data(Bytes::from_static(body.as_bytes())).await;
+                        let _ = stream.finish().await;
+                    }
+                });
+            }
+        });
+
+        addr
+    }
+
+    async fn download(url: &str, save_dir: &str, ca_file: &Path) -> Result<(), NgetError> {
+        let output_file_name = None;
+        let tls_config = TlsConfig {
+            ca_certificate: Some(ca_file.display().to_string()),
+            ..Default::default()
+        };
+
+        download_file(
+            url,
+            save_dir,
+            &output_file_name,
+            Arc::new(SilentObserver),
+            &HttpVersion::Http3,
+            &ProxyConfig::empty(),
+            &tls_config,
+            &Compression::Auto,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_http3_direct_download() {
+        let pki = generate_pki();
+        let addr = start_h3_server(&pki, "HTTP/3 content");
+
+        let save_dir = "./test_output_http3_direct";
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+        let ca_file = Path::new(save_dir).join("ca.pem");
+        tokio::fs::write(&ca_file, &pki.ca_pem).await.unwrap();
+
+        // Nothing listens on the TCP port, so there is no Alt-Svc to discover
+        let url = format!("https://127.0.0.1:{}/h3file", addr.port());
+        let result = download(&url, save_dir, &ca_file).await;
+        assert!(result.is_ok(), "HTTP/3 request failed: {:?}", result.err());
+
+        let content = tokio::fs::read_to_string(Path::new(save_dir).join("h3file"))
+            .await
+            .unwrap();
+        assert_eq!(content, "HTTP/3 content");
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_http3_alt_svc_upgrade() {
+        let pki = generate_pki();
+        let h3_addr = start_h3_server(&pki, "HTTP/3 content");
+        let alt_svc = format!("Alt-Svc: h3=\":{}\"; ma=60\r\n", h3_addr.port());
+        let tcp_addr = start_https_server_with_headers(&pki, "TCP content", false, alt_svc).await;
+
+        let save_dir = "./test_output_http3_alt_svc";
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+        let ca_file = Path::new(save_dir).join("ca.pem");
+        tokio::fs::write(&ca_file, &pki.ca_pem).await.unwrap();
+
+        let url = format!("https://localhost:{}/altfile", tcp_addr.port());
+        let result = download(&url, save_dir, &ca_file).await;
+        assert!(result.is_ok(), "HTTP/3 request failed: {:?}", result.err());
+
+        // The body came from the advertised HTTP/3 endpoint, not the TCP server
+        let content = tokio::fs::read_to_string(Path::new(save_dir).join("altfile"))
+            .await
+            .unwrap();
+        assert_eq!(content, "HTTP/3 content");
+        assert_eq!(
+            cached_alt_svc(&parse_url(&url).unwrap()),
+            Some(AltSvc {
+                host: None,
+                port: h3_addr.port(),
+                max_age: 60,
+            })
+        );
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_alt_svc_ignored_without_http3() {
+        let pki = generate_pki();
+        let alt_svc = "Alt-Svc: h3=\":443\"; ma=60\r\n".to_string();
+        let tcp_addr = start_https_server_with_headers(&pki, "TCP content", false, alt_svc).await;
+
+        let save_dir = "./test_output_alt_svc_auto";
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+        let ca_file = Path::new(save_dir).join("ca.pem");
+        tokio::fs::write(&ca_file, &pki.ca_pem).await.unwrap();
+
+        let url = format!("https://localhost:{}/autofile", tcp_addr.port());
+        let tls_config = TlsConfig {
+            ca_certificate: Some(ca_file.display().to_string()),
+            ..Default::default()
+        };
+        let result = download_file(
+            &url,
+            save_dir,
+            &None,
+            Arc::new(SilentObserver),
+            &HttpVersion::Auto,
+            &ProxyConfig::empty(),
+            &tls_config,
+            &Compression::Auto,
+        )
+        .await;
+        assert!(result.is_ok(), "Request failed: {:?}", result.err());
+
+        // Without an explicit HTTP/3 request the advertised alternative is never used
+        assert_eq!(cached_alt_svc(&parse_url(&url).unwrap()), None);
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_http3_unsupported_server() {
+        let pki = generate_pki();
+        let tcp_addr = start_https_server(&pki, "TCP content", false).await;
+
+        let save_dir = "./test_output_http3_unsupported";
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+        let ca_file = Path::new(save_dir).join("ca.pem");
+        tokio::fs::write(&ca_file, &pki.ca_pem).await.unwrap();
+
+        // The server only speaks HTTP/1.1 over TCP and advertises no alternative
+        let url = format!("https://127.0.0.1:{}/tcpfile", tcp_addr.port());
+        let result = download(&url, save_dir, &ca_file).await;
+        assert!(
+            matches!(result, Err(NgetError::UnsupportedHTTPVersion(_))),
+            "Expected an unsupported version error, got {:?}",
+            result
+        );
+        assert!(!Path::new(save_dir).join("tcpfile").exists());
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_http3_requires_https() {
+        let result = download(
+            "http://127.0.0.1:9/file",
+            "./test_output_http3_plain",
+            Path::new("./ca.pem"),
+        )
+        .await;
+        assert!(matches!(result, Err(NgetError::UnsupportedHTTPVersion(_))));
+    }
+
+    #[test]
+    fn test_parse_alt_svc() {
+        assert_eq!(
+            parse_alt_svc("h3=\":443\"; ma=3600"),
+            Some(AltSvc {
+                host: None,
+                port: 443,
+                max_age: 3600,
+            })
+        );
+        assert_eq!(
+            parse_alt_svc("h3-29=\":443\", h3=\"alt.example.com:8443\""),
+            Some(AltSvc {
+                host: Some("alt.example.com".to_string()),
+                port: 8443,
+                max_age: 86400,
+            })
+        );
+        assert_eq!(parse_alt_svc("h2=\":443\""), None);
+        assert_eq!(parse_alt_svc("clear"), None);
+    }
+}
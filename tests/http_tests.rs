@@ -1,16 +1,17 @@
 #[cfg(test)]
 mod tests {
-    use indicatif::ProgressBar;
-
-    use nget::enums::HttpVersion;
+    use nget::enums::{Compression, HttpVersion};
     use nget::http::download_file;
+    use nget::progress::SilentObserver;
     use nget::proxy_utils::ProxyConfig;
+    use nget::tls_utils::TlsConfig;
 
     use std::path::Path;
+    use std::sync::Arc;
 
     use tokio::fs;
 
-    use wiremock::matchers::{method, path};
+    use wiremock::matchers::{header, method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[tokio::test]
@@ -28,7 +29,6 @@ mod tests {
         let url = format!("{}/testfile", &mock_server.uri());
         let save_dir = "./test_output";
         let output_file_name = None;
-        let progress_bar = ProgressBar::hidden(); // Hidden to prevent output during testing
 
         // Ensure the directory exists
         fs::create_dir_all(save_dir).await.unwrap();
@@ -39,9 +39,11 @@ mod tests {
             &url,
             save_dir,
             &output_file_name,
-            &progress_bar,
+            Arc::new(SilentObserver),
             &HttpVersion::Http11,
             &config,
+            &TlsConfig::empty(),
+            &Compression::Auto,
         )
         .await;
 
@@ -63,7 +65,6 @@ mod tests {
         let invalid_url = "http://nonexistent.url.invalid";
         let save_dir = "./test_output";
         let output_file_name = None;
-        let progress_bar = ProgressBar::hidden();
 
         let config = ProxyConfig::empty();
 
@@ -71,9 +72,11 @@ mod tests {
             invalid_url,
             save_dir,
             &output_file_name,
-            &progress_bar,
+            Arc::new(SilentObserver),
             &HttpVersion::Http11,
             &config,
+            &TlsConfig::empty(),
+            &Compression::Auto,
         )
         .await;
 
@@ -82,10 +85,10 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_http2_download_success() {
+    async fn test_h2c_download_success() {
         let mock_server = MockServer::start().await;
 
-        // Mock a successful HTTP/2 response
+        // Mock a successful HTTP/2 response over cleartext (prior knowledge)
         let mock_response = ResponseTemplate::new(200).set_body_string("HTTP/2 file content");
         Mock::given(method("GET"))
             .and(path("/http2file"))
@@ -96,7 +99,6 @@ mod tests {
         let url = format!("{}/http2file", &mock_server.uri());
         let save_dir = "./test_output";
         let output_file_name = None;
-        let progress_bar = ProgressBar::hidden();
 
         let config = ProxyConfig::empty();
 
@@ -107,9 +109,11 @@ mod tests {
             &url,
             save_dir,
             &output_file_name,
-            &progress_bar,
-            &HttpVersion::Http2,
+            Arc::new(SilentObserver),
+            &HttpVersion::H2c,
             &config,
+            &TlsConfig::empty(),
+            &Compression::Auto,
         )
         .await;
 
@@ -127,7 +131,7 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_http2_download_unsupported_version() {
+    async fn test_h2c_download_unsupported_version() {
         let mock_server = MockServer::start().await;
 
         // Mock an unsupported HTTP version response
@@ -141,7 +145,6 @@ mod tests {
         let url = format!("{}/unsupported", &mock_server.uri());
         let save_dir = "./test_output";
         let output_file_name = None;
-        let progress_bar = ProgressBar::hidden();
 
         let config = ProxyConfig::empty();
 
@@ -149,9 +152,11 @@ mod tests {
             &url,
             save_dir,
             &output_file_name,
-            &progress_bar,
-            &HttpVersion::Http2,
+            Arc::new(SilentObserver),
+            &HttpVersion::H2c,
             &config,
+            &TlsConfig::empty(),
+            &Compression::Auto,
         )
         .await;
 
@@ -176,7 +181,6 @@ mod tests {
         let url = format!("{}/partial", &mock_server.uri());
         let save_dir = "./test_output";
         let output_file_name = None;
-        let progress_bar = ProgressBar::hidden();
 
         let config = ProxyConfig::empty();
 
@@ -186,9 +190,11 @@ mod tests {
             &url,
             save_dir,
             &output_file_name,
-            &progress_bar,
+            Arc::new(SilentObserver),
             &HttpVersion::Http11,
             &config,
+            &TlsConfig::empty(),
+            &Compression::Auto,
         )
         .await;
 
@@ -204,4 +210,126 @@ mod tests {
         fs::remove_file(saved_file_path).await.unwrap();
         fs::remove_dir_all(save_dir).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_h2c_resume_sends_range() {
+        let mock_server = MockServer::start().await;
+
+        // Only answer requests asking for the rest of the file
+        let partial_response = ResponseTemplate::new(206)
+            .set_body_string(" content")
+            .insert_header("Content-Range", "bytes 7-14/15");
+        Mock::given(method("GET"))
+            .and(path("/resumed"))
+            .and(header("Range", "bytes=7-"))
+            .respond_with(partial_response)
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/resumed", &mock_server.uri());
+        let save_dir = "./test_output_h2c_resume";
+
+        fs::create_dir_all(save_dir).await.unwrap();
+        let saved_file_path = Path::new(save_dir).join("resumed");
+        fs::write(&saved_file_path, "Partial").await.unwrap();
+
+        let result = download_file(
+            &url,
+            save_dir,
+            &None,
+            Arc::new(SilentObserver),
+            &HttpVersion::H2c,
+            &ProxyConfig::empty(),
+            &TlsConfig::empty(),
+            &Compression::Auto,
+        )
+        .await;
+
+        assert!(result.is_ok(), "{:?}", result);
+
+        // The remaining bytes are appended to the partial file
+        let content = fs::read_to_string(&saved_file_path).await.unwrap();
+        assert_eq!(content, "Partial content");
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_range_ignored() {
+        let mock_server = MockServer::start().await;
+
+        // The server sends the whole file despite the Range header
+        let full_response = ResponseTemplate::new(200).set_body_string("Complete file");
+        Mock::given(method("GET"))
+            .and(path("/norange"))
+            .respond_with(full_response)
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/norange", &mock_server.uri());
+        let save_dir = "./test_output_range_ignored";
+
+        fs::create_dir_all(save_dir).await.unwrap();
+        let saved_file_path = Path::new(save_dir).join("norange");
+        fs::write(&saved_file_path, "Complete").await.unwrap();
+
+        let result = download_file(
+            &url,
+            save_dir,
+            &None,
+            Arc::new(SilentObserver),
+            &HttpVersion::Http11,
+            &ProxyConfig::empty(),
+            &TlsConfig::empty(),
+            &Compression::Auto,
+        )
+        .await;
+
+        assert!(result.is_ok(), "{:?}", result);
+
+        // The file is replaced rather than having the full body appended
+        let content = fs::read_to_string(&saved_file_path).await.unwrap();
+        assert_eq!(content, "Complete file");
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_already_complete() {
+        let mock_server = MockServer::start().await;
+
+        // Nothing is left past the end of the local file
+        Mock::given(method("GET"))
+            .and(path("/complete"))
+            .and(header("Range", "bytes=13-"))
+            .respond_with(ResponseTemplate::new(416))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/complete", &mock_server.uri());
+        let save_dir = "./test_output_already_complete";
+
+        fs::create_dir_all(save_dir).await.unwrap();
+        let saved_file_path = Path::new(save_dir).join("complete");
+        fs::write(&saved_file_path, "Complete file").await.unwrap();
+
+        let result = download_file(
+            &url,
+            save_dir,
+            &None,
+            Arc::new(SilentObserver),
+            &HttpVersion::Http11,
+            &ProxyConfig::empty(),
+            &TlsConfig::empty(),
+            &Compression::Auto,
+        )
+        .await;
+
+        assert!(result.is_ok(), "{:?}", result);
+
+        let content = fs::read_to_string(&saved_file_path).await.unwrap();
+        assert_eq!(content, "Complete file");
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
 }
@@ -0,0 +1,220 @@
+#[cfg(test)]
+mod json_output_tests {
+    use nget::error::NgetError;
+    use nget::json_output::{JsonObserver, JsonWriter};
+    use nget::Downloader;
+
+    use serde_json::Value;
+
+    use std::io::Write;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio::fs;
+
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// An output buffer shared with the test.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn lines(&self) -> Vec<Value> {
+            let buffer = self.0.lock().unwrap();
+            String::from_utf8_lossy(&buffer)
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    fn sha256_hex(content: &[u8]) -> String {
+        use sha2::{Digest, Sha256};
+        Sha256::digest(content)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_json_events_for_download() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/data.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("JSON file content"))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/data.json", &mock_server.uri());
+        let save_dir = "./test_output_json_events";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        let buffer = SharedBuffer::default();
+        let writer = JsonWriter::new(buffer.clone());
+        Downloader::new()
+            .checksum(true)
+            .request(&url)
+            .output_dir(save_dir)
+            .observer(Arc::new(JsonObserver::new(writer, &url, Duration::ZERO)))
+            .send()
+            .await
+            .unwrap();
+
+        let lines = buffer.lines();
+        let events: Vec<&str> = lines
+            .iter()
+            .map(|line| line["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(events.first(), Some(&"start"));
+        assert!(events.contains(&"progress"));
+        assert_eq!(events.last(), Some(&"complete"));
+
+        let complete = lines.last().unwrap();
+        assert_eq!(complete["url"], url);
+        assert_eq!(
+            complete["path"],
+            Path::new(save_dir).join("data.json").display().to_string()
+        );
+        assert_eq!(complete["bytes"], 17);
+        assert_eq!(complete["status"], 200);
+        assert!(complete["duration_ms"].is_u64());
+        assert_eq!(complete["sha256"], sha256_hex(b"JSON file content"));
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_json_events_for_retry_and_failure() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/gone"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/gone", &mock_server.uri());
+
+        let buffer = SharedBuffer::default();
+        let writer = JsonWriter::new(buffer.clone());
+        let result = Downloader::new()
+            .retries(1)
+            .retry_delay(Duration::from_millis(10))
+            .request(&url)
+            .output_dir("./test_output_json_failure")
+            .observer(Arc::new(JsonObserver::new(writer, &url, Duration::ZERO)))
+            .send()
+            .await;
+        assert!(matches!(result, Err(NgetError::InvalidUrl(_))));
+
+        let lines = buffer.lines();
+        let retry = lines.iter().find(|line| line["event"] == "retry").unwrap();
+        assert_eq!(retry["attempt"], 1);
+        assert_eq!(retry["kind"], "InvalidUrl");
+        assert_eq!(retry["delay_ms"], 10);
+
+        let failure = lines.last().unwrap();
+        assert_eq!(failure["event"], "failure");
+        assert_eq!(failure["kind"], "InvalidUrl");
+        assert_eq!(failure["url"], url);
+    }
+
+    #[tokio::test]
+    async fn test_json_progress_interval() {
+        let mock_server = MockServer::start().await;
+
+        // A body large enough to arrive in several chunks
+        let body = vec![b'x'; 512 * 1024];
+        Mock::given(method("GET"))
+            .and(path("/large.bin"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(body))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/large.bin", &mock_server.uri());
+        let save_dir = "./test_output_json_interval";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        let buffer = SharedBuffer::default();
+        let writer = JsonWriter::new(buffer.clone());
+        Downloader::new()
+            .request(&url)
+            .output_dir(save_dir)
+            .observer(Arc::new(JsonObserver::new(
+                writer,
+                &url,
+                Duration::from_secs(3600),
+            )))
+            .send()
+            .await
+            .unwrap();
+
+        // Only the first chunk is reported within the interval
+        let lines = buffer.lines();
+        let progress: Vec<&Value> = lines
+            .iter()
+            .filter(|line| line["event"] == "progress")
+            .collect();
+        assert_eq!(progress.len(), 1);
+        assert_eq!(progress[0]["total"], 512 * 1024);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_checksum_covers_resumed_file() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/hashed.txt"))
+            .respond_with(
+                ResponseTemplate::new(206)
+                    .set_body_string(" second")
+                    .insert_header("Content-Range", "bytes 5-11/12"),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/hashed.txt", &mock_server.uri());
+        let save_dir = "./test_output_json_checksum";
+        fs::create_dir_all(save_dir).await.unwrap();
+        fs::write(Path::new(save_dir).join("hashed.txt"), "First")
+            .await
+            .unwrap();
+
+        let outcome = Downloader::new()
+            .checksum(true)
+            .request(&url)
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.sha256, Some(sha256_hex(b"First second")));
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[test]
+    fn test_error_kind() {
+        assert_eq!(NgetError::TlsError(String::new()).kind(), "TlsError");
+        assert_eq!(
+            NgetError::Cancelled("http://example.com".to_string()).kind(),
+            "Cancelled"
+        );
+        assert_eq!(NgetError::Unknown.kind(), "Unknown");
+    }
+}
@@ -0,0 +1,148 @@
+mod common;
+
+#[cfg(test)]
+mod link_converter_tests {
+    use crate::common::mount_page;
+
+    use nget::crawler::Crawler;
+    use nget::link_converter::{backup_path, convert_links};
+    use nget::Downloader;
+
+    use std::path::Path;
+
+    use tokio::fs;
+
+    use url::Url;
+
+    use wiremock::MockServer;
+
+    #[tokio::test]
+    async fn test_convert_links() {
+        let mock_server = MockServer::start().await;
+        let uri = mock_server.uri();
+
+        mount_page(
+            &mock_server,
+            "/docs/guide.html",
+            &format!(
+                r##"<link rel="stylesheet" href="{}/static/site.css">
+                <img src="../img/logo.png">
+                <a href="guide.html#install">Install</a>
+                <a href="#top">Top</a>
+                <a href="/search?q=a&amp;page=2">Search</a>
+                <a href="mailto:docs@example.com">Mail</a>
+                <style>.hero {{ background: url(/img/hero.png?size=2&dpr=1) }}</style>"##,
+                uri
+            ),
+            "text/html",
+        )
+        .await;
+        mount_page(
+            &mock_server,
+            "/static/site.css",
+            "body { background: url(/img/bg.png) } .x { background: url(missing.png) }",
+            "text/css",
+        )
+        .await;
+        mount_page(&mock_server, "/img/logo.png", "logo", "image/png").await;
+        mount_page(&mock_server, "/img/bg.png", "bg", "image/png").await;
+
+        let save_dir = "./test_output_convert_links";
+        let entries = Crawler::new(Downloader::new())
+            .output_dir(save_dir)
+            .level(Some(0))
+            .page_requisites(true)
+            .crawl(&[format!("{}/docs/guide.html", uri)])
+            .await;
+        // Only missing.png and hero.png fail
+        let failed = entries.iter().filter(|entry| entry.result.is_err()).count();
+        assert_eq!(failed, 2);
+
+        let converted = convert_links(&entries, true).await;
+        assert_eq!(converted, 2);
+
+        let port = Url::parse(&uri).unwrap().port().unwrap();
+        let host_dir = Path::new(save_dir).join(format!("127.0.0.1:{}", port));
+        let page = host_dir.join("docs/guide.html");
+        assert_eq!(
+            fs::read_to_string(&page).await.unwrap(),
+            format!(
+                r##"<link rel="stylesheet" href="../static/site.css">
+                <img src="../img/logo.png">
+                <a href="guide.html#install">Install</a>
+                <a href="#top">Top</a>
+                <a href="{}/search?q=a&amp;page=2">Search</a>
+                <a href="mailto:docs@example.com">Mail</a>
+                <style>.hero {{ background: url({}/img/hero.png?size=2&dpr=1) }}</style>"##,
+                uri, uri
+            )
+        );
+        assert_eq!(
+            fs::read_to_string(host_dir.join("static/site.css"))
+                .await
+                .unwrap(),
+            format!(
+                "body {{ background: url(../img/bg.png) }} .x {{ background: url({}/static/missing.png) }}",
+                uri
+            )
+        );
+
+        // The originals are kept next to the converted files
+        let original = fs::read_to_string(backup_path(&page)).await.unwrap();
+        assert!(original.contains(&format!("href=\"{}/static/site.css\"", uri)));
+
+        // Converting again changes nothing
+        assert_eq!(convert_links(&entries, false).await, 0);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_convert_links_with_base() {
+        let mock_server = MockServer::start().await;
+        let uri = mock_server.uri();
+
+        mount_page(
+            &mock_server,
+            "/docs/page.html",
+            &format!(
+                r#"<base href="{}/static/"><img src="logo.png"><a href="/docs/other.html">Other</a>"#,
+                uri
+            ),
+            "text/html",
+        )
+        .await;
+        mount_page(&mock_server, "/static/logo.png", "logo", "image/png").await;
+
+        let save_dir = "./test_output_convert_links_base";
+        let entries = Crawler::new(Downloader::new())
+            .output_dir(save_dir)
+            .level(Some(0))
+            .page_requisites(true)
+            .crawl(&[format!("{}/docs/page.html", uri)])
+            .await;
+        assert_eq!(convert_links(&entries, false).await, 1);
+
+        // The base points at the page itself, so the rewritten links stay local
+        let port = Url::parse(&uri).unwrap().port().unwrap();
+        let page = Path::new(save_dir).join(format!("127.0.0.1:{}/docs/page.html", port));
+        assert_eq!(
+            fs::read_to_string(&page).await.unwrap(),
+            format!(
+                r#"<base href="page.html"><img src="../static/logo.png"><a href="{}/docs/other.html">Other</a>"#,
+                uri
+            )
+        );
+        assert_eq!(convert_links(&entries, false).await, 0);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[test]
+    fn test_backup_path() {
+        assert_eq!(
+            backup_path(Path::new("site/index.html")),
+            Path::new("site/index.html.orig")
+        );
+    }
+}
@@ -0,0 +1,256 @@
+mod common;
+
+#[cfg(test)]
+mod local_url_tests {
+    use crate::common::RecordingObserver;
+
+    use nget::enums::{Compression, HttpVersion};
+    use nget::http::download_file;
+    use nget::local_url::{is_data_url, is_file_url, parse_data_url};
+    use nget::progress::SilentObserver;
+    use nget::proxy_utils::ProxyConfig;
+    use nget::tls_utils::TlsConfig;
+    use nget::{DownloadEvent, Downloader};
+
+    use reqwest::StatusCode;
+
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use tokio::fs;
+
+    use url::Url;
+
+    fn sha256_hex(content: &[u8]) -> String {
+        use sha2::{Digest, Sha256};
+        Sha256::digest(content)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Writes a source file and returns its `file://` URL.
+    async fn source_file(dir: &str, name: &str, content: &str) -> (PathBuf, String) {
+        let dir = std::env::current_dir().unwrap().join(dir);
+        fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join(name);
+        fs::write(&path, content).await.unwrap();
+        let url = Url::from_file_path(&path).unwrap().to_string();
+        (path, url)
+    }
+
+    #[test]
+    fn test_parse_data_url() {
+        assert!(is_data_url("DATA:,hi"));
+        assert!(is_file_url("file:///tmp/a.txt"));
+        assert!(!is_file_url("https://example.com/file:"));
+
+        assert_eq!(
+            parse_data_url("data:,Hello%2C%20World%21").unwrap(),
+            (
+                "text/plain;charset=US-ASCII".to_string(),
+                b"Hello, World!".to_vec()
+            )
+        );
+        assert_eq!(
+            parse_data_url("data:text/plain;base64,SGVsbG8sIFdvcmxkIQ==").unwrap(),
+            ("text/plain".to_string(), b"Hello, World!".to_vec())
+        );
+
+        // Padding and whitespace are optional in base64, and the fragment is left out
+        assert_eq!(
+            parse_data_url("data:application/octet-stream;BASE64,AAEC%20/w#frag")
+                .unwrap()
+                .1,
+            [0, 1, 2, 255]
+        );
+        assert_eq!(
+            parse_data_url("data:;charset=utf-8,caf%C3%A9").unwrap(),
+            (
+                "text/plain;charset=utf-8".to_string(),
+                "café".as_bytes().to_vec()
+            )
+        );
+
+        for url in [
+            "data:text/plain",
+            "data:;base64,%%%",
+            "https://example.com/",
+        ] {
+            let error = parse_data_url(url).unwrap_err();
+            assert_eq!(error.kind(), "InvalidUrl", "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_data_url() {
+        let save_dir = "./test_output_data_url";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        download_file(
+            "data:text/plain;base64,SW5saW5lIGNvbnRlbnQ=",
+            save_dir,
+            &None,
+            Arc::new(SilentObserver),
+            &HttpVersion::Auto,
+            &ProxyConfig::default(),
+            &TlsConfig::default(),
+            &Compression::Auto,
+        )
+        .await
+        .unwrap();
+        let content = fs::read_to_string(Path::new(save_dir).join("data"))
+            .await
+            .unwrap();
+        assert_eq!(content, "Inline content");
+
+        let outcome = Downloader::new()
+            .checksum(true)
+            .request("data:application/json,%7B%22a%22%3A1%7D")
+            .output_dir(save_dir)
+            .file_name("inline.json")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(outcome.bytes, 7);
+        assert_eq!(outcome.content_type.as_deref(), Some("application/json"));
+        assert_eq!(outcome.sha256, Some(sha256_hex(br#"{"a":1}"#)));
+        assert_eq!(
+            fs::read_to_string(&outcome.path).await.unwrap(),
+            r#"{"a":1}"#
+        );
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_copy_file_url() {
+        let (source, url) = source_file(
+            "./test_output_file_url/source",
+            "notes.txt",
+            "Local content",
+        )
+        .await;
+        let modified = UNIX_EPOCH + Duration::from_secs(1709294400);
+        std::fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let save_dir = "./test_output_file_url";
+        let observer = Arc::new(RecordingObserver::default());
+        let outcome = Downloader::new()
+            .checksum(true)
+            .request(&url)
+            .output_dir(save_dir)
+            .observer(observer.clone())
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.path, Path::new(save_dir).join("notes.txt"));
+        assert_eq!(outcome.status, StatusCode::OK);
+        assert_eq!(outcome.bytes, 13);
+        assert_eq!(outcome.sha256, Some(sha256_hex(b"Local content")));
+        assert_eq!(
+            fs::read_to_string(&outcome.path).await.unwrap(),
+            "Local content"
+        );
+
+        // The copy keeps the source's modification time
+        let copied = fs::metadata(&outcome.path).await.unwrap();
+        assert_eq!(copied.modified().unwrap(), modified);
+
+        let events = observer.events();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            DownloadEvent::HeadersReceived {
+                content_length: Some(13),
+                resume_from: 0,
+                ..
+            }
+        )));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, DownloadEvent::Bytes { received: 13 })));
+
+        // A file can't be copied onto itself
+        let error = Downloader::new()
+            .request(&url)
+            .output_dir(source.parent().unwrap())
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), "FileError");
+        assert_eq!(fs::read_to_string(&source).await.unwrap(), "Local content");
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_file_url() {
+        let (_, url) = source_file(
+            "./test_output_file_resume/source",
+            "app.bin",
+            "Resumed copy",
+        )
+        .await;
+
+        let save_dir = "./test_output_file_resume";
+        fs::write(Path::new(save_dir).join("app.bin"), "Resumed")
+            .await
+            .unwrap();
+
+        let outcome = Downloader::new()
+            .checksum(true)
+            .request(&url)
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(outcome.status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(outcome.received, 5);
+        assert_eq!(outcome.sha256, Some(sha256_hex(b"Resumed copy")));
+        assert_eq!(
+            fs::read_to_string(&outcome.path).await.unwrap(),
+            "Resumed copy"
+        );
+
+        // A complete copy is left as it is
+        let outcome = Downloader::new()
+            .request(&url)
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(outcome.status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(outcome.bytes, 12);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_missing_file_url() {
+        let save_dir = "./test_output_file_missing";
+        let (_, url) = source_file(save_dir, "present.txt", "").await;
+
+        for url in [
+            url.replace("present.txt", "missing.txt"),
+            url.replace("present.txt", ""),
+        ] {
+            let error = Downloader::new()
+                .request(&url)
+                .output_dir(save_dir)
+                .file_name("out")
+                .send()
+                .await
+                .unwrap_err();
+            assert_eq!(error.kind(), "InvalidUrl", "{}", url);
+        }
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+}
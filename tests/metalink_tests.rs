@@ -0,0 +1,452 @@
+mod common;
+
+#[cfg(test)]
+mod metalink_tests {
+    use crate::common::RangeResponder;
+
+    use nget::metalink::{download_files, load_metalink, Metalink, MetalinkUrl, Pieces};
+    use nget::progress::SilentObserver;
+    use nget::scheduler::Scheduler;
+    use nget::{Downloader, ProgressObserver};
+
+    use reqwest::StatusCode;
+
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use tokio::fs;
+
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn sha256_hex(content: &[u8]) -> String {
+        use sha2::{Digest, Sha256};
+        Sha256::digest(content)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    fn silent(_: &str) -> Arc<dyn ProgressObserver> {
+        Arc::new(SilentObserver)
+    }
+
+    #[test]
+    fn test_parse_metalink_4() {
+        let metalink = Metalink::parse(
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+            <metalink xmlns="urn:ietf:params:xml:ns:metalink">
+              <published>2024-05-01T00:00:00Z</published>
+              <file name="release/app-1.0.tar.gz">
+                <size>10</size>
+                <hash type="sha-256">ABCDEF</hash>
+                <hash type="md5">0123</hash>
+                <pieces length="4" type="sha-1">
+                  <hash>aa</hash>
+                  <hash>bb</hash>
+                  <hash>cc</hash>
+                </pieces>
+                <url location="us">https://us.example.com/app.tar.gz</url>
+                <url priority="1" location="DE">https://de.example.com/app.tar.gz?a=1&amp;b=2</url>
+                <url priority="2">ftp://ftp.example.com/app.tar.gz</url>
+                <url priority="1">magnet:?xt=urn:btih:abc</url>
+                <metaurl mediatype="torrent">https://example.com/app.torrent</metaurl>
+              </file>
+              <file name="notes.txt">
+                <url>https://example.com/notes.txt</url>
+              </file>
+            </metalink>"#,
+        )
+        .unwrap();
+
+        assert_eq!(metalink.files.len(), 2);
+        let file = &metalink.files[0];
+        assert_eq!(file.name, "release/app-1.0.tar.gz");
+        assert_eq!(file.size, Some(10));
+        assert_eq!(
+            file.hashes,
+            [
+                ("sha-256".to_string(), "abcdef".to_string()),
+                ("md5".to_string(), "0123".to_string())
+            ]
+        );
+        assert_eq!(
+            file.pieces,
+            Some(Pieces {
+                length: 4,
+                hash_type: "sha-1".to_string(),
+                hashes: vec!["aa".to_string(), "bb".to_string(), "cc".to_string()],
+            })
+        );
+
+        // Mirrors are sorted by priority, and those that aren't downloadable are left out
+        assert_eq!(
+            file.urls,
+            [
+                MetalinkUrl {
+                    url: "https://de.example.com/app.tar.gz?a=1&b=2".to_string(),
+                    priority: 1,
+                    location: Some("de".to_string()),
+                },
+                MetalinkUrl {
+                    url: "ftp://ftp.example.com/app.tar.gz".to_string(),
+                    priority: 2,
+                    location: None,
+                },
+                MetalinkUrl {
+                    url: "https://us.example.com/app.tar.gz".to_string(),
+                    priority: 999999,
+                    location: Some("us".to_string()),
+                },
+            ]
+        );
+
+        assert_eq!(metalink.files[1].size, None);
+        assert!(metalink.files[1].hashes.is_empty());
+    }
+
+    #[test]
+    fn test_parse_metalink_3() {
+        let metalink = Metalink::parse(
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+            <metalink version="3.0" xmlns="http://www.metalinker.org/">
+              <files>
+                <file name="app.iso">
+                  <size>8</size>
+                  <verification>
+                    <hash type="sha1">1111</hash>
+                    <hash type="sha256">2222</hash>
+                    <pieces length="4" type="sha1">
+                      <hash piece="0">aa</hash>
+                      <hash piece="1">bb</hash>
+                    </pieces>
+                  </verification>
+                  <resources>
+                    <url type="http" preference="90">http://b.example.com/app.iso</url>
+                    <url type="ftp" location="fr" preference="100">ftp://a.example.com/app.iso</url>
+                    <url type="bittorrent" preference="100">http://example.com/app.torrent</url>
+                  </resources>
+                </file>
+              </files>
+            </metalink>"#,
+        )
+        .unwrap();
+
+        let file = &metalink.files[0];
+        assert_eq!(file.name, "app.iso");
+        assert_eq!(file.size, Some(8));
+        assert_eq!(file.hashes[0], ("sha-1".to_string(), "1111".to_string()));
+        assert_eq!(file.hashes[1], ("sha-256".to_string(), "2222".to_string()));
+        let pieces = file.pieces.as_ref().unwrap();
+        assert_eq!(pieces.hash_type, "sha-1");
+        assert_eq!(pieces.hashes, ["aa", "bb"]);
+
+        // Higher preferences come first, and torrents are left out
+        let urls: Vec<_> = file.urls.iter().map(|url| url.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "ftp://a.example.com/app.iso",
+                "http://b.example.com/app.iso"
+            ]
+        );
+        assert_eq!(file.urls[0].location.as_deref(), Some("fr"));
+    }
+
+    #[test]
+    fn test_parse_rejects_unsafe_names() {
+        for name in ["../evil", "/etc/passwd", "a/../../b", "a\\b", "", "./a"] {
+            let document = format!(
+                r#"<metalink xmlns="urn:ietf:params:xml:ns:metalink"><file name="{}"><url>https://example.com/</url></file></metalink>"#,
+                name
+            );
+            let error = Metalink::parse(document.as_bytes()).unwrap_err();
+            assert_eq!(error.kind(), "ConfigurationError", "{}", name);
+        }
+
+        assert!(Metalink::parse(b"<urlset></urlset>").is_err());
+        assert!(Metalink::parse(b"").is_err());
+        assert!(Metalink::parse(b"<metalink><file></file></metalink>").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_download_with_failover() {
+        let mock_server = MockServer::start().await;
+        let content: &[u8] = b"Release content";
+        Mock::given(method("GET"))
+            .and(path("/missing/app.bin"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/corrupt/app.bin"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"Release c0ntent".to_vec()))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/good/app.bin"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(content))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/release.meta4"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                r#"<metalink xmlns="urn:ietf:params:xml:ns:metalink">
+                  <file name="dist/app.bin">
+                    <size>15</size>
+                    <hash type="sha-256">{hash}</hash>
+                    <url priority="2">{uri}/good/app.bin</url>
+                    <url priority="1" location="us">{uri}/corrupt/app.bin</url>
+                    <url priority="1" location="de">{uri}/missing/app.bin</url>
+                  </file>
+                  <file name="gone.bin">
+                    <url>{uri}/missing/app.bin</url>
+                  </file>
+                </metalink>"#,
+                hash = sha256_hex(content),
+                uri = mock_server.uri(),
+            )))
+            .mount(&mock_server)
+            .await;
+
+        let save_dir = "./test_output_metalink_failover";
+        let downloader = Downloader::new().retries(0);
+        let metalink = load_metalink(&downloader, &format!("{}/release.meta4", mock_server.uri()))
+            .await
+            .unwrap();
+        let entries = download_files(
+            &downloader,
+            &metalink,
+            Path::new(save_dir),
+            &Scheduler::new(),
+            Some("DE"),
+            &silent,
+        )
+        .await;
+
+        // The preferred location is tried first, then the corrupt mirror, then the good one
+        let requested: Vec<String> = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| request.url.path().to_string())
+            .filter(|path| path.ends_with("app.bin"))
+            .collect();
+        assert_eq!(
+            requested
+                .iter()
+                .filter(|path| *path == "/corrupt/app.bin")
+                .count(),
+            1
+        );
+        assert_eq!(
+            requested.iter().position(|path| path == "/missing/app.bin"),
+            Some(0)
+        );
+        assert_eq!(requested.last().map(String::as_str), Some("/good/app.bin"));
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0].url,
+            format!("{}/good/app.bin", mock_server.uri())
+        );
+        let outcome = entries[0].result.as_ref().unwrap();
+        assert_eq!(outcome.path, Path::new(save_dir).join("dist/app.bin"));
+        assert_eq!(fs::read(&outcome.path).await.unwrap(), content);
+
+        // A file whose mirrors all fail is reported with the last error
+        assert_eq!(
+            entries[1].url,
+            format!("{}/missing/app.bin", mock_server.uri())
+        );
+        assert!(entries[1].result.is_err());
+        assert!(!Path::new(save_dir).join("gone.bin").exists());
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_hash_mismatch_on_every_mirror() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/app.bin"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"Tampered".to_vec()))
+            .mount(&mock_server)
+            .await;
+
+        let save_dir = "./test_output_metalink_mismatch";
+        fs::create_dir_all(save_dir).await.unwrap();
+        let metalink_path = Path::new(save_dir).join("app.metalink");
+        fs::write(
+            &metalink_path,
+            format!(
+                r#"<metalink version="3.0" xmlns="http://www.metalinker.org/"><files>
+                  <file name="app.bin">
+                    <verification><hash type="sha256">{}</hash></verification>
+                    <resources><url type="http">{}/app.bin</url></resources>
+                  </file>
+                </files></metalink>"#,
+                sha256_hex(b"Original"),
+                mock_server.uri()
+            ),
+        )
+        .await
+        .unwrap();
+
+        let downloader = Downloader::new().retries(0);
+        let metalink = load_metalink(&downloader, metalink_path.to_str().unwrap())
+            .await
+            .unwrap();
+        let entries = download_files(
+            &downloader,
+            &metalink,
+            Path::new(save_dir),
+            &Scheduler::new(),
+            None,
+            &silent,
+        )
+        .await;
+
+        // The bad download isn't kept
+        let error = entries[0].result.as_ref().unwrap_err();
+        assert_eq!(error.kind(), "FileChecksumMismatch");
+        assert!(!Path::new(save_dir).join("app.bin").exists());
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_refetch_from_bad_piece() {
+        let mock_server = MockServer::start().await;
+        let content = b"aaaabbbbcc";
+        Mock::given(method("GET"))
+            .and(path("/app.bin"))
+            .respond_with(RangeResponder(content.to_vec()))
+            .mount(&mock_server)
+            .await;
+
+        let sha1_hex = |content: &[u8]| {
+            use sha1::{Digest, Sha1};
+            Sha1::digest(content)
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        };
+        let metalink = Metalink::parse(
+            format!(
+                r#"<metalink xmlns="urn:ietf:params:xml:ns:metalink">
+                  <file name="app.bin">
+                    <size>10</size>
+                    <hash type="sha-256">{}</hash>
+                    <pieces length="4" type="sha-1">
+                      <hash>{}</hash><hash>{}</hash><hash>{}</hash>
+                    </pieces>
+                    <url>{}/app.bin</url>
+                  </file>
+                </metalink>"#,
+                sha256_hex(content),
+                sha1_hex(b"aaaa"),
+                sha1_hex(b"bbbb"),
+                sha1_hex(b"cc"),
+                mock_server.uri()
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        // An earlier download left a file whose second piece is corrupt
+        let save_dir = "./test_output_metalink_pieces";
+        fs::create_dir_all(save_dir).await.unwrap();
+        fs::write(Path::new(save_dir).join("app.bin"), "aaaaXXXXcc")
+            .await
+            .unwrap();
+
+        let entries = download_files(
+            &Downloader::new().retries(0),
+            &metalink,
+            Path::new(save_dir),
+            &Scheduler::new(),
+            None,
+            &silent,
+        )
+        .await;
+
+        // Only the part from the bad piece is fetched again
+        let outcome = entries[0].result.as_ref().unwrap();
+        assert_eq!(outcome.status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(outcome.received, 6);
+        assert_eq!(fs::read(&outcome.path).await.unwrap(), content);
+
+        let requests = mock_server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].headers.get("range").unwrap(), "bytes=4-");
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pieces_without_file_hash() {
+        let mock_server = MockServer::start().await;
+        let content = b"aaaabbbbcc";
+        Mock::given(method("GET"))
+            .and(path("/corrupt/app.bin"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"aaaaXXXXcc".to_vec()))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/good/app.bin"))
+            .respond_with(RangeResponder(content.to_vec()))
+            .mount(&mock_server)
+            .await;
+
+        let sha1_hex = |content: &[u8]| {
+            use sha1::{Digest, Sha1};
+            Sha1::digest(content)
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        };
+        let metalink = Metalink::parse(
+            format!(
+                r#"<metalink xmlns="urn:ietf:params:xml:ns:metalink">
+                  <file name="app.bin">
+                    <pieces length="4" type="sha-1">
+                      <hash>{}</hash><hash>{}</hash><hash>{}</hash>
+                    </pieces>
+                    <url priority="1">{uri}/corrupt/app.bin</url>
+                    <url priority="2">{uri}/good/app.bin</url>
+                  </file>
+                </metalink>"#,
+                sha1_hex(b"aaaa"),
+                sha1_hex(b"bbbb"),
+                sha1_hex(b"cc"),
+                uri = mock_server.uri()
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let save_dir = "./test_output_metalink_pieces_only";
+        let entries = download_files(
+            &Downloader::new().retries(0),
+            &metalink,
+            Path::new(save_dir),
+            &Scheduler::new(),
+            None,
+            &silent,
+        )
+        .await;
+
+        // The corrupt download is caught by its pieces, and the good mirror sends the rest
+        assert_eq!(
+            entries[0].url,
+            format!("{}/good/app.bin", mock_server.uri())
+        );
+        let outcome = entries[0].result.as_ref().unwrap();
+        assert_eq!(outcome.received, 6);
+        assert_eq!(fs::read(&outcome.path).await.unwrap(), content);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+}
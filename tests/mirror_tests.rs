@@ -0,0 +1,350 @@
+mod common;
+
+#[cfg(test)]
+mod mirror_tests {
+    use crate::common::{RangeResponder, RecordingObserver};
+
+    use nget::enums::MirrorOrder;
+    use nget::mirrors::parse_url_list;
+    use nget::resume_utils::ResumeState;
+    use nget::{DownloadEvent, Downloader};
+
+    use reqwest::StatusCode;
+
+    use std::net::SocketAddr;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::fs;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn sha256_hex(content: &[u8]) -> String {
+        use sha2::{Digest, Sha256};
+        Sha256::digest(content)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    async fn range_server(content: &[u8]) -> MockServer {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/app.bin"))
+            .respond_with(RangeResponder(content.to_vec()))
+            .mount(&mock_server)
+            .await;
+        mock_server
+    }
+
+    /// Starts a server that promises `len` bytes but closes the connection after `body`.
+    async fn start_truncating_server(len: usize, body: &'static [u8]) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).await;
+                let headers = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: \"primary\"\r\n\r\n",
+                    len
+                );
+                let _ = stream.write_all(headers.as_bytes()).await;
+                let _ = stream.write_all(body).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn test_parse_url_list() {
+        let lists = parse_url_list(
+            "https://cdn.example.com/a.iso\thttps://m1.example.com/a.iso\t https://m2.example.com/a.iso\n\
+             \n\
+             # Comments are skipped\n\
+             https://example.com/b.txt\t\n",
+        );
+        assert_eq!(
+            lists,
+            [
+                vec![
+                    "https://cdn.example.com/a.iso",
+                    "https://m1.example.com/a.iso",
+                    "https://m2.example.com/a.iso"
+                ],
+                vec!["https://example.com/b.txt"],
+            ]
+        );
+        assert!(parse_url_list("").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failover_to_mirror() {
+        let primary = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&primary)
+            .await;
+        let mirror = range_server(b"Mirrored content").await;
+
+        let save_dir = "./test_output_mirror_failover";
+        fs::create_dir_all(save_dir).await.unwrap();
+        let observer = Arc::new(RecordingObserver::default());
+        let outcome = Downloader::new()
+            .request(&format!("{}/app.bin", primary.uri()))
+            .mirrors(vec![format!("{}/app.bin", mirror.uri())])
+            .output_dir(save_dir)
+            .observer(observer.clone())
+            .send()
+            .await
+            .unwrap();
+
+        // The file is named after the first URL, and the mirror is tried straight away
+        assert_eq!(outcome.path, Path::new(save_dir).join("app.bin"));
+        assert_eq!(outcome.final_url, format!("{}/app.bin", mirror.uri()));
+        assert_eq!(
+            fs::read_to_string(&outcome.path).await.unwrap(),
+            "Mirrored content"
+        );
+        let events = observer.events();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            DownloadEvent::Retrying { attempt: 1, delay, .. } if delay.is_zero()
+        )));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            DownloadEvent::Started { url, attempt: 2, .. } if url.starts_with(&mirror.uri())
+        )));
+
+        // When every mirror fails, retries start again from the first
+        let error = Downloader::new()
+            .retries(1)
+            .request(&format!("{}/app.bin", primary.uri()))
+            .mirrors(vec![format!("{}/missing.bin", mirror.uri())])
+            .output_dir(save_dir)
+            .file_name("missing.bin")
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), "InvalidUrl");
+        let primary_requests = primary.received_requests().await.unwrap().len();
+        assert_eq!(primary_requests, 3);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_on_mirror() {
+        let content = b"The first half, then the second half";
+        let primary = start_truncating_server(content.len(), b"The first half, ").await;
+        let mirror = range_server(content).await;
+
+        let save_dir = "./test_output_mirror_resume";
+        fs::create_dir_all(save_dir).await.unwrap();
+        let outcome = Downloader::new()
+            .checksum(true)
+            .request(&format!("http://{}/app.bin", primary))
+            .mirrors(vec![format!("{}/app.bin", mirror.uri())])
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap();
+
+        // The mirror sends only what the primary didn't, without the primary's ETag
+        assert_eq!(outcome.status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(outcome.received, 20);
+        assert_eq!(outcome.sha256, Some(sha256_hex(content)));
+        assert_eq!(fs::read(&outcome.path).await.unwrap(), content);
+        assert!(ResumeState::load(&outcome.path).await.is_none());
+
+        let requests = mirror.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].headers.get("range").unwrap(), "bytes=16-");
+        assert!(requests[0].headers.get("if-range").is_none());
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_latency_order() {
+        let slow = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("Slow")
+                    .set_delay(Duration::from_millis(500)),
+            )
+            .mount(&slow)
+            .await;
+        let fast = range_server(b"Fast").await;
+
+        let save_dir = "./test_output_mirror_latency";
+        fs::create_dir_all(save_dir).await.unwrap();
+        let request = |order| {
+            Downloader::new()
+                .mirror_order(order)
+                .request(&format!("{}/app.bin", slow.uri()))
+                .mirrors(vec![format!("{}/app.bin", fast.uri())])
+                .output_dir(save_dir)
+        };
+
+        let outcome = request(MirrorOrder::Latency).send().await.unwrap();
+        assert_eq!(outcome.final_url, format!("{}/app.bin", fast.uri()));
+        assert_eq!(fs::read_to_string(&outcome.path).await.unwrap(), "Fast");
+
+        fs::remove_file(&outcome.path).await.unwrap();
+        let outcome = request(MirrorOrder::Listed).send().await.unwrap();
+        assert_eq!(outcome.final_url, format!("{}/app.bin", slow.uri()));
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_segments_across_mirrors() {
+        let content: Vec<u8> = (0..900_000u32).map(|i| (i % 251) as u8).collect();
+        let first = range_server(&content).await;
+        let second = range_server(&content).await;
+
+        // The third mirror fails, so its segment is downloaded from another one
+        let broken = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&broken)
+            .await;
+
+        let save_dir = "./test_output_mirror_segments";
+        fs::create_dir_all(save_dir).await.unwrap();
+        let observer = Arc::new(RecordingObserver::default());
+        let outcome = Downloader::new()
+            .checksum(true)
+            .mirror_segments(true)
+            .request(&format!("{}/app.bin", first.uri()))
+            .mirrors(vec![
+                format!("{}/app.bin", second.uri()),
+                format!("{}/app.bin", broken.uri()),
+            ])
+            .output_dir(save_dir)
+            .observer(observer.clone())
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.status, StatusCode::OK);
+        assert_eq!(outcome.bytes, 900_000);
+        assert_eq!(outcome.sha256, Some(sha256_hex(&content)));
+        assert!(fs::read(&outcome.path).await.unwrap() == content);
+
+        // Both working mirrors sent parts of the file
+        let ranges = |requests: Vec<wiremock::Request>| -> Vec<String> {
+            requests
+                .iter()
+                .filter_map(|request| request.headers.get("range"))
+                .map(|range| range.to_str().unwrap().to_string())
+                .collect()
+        };
+        let first_ranges = ranges(first.received_requests().await.unwrap());
+        let second_ranges = ranges(second.received_requests().await.unwrap());
+        assert_eq!(first_ranges[0], "bytes=0-0");
+        assert!(first_ranges.contains(&"bytes=0-299999".to_string()));
+        assert!(second_ranges.contains(&"bytes=300000-599999".to_string()));
+        assert!([first_ranges, second_ranges]
+            .concat()
+            .contains(&"bytes=600000-899999".to_string()));
+
+        let received: u64 = observer
+            .events()
+            .iter()
+            .map(|event| match event {
+                DownloadEvent::Bytes { received } => *received,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(received, 900_000);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_segments_stopped_early() {
+        let content: Vec<u8> = (0..600_000u32).map(|i| (i % 251) as u8).collect();
+        let first = range_server(&content).await;
+
+        // The second segment never arrives before the download is stopped
+        let stalled = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(206).set_delay(Duration::from_secs(10)))
+            .mount(&stalled)
+            .await;
+
+        let save_dir = "./test_output_mirror_segments_stopped";
+        fs::create_dir_all(save_dir).await.unwrap();
+        let download = Downloader::new()
+            .mirror_segments(true)
+            .request(&format!("{}/app.bin", first.uri()))
+            .mirrors(vec![format!("{}/app.bin", stalled.uri())])
+            .output_dir(save_dir)
+            .send();
+        assert!(tokio::time::timeout(Duration::from_secs(1), download)
+            .await
+            .is_err());
+
+        // Only the part downloaded without gaps is kept, though the file has its full size
+        let file_path = Path::new(save_dir).join("app.bin");
+        assert_eq!(fs::metadata(&file_path).await.unwrap().len(), 600_000);
+        let state = ResumeState::load(&file_path).await.unwrap();
+        assert_eq!(state.bytes, 300_000);
+
+        let outcome = Downloader::new()
+            .request(&format!("{}/app.bin", first.uri()))
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(outcome.status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(outcome.received, 300_000);
+        assert!(fs::read(&outcome.path).await.unwrap() == content);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_segments_from_different_versions() {
+        let content: Vec<u8> = (0..600_000u32).map(|i| (i % 251) as u8).collect();
+        let first = range_server(&content).await;
+        let other: Vec<u8> = (0..600_001u32).map(|i| (i % 241) as u8).collect();
+        let outdated = range_server(&other).await;
+
+        let save_dir = "./test_output_mirror_segments_versions";
+        fs::create_dir_all(save_dir).await.unwrap();
+        let outcome = Downloader::new()
+            .mirror_segments(true)
+            .request(&format!("{}/app.bin", first.uri()))
+            .mirrors(vec![format!("{}/app.bin", outdated.uri())])
+            .output_dir(save_dir)
+            .send()
+            .await
+            .unwrap();
+
+        // The mirror with another size is refused, so its segment comes from the first
+        assert!(fs::read(&outcome.path).await.unwrap() == content);
+        let ranges: Vec<String> = first
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter_map(|request| request.headers.get("range"))
+            .map(|range| range.to_str().unwrap().to_string())
+            .collect();
+        assert!(ranges.contains(&"bytes=300000-599999".to_string()));
+        assert_eq!(outdated.received_requests().await.unwrap().len(), 1);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+mod common;
+
+#[cfg(test)]
+mod progress_tests {
+    use crate::common::RecordingObserver;
+
+    use nget::{DownloadEvent, Downloader};
+
+    use reqwest::StatusCode;
+
+    use std::path::Path;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::fs;
+
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_events_for_download() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/events.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Event content"))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/events.txt", &mock_server.uri());
+        let save_dir = "./test_output_events";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        let recorder = Arc::new(RecordingObserver::default());
+        Downloader::new()
+            .request(&url)
+            .output_dir(save_dir)
+            .observer(recorder.clone())
+            .send()
+            .await
+            .unwrap();
+
+        let events = recorder.events();
+        assert!(matches!(
+            &events[0],
+            DownloadEvent::Started { path, attempt: 1, .. }
+                if *path == Path::new(save_dir).join("events.txt")
+        ));
+        assert!(matches!(
+            events[1],
+            DownloadEvent::HeadersReceived {
+                status: StatusCode::OK,
+                content_length: Some(13),
+                resume_from: 0,
+                ..
+            }
+        ));
+
+        // Every received byte is reported before the download finishes
+        let received: u64 = events
+            .iter()
+            .filter_map(|event| match event {
+                DownloadEvent::Bytes { received } => Some(*received),
+                _ => None,
+            })
+            .sum();
+        assert_eq!(received, 13);
+        assert!(matches!(
+            events.last(),
+            Some(DownloadEvent::Finished { outcome }) if outcome.bytes == 13
+        ));
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_events_for_resume() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/resume.txt"))
+            .respond_with(
+                ResponseTemplate::new(206)
+                    .set_body_string(" half")
+                    .insert_header("Content-Range", "bytes 10-14/15"),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/resume.txt", &mock_server.uri());
+        let save_dir = "./test_output_events_resume";
+        fs::create_dir_all(save_dir).await.unwrap();
+        fs::write(Path::new(save_dir).join("resume.txt"), "First half")
+            .await
+            .unwrap();
+
+        let recorder = Arc::new(RecordingObserver::default());
+        Downloader::new()
+            .request(&url)
+            .output_dir(save_dir)
+            .observer(recorder.clone())
+            .send()
+            .await
+            .unwrap();
+
+        let events = recorder.events();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            DownloadEvent::HeadersReceived {
+                status: StatusCode::PARTIAL_CONTENT,
+                content_length: Some(5),
+                resume_from: 10,
+                ..
+            }
+        )));
+        assert!(matches!(
+            events.last(),
+            Some(DownloadEvent::Finished { outcome }) if outcome.bytes == 15
+        ));
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_events_for_retries_and_failure() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/broken"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/broken", &mock_server.uri());
+
+        let recorder = Arc::new(RecordingObserver::default());
+        let result = Downloader::new()
+            .retries(1)
+            .retry_delay(Duration::from_millis(10))
+            .request(&url)
+            .output_dir("./test_output_events_failure")
+            .observer(recorder.clone())
+            .send()
+            .await;
+        assert!(result.is_err());
+
+        // Failed responses still report their headers
+        let events = recorder.events();
+        assert!(matches!(
+            events.as_slice(),
+            [
+                DownloadEvent::Started { attempt: 1, .. },
+                DownloadEvent::HeadersReceived {
+                    status: StatusCode::INTERNAL_SERVER_ERROR,
+                    ..
+                },
+                DownloadEvent::Retrying { attempt: 1, .. },
+                DownloadEvent::Started { attempt: 2, .. },
+                DownloadEvent::HeadersReceived { .. },
+                DownloadEvent::Failed { .. },
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_closure_observer() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/closure.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Counted"))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/closure.txt", &mock_server.uri());
+        let save_dir = "./test_output_events_closure";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        let total = Arc::new(AtomicU64::new(0));
+        let counter = total.clone();
+        Downloader::new()
+            .request(&url)
+            .output_dir(save_dir)
+            .observer(Arc::new(move |event: &DownloadEvent| {
+                if let DownloadEvent::Bytes { received } = event {
+                    counter.fetch_add(*received, Ordering::SeqCst);
+                }
+            }))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(total.load(Ordering::SeqCst), 7);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+}
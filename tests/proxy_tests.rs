@@ -1,13 +1,109 @@
 #[cfg(test)]
 mod proxy_tests {
-    use indicatif::ProgressBar;
-    use nget::enums::HttpVersion;
+    use nget::enums::{Compression, HttpVersion, ProxyType};
     use nget::http::download_file;
+    use nget::progress::SilentObserver;
     use nget::proxy_utils::ProxyConfig;
+    use nget::tls_utils::TlsConfig;
+    use std::net::SocketAddr;
     use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
     use wiremock::matchers::{method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
+    /// Target addresses requested through the SOCKS5 stand-in, as sent by the client.
+    type SocksTargets = Arc<Mutex<Vec<String>>>;
+
+    /// Starts a minimal SOCKS5 server that tunnels every CONNECT to `upstream`,
+    /// recording the requested target. Requires username/password auth when
+    /// `credentials` is set.
+    async fn start_socks5_server(
+        upstream: SocketAddr,
+        credentials: Option<(&'static str, &'static str)>,
+    ) -> (SocketAddr, SocksTargets) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let targets = SocksTargets::default();
+        let recorded = targets.clone();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    let _ = handle_socks5(stream, upstream, credentials, recorded).await;
+                });
+            }
+        });
+
+        (addr, targets)
+    }
+
+    async fn handle_socks5(
+        mut stream: TcpStream,
+        upstream: SocketAddr,
+        credentials: Option<(&'static str, &'static str)>,
+        recorded: SocksTargets,
+    ) -> std::io::Result<()> {
+        // Greeting: version, method count, methods
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).await?;
+        let mut methods = vec![0u8; header[1] as usize];
+        stream.read_exact(&mut methods).await?;
+
+        if let Some((user, pass)) = credentials {
+            stream.write_all(&[5, 2]).await?;
+
+            // Username/password sub-negotiation (RFC 1929)
+            let mut len = [0u8; 2];
+            stream.read_exact(&mut len).await?;
+            let mut username = vec![0u8; len[1] as usize];
+            stream.read_exact(&mut username).await?;
+            stream.read_exact(&mut len[..1]).await?;
+            let mut password = vec![0u8; len[0] as usize];
+            stream.read_exact(&mut password).await?;
+
+            if username != user.as_bytes() || password != pass.as_bytes() {
+                stream.write_all(&[1, 1]).await?;
+                return Ok(());
+            }
+            stream.write_all(&[1, 0]).await?;
+        } else {
+            stream.write_all(&[5, 0]).await?;
+        }
+
+        // Request: version, command, reserved, address type
+        let mut request = [0u8; 4];
+        stream.read_exact(&mut request).await?;
+        let host = match request[3] {
+            1 => {
+                let mut ip = [0u8; 4];
+                stream.read_exact(&mut ip).await?;
+                std::net::Ipv4Addr::from(ip).to_string()
+            }
+            3 => {
+                let mut len = [0u8; 1];
+                stream.read_exact(&mut len).await?;
+                let mut domain = vec![0u8; len[0] as usize];
+                stream.read_exact(&mut domain).await?;
+                String::from_utf8_lossy(&domain).to_string()
+            }
+            _ => {
+                let mut ip = [0u8; 16];
+                stream.read_exact(&mut ip).await?;
+                format!("[{}]", std::net::Ipv6Addr::from(ip))
+            }
+        };
+        let port = stream.read_u16().await?;
+        recorded.lock().unwrap().push(format!("{}:{}", host, port));
+
+        let mut target = TcpStream::connect(upstream).await?;
+        stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+        tokio::io::copy_bidirectional(&mut stream, &mut target).await?;
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_proxy_with_mock_server() {
         let proxy_server = MockServer::start().await; // Mock proxy server
@@ -29,7 +125,6 @@ mod proxy_tests {
         let url = format!("{}/proxytest", target_server.uri());
         let save_dir = "./test_output";
         let output_file_name = None;
-        let progress_bar = ProgressBar::hidden();
 
         // Create directory for test output
         tokio::fs::create_dir_all(save_dir).await.unwrap();
@@ -38,9 +133,11 @@ mod proxy_tests {
             &url,
             save_dir,
             &output_file_name,
-            &progress_bar,
+            Arc::new(SilentObserver),
             &HttpVersion::Http11,
             &proxy_config,
+            &TlsConfig::empty(),
+            &Compression::Auto,
         )
         .await;
 
@@ -76,7 +173,6 @@ mod proxy_tests {
         let url = format!("{}/proxytest", &target_server.uri());
         let save_dir = "./test_output";
         let output_file_name = None;
-        let progress_bar = ProgressBar::hidden();
 
         tokio::fs::create_dir_all(save_dir).await.unwrap();
 
@@ -84,9 +180,11 @@ mod proxy_tests {
             &url,
             save_dir,
             &output_file_name,
-            &progress_bar,
+            Arc::new(SilentObserver),
             &HttpVersion::Http11,
             &proxy_config,
+            &TlsConfig::empty(),
+            &Compression::Auto,
         )
         .await;
 
@@ -114,7 +212,6 @@ mod proxy_tests {
         let url = format!("{}/noproxytest", &target_server.uri());
         let save_dir = "./test_output";
         let output_file_name = None;
-        let progress_bar = ProgressBar::hidden();
 
         tokio::fs::create_dir_all(save_dir).await.unwrap();
 
@@ -122,9 +219,11 @@ mod proxy_tests {
             &url,
             save_dir,
             &output_file_name,
-            &progress_bar,
+            Arc::new(SilentObserver),
             &HttpVersion::Http11,
             &proxy_config,
+            &TlsConfig::empty(),
+            &Compression::Auto,
         )
         .await;
 
@@ -138,4 +237,169 @@ mod proxy_tests {
         tokio::fs::remove_file(&saved_file_path).await.unwrap();
         tokio::fs::remove_dir_all(save_dir).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_socks5h_proxy_resolves_remotely() {
+        let target_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/sockstest"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Socks content"))
+            .mount(&target_server)
+            .await;
+
+        let (socks_addr, targets) =
+            start_socks5_server(*target_server.address(), Some(("nget", "secret"))).await;
+
+        let proxy_config = ProxyConfig {
+            proxy_url: format!("socks5h://{}", socks_addr),
+            proxy_user: "nget".to_string(),
+            proxy_password: "secret".to_string(),
+        };
+
+        // This host only exists on the proxy side, so a local DNS lookup would fail
+        let port = target_server.address().port();
+        let url = format!("http://nget-socks.invalid:{}/sockstest", port);
+        let save_dir = "./test_output_socks5h";
+        let output_file_name = None;
+
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+
+        let result = download_file(
+            &url,
+            save_dir,
+            &output_file_name,
+            Arc::new(SilentObserver),
+            &HttpVersion::Http11,
+            &proxy_config,
+            &TlsConfig::empty(),
+            &Compression::Auto,
+        )
+        .await;
+
+        assert!(result.is_ok(), "Socks5h request failed: {:?}", result.err());
+        assert_eq!(
+            *targets.lock().unwrap(),
+            vec![format!("nget-socks.invalid:{}", port)]
+        );
+
+        let saved_file_path = Path::new(save_dir).join("sockstest");
+        let content = tokio::fs::read_to_string(&saved_file_path).await.unwrap();
+        assert_eq!(content, "Socks content");
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_socks5_proxy_resolves_locally() {
+        let target_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/sockslocal"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Local DNS content"))
+            .mount(&target_server)
+            .await;
+
+        let (socks_addr, targets) = start_socks5_server(*target_server.address(), None).await;
+
+        let proxy_config = ProxyConfig {
+            proxy_url: format!("socks5://{}", socks_addr),
+            ..Default::default()
+        };
+
+        let port = target_server.address().port();
+        let url = format!("http://localhost:{}/sockslocal", port);
+        let save_dir = "./test_output_socks5";
+        let output_file_name = None;
+
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+
+        let result = download_file(
+            &url,
+            save_dir,
+            &output_file_name,
+            Arc::new(SilentObserver),
+            &HttpVersion::Http11,
+            &proxy_config,
+            &TlsConfig::empty(),
+            &Compression::Auto,
+        )
+        .await;
+
+        assert!(result.is_ok(), "Socks5 request failed: {:?}", result.err());
+
+        // The host name was resolved before reaching the proxy
+        let targets = targets.lock().unwrap().clone();
+        assert_eq!(targets.len(), 1);
+        assert!(!targets[0].starts_with("localhost"));
+
+        let content = tokio::fs::read_to_string(Path::new(save_dir).join("sockslocal"))
+            .await
+            .unwrap();
+        assert_eq!(content, "Local DNS content");
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_socks5_proxy_bad_credentials() {
+        let target_server = MockServer::start().await;
+
+        let (socks_addr, targets) =
+            start_socks5_server(*target_server.address(), Some(("nget", "secret"))).await;
+
+        let proxy_config = ProxyConfig {
+            proxy_url: format!("socks5h://{}", socks_addr),
+            proxy_user: "nget".to_string(),
+            proxy_password: "wrong".to_string(),
+        };
+
+        let url = format!("{}/sockstest", target_server.uri());
+        let save_dir = "./test_output_socks5_auth";
+        let output_file_name = None;
+
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+
+        let result = download_file(
+            &url,
+            save_dir,
+            &output_file_name,
+            Arc::new(SilentObserver),
+            &HttpVersion::Http11,
+            &proxy_config,
+            &TlsConfig::empty(),
+            &Compression::Auto,
+        )
+        .await;
+
+        assert!(result.is_err()); // Rejected credentials should cause an error
+        assert!(targets.lock().unwrap().is_empty());
+        assert!(!Path::new(save_dir).join("sockstest").exists());
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[test]
+    fn test_proxy_type_from_scheme() {
+        let proxy_type = |url: &str| {
+            ProxyConfig {
+                proxy_url: url.to_string(),
+                ..Default::default()
+            }
+            .proxy_type()
+        };
+
+        assert_eq!(proxy_type("127.0.0.1:8080").unwrap(), ProxyType::Http);
+        assert_eq!(proxy_type("http://proxy:8080").unwrap(), ProxyType::Http);
+        assert_eq!(proxy_type("https://proxy:8443").unwrap(), ProxyType::Https);
+        assert_eq!(
+            proxy_type("SOCKS5://proxy:1080").unwrap(),
+            ProxyType::Socks5
+        );
+        assert_eq!(
+            proxy_type("socks5h://proxy:1080").unwrap(),
+            ProxyType::Socks5h
+        );
+        assert!(proxy_type("socks4://proxy:1080").is_err());
+    }
 }
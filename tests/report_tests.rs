@@ -0,0 +1,170 @@
+#[cfg(test)]
+mod report_tests {
+    use nget::download_log::DownloadLog;
+    use nget::error::NgetError;
+    use nget::progress::ProgressObserver;
+    use nget::report::{Report, ReportFormat, ReportStatus};
+    use nget::Downloader;
+
+    use serde_json::Value;
+
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use tokio::fs;
+
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Downloads `/moved` (redirected to `/target.txt`) and `/missing` into `save_dir`,
+    /// each reporting to the observer created for its URL.
+    async fn download_pair(save_dir: &str, observers: impl Fn(&str) -> Arc<dyn ProgressObserver>) {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/moved"))
+            .respond_with(ResponseTemplate::new(302).insert_header("Location", "/target.txt"))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/target.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Redirected content"))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/missing"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        fs::create_dir_all(save_dir).await.unwrap();
+        let downloader = Downloader::new();
+        for name in ["moved", "missing"] {
+            let url = format!("{}/{}", &mock_server.uri(), name);
+            let _ = downloader
+                .request(&url)
+                .output_dir(save_dir)
+                .observer(observers(&url))
+                .send()
+                .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_report_records_success_and_failure() {
+        let save_dir = "./test_output_report_entries";
+        let report = Report::new();
+        download_pair(save_dir, |url| Arc::new(report.observer(url))).await;
+
+        let entries = report.entries();
+        assert_eq!(entries.len(), 2);
+
+        let moved = &entries[0];
+        assert!(moved.url.ends_with("/moved"));
+        assert_eq!(moved.status, ReportStatus::Completed);
+        assert_eq!(moved.http_status, Some(200));
+        assert!(moved.final_url.as_deref().unwrap().ends_with("/target.txt"));
+        assert_eq!(moved.size, 18);
+        assert!(moved.error.is_none());
+
+        let missing = &entries[1];
+        assert_eq!(missing.status, ReportStatus::Failed);
+        assert_eq!(missing.http_status, Some(404));
+        assert!(missing.error.is_some());
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_report_files() {
+        let save_dir = "./test_output_report_files";
+        let report = Report::new();
+        download_pair(save_dir, |url| Arc::new(report.observer(url))).await;
+
+        let json_path = Path::new(save_dir).join("report.json");
+        report.write(&json_path).await.unwrap();
+        let json: Value =
+            serde_json::from_str(&fs::read_to_string(&json_path).await.unwrap()).unwrap();
+        let rows = json.as_array().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["status"], "completed");
+        assert_eq!(rows[0]["size"], 18);
+        assert!(rows[0]["duration_ms"].is_u64());
+        assert!(rows[0]["average_speed"].is_u64());
+        assert_eq!(rows[1]["status"], "failed");
+        assert_eq!(rows[1]["http_status"], 404);
+
+        let csv_path = Path::new(save_dir).join("report.csv");
+        report.write(&csv_path).await.unwrap();
+        let csv = fs::read_to_string(&csv_path).await.unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "url,status,http_status,final_url,path,size,duration_ms,average_speed,error"
+        );
+        assert!(lines[1].contains(",completed,200,"));
+        assert!(lines[2].contains(",failed,404,"));
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_log() {
+        let save_dir = "./test_output_download_log";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        let log_path = Path::new(save_dir).join("nget.log");
+        fs::write(&log_path, "earlier run\n").await.unwrap();
+
+        let log = DownloadLog::open(&log_path, true).unwrap();
+        download_pair(save_dir, |url| Arc::new(log.observer(url))).await;
+
+        let content = fs::read_to_string(&log_path).await.unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines[0], "earlier run");
+        assert!(lines[1].contains("Downloading") && lines[1].ends_with("(attempt 1)"));
+        assert!(lines[2].contains("Saved") && lines[2].contains("/target.txt"));
+        assert!(lines[4].contains("Failed to download"));
+
+        // Without appending, the log starts over
+        let log = DownloadLog::open(&log_path, false).unwrap();
+        log.write("new run");
+        let content = fs::read_to_string(&log_path).await.unwrap();
+        assert_eq!(content.lines().count(), 1);
+        assert!(content.trim_end().ends_with(" new run"));
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[test]
+    fn test_report_format_from_path() {
+        assert_eq!(
+            ReportFormat::from_path(Path::new("out/report.JSON")).unwrap(),
+            ReportFormat::Json
+        );
+        assert_eq!(
+            ReportFormat::from_path(Path::new("report.csv")).unwrap(),
+            ReportFormat::Csv
+        );
+        assert!(matches!(
+            ReportFormat::from_path(Path::new("report.txt")),
+            Err(NgetError::ConfigurationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_csv_quoting() {
+        let report = Report::new();
+        let observer = report.observer("http://example.com/a,b");
+        observer.on_event(&nget::DownloadEvent::Failed {
+            error: "said \"no\"".to_string(),
+            kind: "HttpRequest",
+        });
+
+        let csv = report.render(ReportFormat::Csv);
+        let row = csv.lines().nth(1).unwrap();
+        assert!(row.starts_with("\"http://example.com/a,b\",failed,"));
+        assert!(row.ends_with(",\"said \"\"no\"\"\""));
+    }
+}
@@ -0,0 +1,261 @@
+mod common;
+
+#[cfg(test)]
+mod robots_tests {
+    use crate::common::mount_page;
+
+    use nget::crawler::Crawler;
+    use nget::robots::{RobotsCache, RobotsTxt};
+    use nget::scheduler::Scheduler;
+    use nget::Downloader;
+
+    use reqwest::header::{self, HeaderValue};
+
+    use std::time::{Duration, Instant};
+
+    use tokio::fs;
+
+    use url::Url;
+
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn url(path: &str) -> Url {
+        Url::parse(&format!("https://example.com{}", path)).unwrap()
+    }
+
+    #[test]
+    fn test_parse_robots() {
+        let text = "
+            # Everyone else
+            User-agent: *
+            Disallow: /
+
+            User-agent: othercrawler
+            User-agent: nget
+            Disallow: /private/
+            Allow: /private/public/
+            Disallow: /*.zip$
+            Crawl-delay: 2.5
+
+            User-agent: NGET
+            Disallow: /tmp # combined with the group above
+        ";
+        let robots = RobotsTxt::parse(text, "nget");
+
+        assert!(robots.is_allowed(&url("/")));
+        assert!(!robots.is_allowed(&url("/private/keys")));
+        assert!(robots.is_allowed(&url("/private/public/index.html")));
+        assert!(!robots.is_allowed(&url("/releases/nget.zip")));
+        assert!(robots.is_allowed(&url("/releases/nget.zip?mirror=1")));
+        assert!(!robots.is_allowed(&url("/tmp/file")));
+        assert_eq!(robots.crawl_delay(), Some(Duration::from_millis(2500)));
+
+        // Agents without a group of their own use the `*` group
+        let other = RobotsTxt::parse(text, "somebot");
+        assert!(!other.is_allowed(&url("/")));
+        assert_eq!(other.crawl_delay(), None);
+    }
+
+    #[test]
+    fn test_allow_wins_ties() {
+        let robots = RobotsTxt::parse(
+            "User-agent: *\nDisallow: /page\nAllow: /page\nDisallow:\n",
+            "nget",
+        );
+        assert!(robots.is_allowed(&url("/page")));
+        assert!(RobotsTxt::allow_all().is_allowed(&url("/anything")));
+    }
+
+    #[tokio::test]
+    async fn test_robots_fetch_errors() {
+        let downloader = Downloader::new();
+
+        // A missing robots.txt allows everything
+        let missing = MockServer::start().await;
+        let page = Url::parse(&format!("{}/page.html", missing.uri())).unwrap();
+        let robots = RobotsCache::new().get(&downloader, &page).await;
+        assert!(robots.is_allowed(&page));
+
+        // An unavailable one disallows everything
+        let unavailable = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/robots.txt"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&unavailable)
+            .await;
+        let page = Url::parse(&format!("{}/page.html", unavailable.uri())).unwrap();
+        let robots = RobotsCache::new().get(&downloader, &page).await;
+        assert!(!robots.is_allowed(&page));
+    }
+
+    #[tokio::test]
+    async fn test_robots_user_agent() {
+        let mock_server = MockServer::start().await;
+        let nget_agent = format!("nget/{}", env!("CARGO_PKG_VERSION"));
+        // Only requests that send one of these User-Agents get the robots.txt
+        for agent in [nget_agent.as_str(), "mybot/2.0"] {
+            Mock::given(method("GET"))
+                .and(path("/robots.txt"))
+                .and(header("user-agent", agent))
+                .respond_with(ResponseTemplate::new(200).set_body_string(
+                    "User-agent: nget\nDisallow: /nget/\n\nUser-agent: mybot\nDisallow: /mybot/\n",
+                ))
+                .mount(&mock_server)
+                .await;
+        }
+        let nget_page = Url::parse(&format!("{}/nget/page.html", mock_server.uri())).unwrap();
+        let mybot_page = Url::parse(&format!("{}/mybot/page.html", mock_server.uri())).unwrap();
+
+        // nget's own User-Agent is sent and matched by default
+        let robots = RobotsCache::new().get(&Downloader::new(), &nget_page).await;
+        assert!(!robots.is_allowed(&nget_page));
+        assert!(robots.is_allowed(&mybot_page));
+
+        // A User-Agent given by the user is matched by its product token
+        let downloader =
+            Downloader::new().header(header::USER_AGENT, HeaderValue::from_static("mybot/2.0"));
+        let robots = RobotsCache::new().get(&downloader, &nget_page).await;
+        assert!(robots.is_allowed(&nget_page));
+        assert!(!robots.is_allowed(&mybot_page));
+    }
+
+    #[tokio::test]
+    async fn test_crawl_obeys_robots() {
+        let mock_server = MockServer::start().await;
+
+        mount_page(
+            &mock_server,
+            "/robots.txt",
+            "User-agent: *\nDisallow: /secret/\n",
+            "text/plain",
+        )
+        .await;
+        mount_page(
+            &mock_server,
+            "/",
+            r#"<a href="/public.html">Public</a> <a href="/secret/plans.html">Secret</a>"#,
+            "text/html",
+        )
+        .await;
+        mount_page(&mock_server, "/public.html", "Public", "text/html").await;
+        mount_page(&mock_server, "/secret/plans.html", "Secret", "text/html").await;
+
+        let save_dir = "./test_output_robots";
+        let start = [format!("{}/", mock_server.uri())];
+        let entries = Crawler::new(Downloader::new())
+            .output_dir(save_dir)
+            .crawl(&start)
+            .await;
+        let urls: Vec<&str> = entries
+            .iter()
+            .map(|entry| entry.url.trim_start_matches(&mock_server.uri()))
+            .collect();
+        assert_eq!(urls, ["/", "/public.html"]);
+
+        // robots=off ignores it
+        let entries = Crawler::new(Downloader::new())
+            .output_dir(save_dir)
+            .robots(false)
+            .crawl(&start)
+            .await;
+        assert_eq!(entries.len(), 3);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_crawl_delay_spaces_requests() {
+        let mock_server = MockServer::start().await;
+
+        mount_page(
+            &mock_server,
+            "/robots.txt",
+            "User-agent: nget\nCrawl-delay: 0.3\n",
+            "text/plain",
+        )
+        .await;
+        mount_page(
+            &mock_server,
+            "/",
+            r#"<a href="/a.html">A</a> <a href="/b.html">B</a>"#,
+            "text/html",
+        )
+        .await;
+        mount_page(&mock_server, "/a.html", "A", "text/html").await;
+        mount_page(&mock_server, "/b.html", "B", "text/html").await;
+
+        let save_dir = "./test_output_crawl_delay";
+        let started = Instant::now();
+        let entries = Crawler::new(Downloader::new())
+            .output_dir(save_dir)
+            .crawl(&[format!("{}/", mock_server.uri())])
+            .await;
+
+        assert_eq!(entries.len(), 3);
+        assert!(started.elapsed() >= Duration::from_millis(600));
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_spacing_per_host() {
+        let scheduler = Scheduler::new().wait(Duration::from_millis(200));
+        let host_a = Url::parse("https://a.example.com/1").unwrap();
+        let host_b = Url::parse("https://b.example.com/1").unwrap();
+
+        let started = Instant::now();
+        scheduler.wait_turn(&host_a, Duration::ZERO).await;
+        scheduler.wait_turn(&host_b, Duration::ZERO).await;
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        scheduler.wait_turn(&host_a, Duration::ZERO).await;
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        // A longer minimum delay wins over the wait
+        let started = Instant::now();
+        scheduler
+            .wait_turn(&host_b, Duration::from_millis(400))
+            .await;
+        scheduler.wait_turn(&host_b, Duration::ZERO).await;
+        assert!(started.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_runs_requests_in_order() {
+        let mock_server = MockServer::start().await;
+        mount_page(&mock_server, "/one.txt", "One", "text/plain").await;
+        mount_page(&mock_server, "/two.txt", "Two", "text/plain").await;
+
+        let save_dir = "./test_output_scheduler_run";
+        fs::create_dir_all(save_dir).await.unwrap();
+
+        let downloader = Downloader::new();
+        let requests = ["one.txt", "two.txt"]
+            .iter()
+            .map(|name| {
+                downloader
+                    .request(&format!("{}/{}", mock_server.uri(), name))
+                    .output_dir(save_dir)
+            })
+            .collect();
+
+        let started = Instant::now();
+        let results = Scheduler::new()
+            .wait(Duration::from_millis(300))
+            .run(requests)
+            .await;
+
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        let names: Vec<String> = results
+            .into_iter()
+            .map(|result| {
+                let path = result.unwrap().path;
+                path.file_name().unwrap().to_string_lossy().to_string()
+            })
+            .collect();
+        assert_eq!(names, ["one.txt", "two.txt"]);
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+}
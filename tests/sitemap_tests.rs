@@ -0,0 +1,235 @@
+mod common;
+
+#[cfg(test)]
+mod sitemap_tests {
+    use crate::common::mount_page;
+
+    use nget::robots::RobotsTxt;
+    use nget::sitemap::{
+        collect_urls, discover_sitemaps, parse_w3c_datetime, Sitemap, SitemapEntry,
+    };
+    use nget::Downloader;
+
+    use async_compression::tokio::write::GzipEncoder;
+
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use tokio::fs;
+    use tokio::io::AsyncWriteExt;
+
+    use url::Url;
+
+    use wiremock::MockServer;
+
+    async fn gzip(content: &str) -> Vec<u8> {
+        let mut encoder = GzipEncoder::new(Vec::new());
+        encoder.write_all(content.as_bytes()).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        encoder.into_inner()
+    }
+
+    #[test]
+    fn test_parse_urlset() {
+        let sitemap = Sitemap::parse(
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url>
+                <loc> https://example.com/?a=1&amp;b=2 </loc>
+                <lastmod>2024-05-01</lastmod>
+                <changefreq>daily</changefreq>
+              </url>
+              <url><loc><![CDATA[https://example.com/about]]></loc></url>
+              <url><lastmod>2024-05-01</lastmod></url>
+            </urlset>"#,
+        )
+        .unwrap();
+
+        let Sitemap::Urls(entries) = sitemap else {
+            panic!("expected a urlset");
+        };
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].url, "https://example.com/?a=1&b=2");
+        assert_eq!(entries[0].lastmod, parse_w3c_datetime("2024-05-01"));
+        assert_eq!(entries[1].url, "https://example.com/about");
+        assert_eq!(entries[1].lastmod, None);
+
+        assert!(Sitemap::parse(b"<html><body></body></html>").is_err());
+        assert!(Sitemap::parse(b"").is_err());
+    }
+
+    #[test]
+    fn test_parse_ignores_extensions() {
+        let sitemap = Sitemap::parse(
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"
+                    xmlns:image="http://www.google.com/schemas/sitemap-image/1.1">
+              <url>
+                <loc>https://a.example/page</loc>
+                <image:image>
+                  <image:loc>https://a.example/img.jpg</image:loc>
+                </image:image>
+                <image:loc>https://a.example/other.jpg</image:loc>
+                <lastmod>2024-05-01</lastmod>
+              </url>
+            </urlset>"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            sitemap,
+            Sitemap::Urls(vec![SitemapEntry {
+                url: "https://a.example/page".to_string(),
+                lastmod: parse_w3c_datetime("2024-05-01"),
+            }])
+        );
+    }
+
+    #[test]
+    fn test_parse_w3c_datetime() {
+        let at = |seconds| Some(UNIX_EPOCH + Duration::from_secs(seconds));
+
+        assert_eq!(parse_w3c_datetime("1970-01-02"), at(86400));
+        assert_eq!(parse_w3c_datetime("2000-03-01"), at(951868800));
+        assert_eq!(parse_w3c_datetime("2024-05-01T10:30:00Z"), at(1714559400));
+        assert_eq!(
+            parse_w3c_datetime("2024-05-01T12:30:00+02:00"),
+            at(1714559400)
+        );
+        assert_eq!(parse_w3c_datetime("2024-05-01T05:30-05:00"), at(1714559400));
+        assert_eq!(
+            parse_w3c_datetime("2024-05-01T10:30:00.25Z"),
+            at(1714559400)
+        );
+        assert_eq!(parse_w3c_datetime("2024"), parse_w3c_datetime("2024-01-01"));
+
+        assert_eq!(parse_w3c_datetime("2024-13-01"), None);
+        assert_eq!(parse_w3c_datetime("2024-05-01T10:30:00"), None);
+        assert_eq!(parse_w3c_datetime("yesterday"), None);
+    }
+
+    #[tokio::test]
+    async fn test_collect_gzipped_sitemap_index() {
+        let mock_server = MockServer::start().await;
+        let uri = mock_server.uri();
+
+        let index = format!(
+            r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <sitemap><loc>{uri}/pages.xml.gz</loc></sitemap>
+              <sitemap><loc>{uri}/missing.xml</loc></sitemap>
+            </sitemapindex>"#
+        );
+        let pages = format!(
+            r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url><loc>{uri}/one.html</loc></url>
+              <url><loc>{uri}/two.html</loc></url>
+            </urlset>"#
+        );
+        mount_page(
+            &mock_server,
+            "/sitemap.xml",
+            index.into_bytes(),
+            "application/xml",
+        )
+        .await;
+        mount_page(
+            &mock_server,
+            "/pages.xml.gz",
+            gzip(&pages).await,
+            "application/gzip",
+        )
+        .await;
+
+        // The missing sitemap is skipped, and the rest are still read
+        let entries = collect_urls(&Downloader::new(), &format!("{}/sitemap.xml", uri))
+            .await
+            .unwrap();
+        let urls: Vec<&str> = entries.iter().map(|entry| entry.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [format!("{}/one.html", uri), format!("{}/two.html", uri)]
+        );
+
+        let missing = collect_urls(&Downloader::new(), &format!("{}/missing.xml", uri)).await;
+        assert!(missing.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_gzip_bomb() {
+        let mock_server = MockServer::start().await;
+        let bomb = gzip(&"0".repeat(60 * 1024 * 1024)).await;
+        assert!(bomb.len() < 1024 * 1024);
+        mount_page(&mock_server, "/sitemap.xml.gz", bomb, "application/gzip").await;
+
+        let error = collect_urls(
+            &Downloader::new(),
+            &format!("{}/sitemap.xml.gz", mock_server.uri()),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("once decompressed"));
+    }
+
+    #[tokio::test]
+    async fn test_discover_sitemaps_from_robots() {
+        let robots = RobotsTxt::parse(
+            "Sitemap: https://example.com/sitemap.xml\n\
+             User-agent: *\n\
+             Disallow: /private/\n\
+             Sitemap: https://example.com/news.xml\n",
+            "nget",
+        );
+        assert_eq!(
+            robots.sitemaps(),
+            [
+                "https://example.com/sitemap.xml",
+                "https://example.com/news.xml"
+            ]
+        );
+        assert!(!robots.is_allowed(&Url::parse("https://example.com/private/x").unwrap()));
+
+        let mock_server = MockServer::start().await;
+        let robots_txt = format!("Sitemap: {}/sitemap.xml\n", mock_server.uri());
+        mount_page(
+            &mock_server,
+            "/robots.txt",
+            robots_txt.into_bytes(),
+            "text/plain",
+        )
+        .await;
+
+        let urls = [
+            Url::parse(&format!("{}/a.html", mock_server.uri())).unwrap(),
+            Url::parse(&format!("{}/b.html", mock_server.uri())).unwrap(),
+        ];
+        let sitemaps = discover_sitemaps(&Downloader::new(), &urls).await;
+        assert_eq!(sitemaps, [format!("{}/sitemap.xml", mock_server.uri())]);
+    }
+
+    #[tokio::test]
+    async fn test_is_unmodified() {
+        let save_dir = "./test_output_sitemap_lastmod";
+        fs::create_dir_all(save_dir).await.unwrap();
+        let local = format!("{}/page.html", save_dir);
+        fs::write(&local, "Saved").await.unwrap();
+
+        let Sitemap::Urls(entries) = Sitemap::parse(
+            br#"<urlset>
+              <url><loc>https://example.com/old</loc><lastmod>2001-01-01</lastmod></url>
+              <url><loc>https://example.com/new</loc><lastmod>2999-01-01</lastmod></url>
+              <url><loc>https://example.com/unknown</loc></url>
+            </urlset>"#,
+        )
+        .unwrap() else {
+            panic!("expected a urlset");
+        };
+
+        let local = std::path::Path::new(&local);
+        assert!(entries[0].is_unmodified(local));
+        assert!(!entries[1].is_unmodified(local));
+        assert!(!entries[2].is_unmodified(local));
+        assert!(!entries[0].is_unmodified(std::path::Path::new("./no_such_file")));
+        assert!(entries[0].lastmod < Some(SystemTime::now()));
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+mod common;
+
+#[cfg(test)]
+mod spider_tests {
+    use crate::common::mount_page;
+
+    use nget::crawler::Crawler;
+    use nget::http::probe;
+    use nget::Downloader;
+
+    use reqwest::StatusCode;
+
+    use std::path::Path;
+
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_spider_reports_broken_links() {
+        let mock_server = MockServer::start().await;
+
+        mount_page(
+            &mock_server,
+            "/",
+            r#"<a href="/moved">Moved</a> <a href="/missing.html">Missing</a>
+            <img src="/logo.png">"#,
+            "text/html",
+        )
+        .await;
+        Mock::given(method("GET"))
+            .and(path("/moved"))
+            .respond_with(
+                ResponseTemplate::new(301)
+                    .insert_header("Location", format!("{}/new.html", mock_server.uri())),
+            )
+            .mount(&mock_server)
+            .await;
+        mount_page(&mock_server, "/new.html", "New page", "text/html").await;
+        mount_page(&mock_server, "/logo.png", "png", "image/png").await;
+        Mock::given(method("GET"))
+            .and(path("/missing.html"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        let start = format!("{}/", mock_server.uri());
+        let entries = Crawler::new(Downloader::new())
+            .level(Some(1))
+            .page_requisites(true)
+            .spider(std::slice::from_ref(&start))
+            .await;
+
+        let urls: Vec<&str> = entries
+            .iter()
+            .map(|entry| entry.url.trim_start_matches(&mock_server.uri()))
+            .collect();
+        assert_eq!(urls, ["/", "/moved", "/missing.html", "/logo.png"]);
+
+        let moved = entries[1].result.as_ref().unwrap();
+        assert_eq!(moved.status, StatusCode::OK);
+        assert_eq!(moved.final_url, format!("{}/new.html", mock_server.uri()));
+        assert!(!moved.is_broken());
+
+        // Broken links keep the page they were found on, and are reported rather than failing
+        let missing = &entries[2];
+        assert_eq!(missing.referrer.as_deref(), Some(start.as_str()));
+        let probe = missing.result.as_ref().unwrap();
+        assert_eq!(probe.status, StatusCode::NOT_FOUND);
+        assert!(probe.is_broken());
+        assert!(probe.error.is_some());
+
+        let logo = entries[3].result.as_ref().unwrap();
+        assert_eq!(logo.content_type.as_deref(), Some("image/png"));
+        assert_eq!(logo.size, Some(3));
+
+        // Nothing is saved
+        let host = format!("127.0.0.1:{}", mock_server.address().port());
+        assert!(!Path::new(&host).exists());
+        assert!(!Path::new("logo.png").exists());
+    }
+
+    #[tokio::test]
+    async fn test_probe() {
+        let mock_server = MockServer::start().await;
+
+        mount_page(
+            &mock_server,
+            "/data.json",
+            r#"{"a": 1}"#,
+            "application/json",
+        )
+        .await;
+        Mock::given(method("GET"))
+            .and(path("/broken"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+
+        let downloader = Downloader::new();
+        let found = probe(&downloader, &format!("{}/data.json", mock_server.uri()))
+            .await
+            .unwrap();
+        assert_eq!(found.status, StatusCode::OK);
+        assert_eq!(found.content_type.as_deref(), Some("application/json"));
+        assert_eq!(found.size, Some(8));
+        assert!(found.error.is_none());
+
+        let broken = probe(&downloader, &format!("{}/broken", mock_server.uri()))
+            .await
+            .unwrap();
+        assert_eq!(broken.status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(broken.is_broken());
+
+        // Connection failures are still errors
+        assert!(probe(&downloader, "http://127.0.0.1:1/").await.is_err());
+    }
+}
@@ -0,0 +1,317 @@
+mod common;
+
+#[cfg(test)]
+mod tls_tests {
+    use crate::common::{generate_pki, start_https_server};
+
+    use nget::enums::{Compression, HttpVersion};
+    use nget::error::NgetError;
+    use nget::http::download_file;
+    use nget::progress::SilentObserver;
+    use nget::proxy_utils::ProxyConfig;
+    use nget::tls_utils::{spki_sha256_pin, TlsConfig};
+
+    use std::net::SocketAddr;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    async fn download(
+        addr: SocketAddr,
+        save_dir: &str,
+        tls_config: &TlsConfig,
+    ) -> Result<(), NgetError> {
+        let url = format!("https://localhost:{}/securefile", addr.port());
+        let output_file_name = None;
+
+        download_file(
+            &url,
+            save_dir,
+            &output_file_name,
+            Arc::new(SilentObserver),
+            &HttpVersion::Http11,
+            &ProxyConfig::empty(),
+            tls_config,
+            &Compression::Auto,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_private_ca_certificate() {
+        let pki = generate_pki();
+        let addr = start_https_server(&pki, "Secure content", false).await;
+
+        let save_dir = "./test_output_tls_ca";
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+        let ca_file = Path::new(save_dir).join("ca.pem");
+        tokio::fs::write(&ca_file, &pki.ca_pem).await.unwrap();
+
+        let tls_config = TlsConfig {
+            ca_certificate: Some(ca_file.display().to_string()),
+            ..Default::default()
+        };
+
+        let result = download(addr, save_dir, &tls_config).await;
+        assert!(result.is_ok(), "TLS request failed: {:?}", result.err());
+
+        let content = tokio::fs::read_to_string(Path::new(save_dir).join("securefile"))
+            .await
+            .unwrap();
+        assert_eq!(content, "Secure content");
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_private_ca_directory() {
+        let pki = generate_pki();
+        let addr = start_https_server(&pki, "Directory CA content", false).await;
+
+        let save_dir = "./test_output_tls_ca_dir";
+        let ca_dir = Path::new(save_dir).join("certs");
+        tokio::fs::create_dir_all(&ca_dir).await.unwrap();
+        tokio::fs::write(ca_dir.join("ca.crt"), &pki.ca_pem)
+            .await
+            .unwrap();
+        tokio::fs::write(ca_dir.join("README"), "not a certificate")
+            .await
+            .unwrap();
+
+        let tls_config = TlsConfig {
+            ca_directory: Some(ca_dir.display().to_string()),
+            ..Default::default()
+        };
+
+        let result = download(addr, save_dir, &tls_config).await;
+        assert!(result.is_ok(), "TLS request failed: {:?}", result.err());
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_certificate() {
+        let pki = generate_pki();
+        let addr = start_https_server(&pki, "Secure content", false).await;
+
+        let save_dir = "./test_output_tls_untrusted";
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+
+        let result = download(addr, save_dir, &TlsConfig::empty()).await;
+
+        // The private CA is not trusted, so the handshake must fail
+        assert!(
+            matches!(result, Err(NgetError::TlsError(_))),
+            "Expected a TLS error, got {:?}",
+            result
+        );
+        assert!(!Path::new(save_dir).join("securefile").exists());
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_no_check_certificate() {
+        let pki = generate_pki();
+        let addr = start_https_server(&pki, "Insecure content", false).await;
+
+        let save_dir = "./test_output_tls_insecure";
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+
+        let tls_config = TlsConfig {
+            no_check_certificate: true,
+            ..Default::default()
+        };
+
+        let result = download(addr, save_dir, &tls_config).await;
+        assert!(result.is_ok(), "TLS request failed: {:?}", result.err());
+
+        let content = tokio::fs::read_to_string(Path::new(save_dir).join("securefile"))
+            .await
+            .unwrap();
+        assert_eq!(content, "Insecure content");
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_certificate() {
+        let pki = generate_pki();
+        let addr = start_https_server(&pki, "Mutual TLS content", true).await;
+
+        let save_dir = "./test_output_tls_client_cert";
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+        let ca_file = Path::new(save_dir).join("ca.pem");
+        let cert_file = Path::new(save_dir).join("client.pem");
+        let key_file = Path::new(save_dir).join("client.key");
+        tokio::fs::write(&ca_file, &pki.ca_pem).await.unwrap();
+        tokio::fs::write(&cert_file, &pki.client_cert_pem)
+            .await
+            .unwrap();
+        tokio::fs::write(&key_file, &pki.client_key_pem)
+            .await
+            .unwrap();
+
+        let tls_config = TlsConfig {
+            ca_certificate: Some(ca_file.display().to_string()),
+            certificate: Some(cert_file.display().to_string()),
+            private_key: Some(key_file.display().to_string()),
+            ..Default::default()
+        };
+
+        let result = download(addr, save_dir, &tls_config).await;
+        assert!(
+            result.is_ok(),
+            "Mutual TLS request failed: {:?}",
+            result.err()
+        );
+
+        let content = tokio::fs::read_to_string(Path::new(save_dir).join("securefile"))
+            .await
+            .unwrap();
+        assert_eq!(content, "Mutual TLS content");
+
+        // Without the client certificate the server rejects the handshake
+        tokio::fs::remove_file(Path::new(save_dir).join("securefile"))
+            .await
+            .unwrap();
+        let tls_config = TlsConfig {
+            ca_certificate: Some(ca_file.display().to_string()),
+            ..Default::default()
+        };
+        let result = download(addr, save_dir, &tls_config).await;
+        assert!(result.is_err());
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_missing_ca_certificate() {
+        let save_dir = "./test_output_tls_missing_ca";
+        let addr: SocketAddr = "127.0.0.1:9".parse().unwrap();
+
+        let tls_config = TlsConfig {
+            ca_certificate: Some("./does-not-exist.pem".to_string()),
+            ..Default::default()
+        };
+
+        let result = download(addr, save_dir, &tls_config).await;
+        assert!(matches!(result, Err(NgetError::TlsError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_pinned_pubkey_match() {
+        let pki = generate_pki();
+        let addr = start_https_server(&pki, "Pinned content", false).await;
+
+        let save_dir = "./test_output_tls_pin_match";
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+        let ca_file = Path::new(save_dir).join("ca.pem");
+        tokio::fs::write(&ca_file, &pki.ca_pem).await.unwrap();
+
+        // Pinning the leaf key
+        let tls_config = TlsConfig {
+            ca_certificate: Some(ca_file.display().to_string()),
+            pinned_pubkeys: vec![
+                "sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_string(),
+                spki_sha256_pin(&pki.server_cert).unwrap(),
+            ],
+            ..Default::default()
+        };
+
+        let result = download(addr, save_dir, &tls_config).await;
+        assert!(result.is_ok(), "Pinned request failed: {:?}", result.err());
+
+        let content = tokio::fs::read_to_string(Path::new(save_dir).join("securefile"))
+            .await
+            .unwrap();
+        assert_eq!(content, "Pinned content");
+        tokio::fs::remove_file(Path::new(save_dir).join("securefile"))
+            .await
+            .unwrap();
+
+        // Pinning the CA key somewhere in the chain
+        let tls_config = TlsConfig {
+            ca_certificate: Some(ca_file.display().to_string()),
+            pinned_pubkeys: vec![spki_sha256_pin(&pki.ca_der).unwrap()],
+            ..Default::default()
+        };
+
+        let result = download(addr, save_dir, &tls_config).await;
+        assert!(result.is_ok(), "Pinned request failed: {:?}", result.err());
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pinned_pubkey_mismatch() {
+        let pki = generate_pki();
+        let other_pki = generate_pki();
+        let addr = start_https_server(&pki, "Pinned content", false).await;
+
+        let save_dir = "./test_output_tls_pin_mismatch";
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+        let ca_file = Path::new(save_dir).join("ca.pem");
+        tokio::fs::write(&ca_file, &pki.ca_pem).await.unwrap();
+
+        let tls_config = TlsConfig {
+            ca_certificate: Some(ca_file.display().to_string()),
+            pinned_pubkeys: vec![spki_sha256_pin(&other_pki.server_cert).unwrap()],
+            ..Default::default()
+        };
+
+        let result = download(addr, save_dir, &tls_config).await;
+        assert!(
+            matches!(result, Err(NgetError::TlsError(_))),
+            "Expected a TLS error, got {:?}",
+            result
+        );
+        assert!(!Path::new(save_dir).join("securefile").exists());
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pinned_pubkey_without_chain_validation() {
+        let pki = generate_pki();
+        let other_pki = generate_pki();
+        let addr = start_https_server(&pki, "Pinned insecure content", false).await;
+
+        let save_dir = "./test_output_tls_pin_insecure";
+        tokio::fs::create_dir_all(save_dir).await.unwrap();
+
+        // The CA is not trusted, but the pin still decides
+        let tls_config = TlsConfig {
+            no_check_certificate: true,
+            pinned_pubkeys: vec![spki_sha256_pin(&pki.server_cert).unwrap()],
+            ..Default::default()
+        };
+        let result = download(addr, save_dir, &tls_config).await;
+        assert!(result.is_ok(), "Pinned request failed: {:?}", result.err());
+
+        let tls_config = TlsConfig {
+            no_check_certificate: true,
+            pinned_pubkeys: vec![spki_sha256_pin(&other_pki.server_cert).unwrap()],
+            ..Default::default()
+        };
+        let result = download(addr, save_dir, &tls_config).await;
+        assert!(matches!(result, Err(NgetError::TlsError(_))));
+
+        tokio::fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_pinned_pubkey() {
+        let save_dir = "./test_output_tls_pin_invalid";
+        let addr: SocketAddr = "127.0.0.1:9".parse().unwrap();
+
+        for pin in ["md5//AAAA", "sha256//not base64!", "sha256//AAAA"] {
+            let tls_config = TlsConfig {
+                pinned_pubkeys: vec![pin.to_string()],
+                ..Default::default()
+            };
+
+            let result = download(addr, save_dir, &tls_config).await;
+            assert!(matches!(result, Err(NgetError::TlsError(_))), "{}", pin);
+        }
+    }
+}
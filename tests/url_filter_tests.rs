@@ -0,0 +1,172 @@
+#[cfg(test)]
+mod url_filter_tests {
+    use nget::crawler::Crawler;
+    use nget::error::NgetError;
+    use nget::url_filter::{glob_match, may_be_html, UrlFilter};
+    use nget::Downloader;
+
+    use std::path::Path;
+
+    use tokio::fs;
+
+    use url::Url;
+
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.pdf", "report.pdf"));
+        assert!(!glob_match("*.pdf", "report.pdf.html"));
+        assert!(glob_match("img-??.png", "img-01.png"));
+        assert!(glob_match("file[0-9].txt", "file7.txt"));
+        assert!(!glob_match("file[!0-9].txt", "file7.txt"));
+        assert!(glob_match("*a*b*", "xxaxxbxx"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn test_accept_and_reject_file_names() {
+        let filter = UrlFilter::new()
+            .accept(strings(&["pdf", "*.zip"]))
+            .reject(strings(&["draft-*"]));
+
+        assert!(filter.accepts_file_name(&url("https://example.com/docs/report.pdf")));
+        assert!(filter.accepts_file_name(&url("https://example.com/release.zip")));
+        assert!(!filter.accepts_file_name(&url("https://example.com/draft-report.pdf")));
+        assert!(!filter.accepts_file_name(&url("https://example.com/index.html")));
+
+        assert!(may_be_html(&url("https://example.com/docs/")));
+        assert!(may_be_html(&url("https://example.com/search.php?q=1")));
+        assert!(!may_be_html(&url("https://example.com/report.pdf")));
+    }
+
+    #[test]
+    fn test_regex_filters() {
+        let filter = UrlFilter::new()
+            .accept_regex(r"/docs/")
+            .unwrap()
+            .reject_regex(r"\?print=1$")
+            .unwrap();
+
+        assert!(filter.allows(&url("https://example.com/docs/a.html"), &[]));
+        assert!(!filter.allows(&url("https://example.com/blog/a.html"), &[]));
+        assert!(!filter.allows(&url("https://example.com/docs/a.html?print=1"), &[]));
+
+        assert!(matches!(
+            UrlFilter::new().accept_regex("("),
+            Err(NgetError::ConfigurationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_domain_filters() {
+        let filter = UrlFilter::new()
+            .domains(strings(&["example.com"]))
+            .exclude_domains(strings(&["ads.example.com"]));
+
+        assert!(filter.allows(&url("https://example.com/"), &[]));
+        assert!(filter.allows(&url("https://cdn.example.com/a.js"), &[]));
+        assert!(!filter.allows(&url("https://notexample.com/"), &[]));
+        assert!(!filter.allows(&url("https://ads.example.com/banner.gif"), &[]));
+
+        // Listing domains lets a crawl leave the start hosts
+        assert!(filter.spans_to(&url("https://cdn.example.com/"), &[]));
+        assert!(!UrlFilter::new()
+            .spans_to(&url("https://cdn.example.com/"), &strings(&["example.com"])));
+    }
+
+    #[test]
+    fn test_directory_filters() {
+        let filter = UrlFilter::new()
+            .include_directories(strings(&["/docs", "/release-*"]))
+            .exclude_directories(strings(&["/docs/old"]));
+
+        assert!(filter.allows(&url("https://example.com/docs/a.html"), &[]));
+        assert!(filter.allows(&url("https://example.com/docs/sub/b.html"), &[]));
+        assert!(filter.allows(&url("https://example.com/release-1.0/nget.zip"), &[]));
+        assert!(!filter.allows(&url("https://example.com/docsx/a.html"), &[]));
+        assert!(!filter.allows(&url("https://example.com/index.html"), &[]));
+        assert!(!filter.allows(&url("https://example.com/docs/old/a.html"), &[]));
+    }
+
+    #[test]
+    fn test_no_parent() {
+        let filter = UrlFilter::new().no_parent(true);
+        let start = [url("https://example.com/docs/guide/index.html")];
+
+        assert!(filter.allows(&url("https://example.com/docs/guide/install.html"), &start));
+        assert!(!filter.allows(&url("https://example.com/docs/index.html"), &start));
+        // Other hosts aren't restricted
+        assert!(filter.allows(&url("https://cdn.example.com/site.css"), &start));
+    }
+
+    #[tokio::test]
+    async fn test_filtered_crawl() {
+        let mock_server = MockServer::start().await;
+
+        let pages = [
+            (
+                "/files/",
+                r#"<a href="a.pdf">A</a> <a href="b.txt">B</a> <a href="../">Up</a>
+                <a href="more/">More</a>"#,
+            ),
+            ("/files/more/", r#"<a href="c.pdf">C</a>"#),
+            ("/", "Parent"),
+        ];
+        for (route, body) in pages {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/html"))
+                .mount(&mock_server)
+                .await;
+        }
+        for route in ["/files/a.pdf", "/files/b.txt", "/files/more/c.pdf"] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_string("data"))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let save_dir = "./test_output_filtered_crawl";
+        let filter = UrlFilter::new().accept(strings(&["*.pdf"])).no_parent(true);
+        let entries = Crawler::new(Downloader::new())
+            .output_dir(save_dir)
+            .filter(filter)
+            .crawl(&[format!("{}/files/", mock_server.uri())])
+            .await;
+
+        let urls: Vec<&str> = entries
+            .iter()
+            .map(|entry| entry.url.trim_start_matches(&mock_server.uri()))
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "/files/",
+                "/files/a.pdf",
+                "/files/more/",
+                "/files/more/c.pdf"
+            ]
+        );
+
+        // Index pages were only fetched for their links
+        let port = Url::parse(&mock_server.uri()).unwrap().port().unwrap();
+        let host_dir = Path::new(save_dir).join(format!("127.0.0.1:{}", port));
+        assert!(host_dir.join("files/a.pdf").exists());
+        assert!(host_dir.join("files/more/c.pdf").exists());
+        assert!(!host_dir.join("files/index.html").exists());
+        assert!(!host_dir.join("files/more/index.html").exists());
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+#[cfg(test)]
+mod webdav_tests {
+    use nget::crawler::Crawler;
+    use nget::webdav::{is_dav_url, list_collection, parse_multistatus, to_http_url};
+    use nget::Downloader;
+
+    use std::path::Path;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use tokio::fs;
+
+    use url::Url;
+
+    use wiremock::matchers::{body_string_contains, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Builds a multistatus listing the given responses, each a (href, properties) pair.
+    fn multistatus(responses: &[(&str, &str)]) -> String {
+        let responses: String = responses
+            .iter()
+            .map(|(href, props)| {
+                format!(
+                    "<D:response><D:href>{}</D:href><D:propstat><D:prop>{}</D:prop>\
+                    <D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+                    href, props
+                )
+            })
+            .collect();
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?><D:multistatus xmlns:D="DAV:">{}</D:multistatus>"#,
+            responses
+        )
+    }
+
+    const COLLECTION: &str = "<D:resourcetype><D:collection/></D:resourcetype>";
+
+    async fn mount_collection(mock_server: &MockServer, route: &str, body: String) {
+        Mock::given(method("PROPFIND"))
+            .and(path(route))
+            .and(header("Depth", "1"))
+            .and(body_string_contains("getlastmodified"))
+            .respond_with(ResponseTemplate::new(207).set_body_raw(body, "application/xml"))
+            .mount(mock_server)
+            .await;
+    }
+
+    #[test]
+    fn test_parse_multistatus() {
+        let base = Url::parse("https://example.com/dav/builds/").unwrap();
+        let content = multistatus(&[
+            ("/dav/builds/", COLLECTION),
+            ("/dav/builds/nightly", COLLECTION),
+            (
+                "/dav/builds/app%201.0.zip",
+                "<D:resourcetype/><D:getcontentlength>1024</D:getcontentlength>\
+                <D:getetag>\"abc\"</D:getetag>\
+                <D:getlastmodified>Wed, 01 May 2024 10:30:00 GMT</D:getlastmodified>",
+            ),
+        ]);
+
+        let resources = parse_multistatus(content.as_bytes(), &base).unwrap();
+        assert_eq!(resources.len(), 3);
+
+        // Collections always end with a slash
+        assert_eq!(
+            resources[1].url.as_str(),
+            "https://example.com/dav/builds/nightly/"
+        );
+        assert!(resources[1].is_collection);
+
+        let file = &resources[2];
+        assert_eq!(
+            file.url.as_str(),
+            "https://example.com/dav/builds/app%201.0.zip"
+        );
+        assert!(!file.is_collection);
+        assert_eq!(file.size, Some(1024));
+        assert_eq!(
+            file.modified,
+            Some(UNIX_EPOCH + Duration::from_secs(1714559400))
+        );
+    }
+
+    #[test]
+    fn test_dav_urls() {
+        assert!(is_dav_url("dav://example.com/share/"));
+        assert!(is_dav_url("DAVS://example.com/share/"));
+        assert!(!is_dav_url("https://example.com/share/"));
+
+        assert_eq!(
+            to_http_url("dav://example.com:8080/share/"),
+            "http://example.com:8080/share/"
+        );
+        assert_eq!(
+            to_http_url("davs://example.com/share/"),
+            "https://example.com/share/"
+        );
+        assert_eq!(to_http_url("https://example.com/"), "https://example.com/");
+    }
+
+    #[tokio::test]
+    async fn test_webdav_recursive_download() {
+        let mock_server = MockServer::start().await;
+
+        mount_collection(
+            &mock_server,
+            "/share/",
+            multistatus(&[
+                ("/share/", COLLECTION),
+                ("/share/docs/", COLLECTION),
+                (
+                    "/share/build.tar",
+                    "<D:resourcetype/><D:getcontentlength>5</D:getcontentlength>",
+                ),
+            ]),
+        )
+        .await;
+        mount_collection(
+            &mock_server,
+            "/share/docs/",
+            multistatus(&[
+                ("/share/docs/", COLLECTION),
+                ("/share/docs/readme.md", "<D:resourcetype/>"),
+            ]),
+        )
+        .await;
+        for (route, body) in [
+            ("/share/build.tar", "build"),
+            ("/share/docs/readme.md", "# Docs"),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_string(body))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let downloader = Downloader::new();
+        let share = Url::parse(&format!("{}/share/", mock_server.uri())).unwrap();
+        let members = list_collection(&downloader, &share).await.unwrap();
+        assert_eq!(members.len(), 2);
+
+        // The trailing slash is added rather than left to a redirect
+        let share = Url::parse(&format!("{}/share", mock_server.uri())).unwrap();
+        assert_eq!(list_collection(&downloader, &share).await.unwrap(), members);
+
+        let save_dir = "./test_output_webdav";
+        let seed = to_http_url(&mock_server.uri().replacen("http", "dav", 1)) + "/share";
+        let entries = Crawler::new(downloader)
+            .output_dir(save_dir)
+            .webdav(true)
+            .robots(false)
+            .crawl_listing(&[seed])
+            .await;
+
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| entry.result.is_ok()));
+
+        let host_dir =
+            Path::new(save_dir).join(format!("127.0.0.1:{}", mock_server.address().port()));
+        assert_eq!(
+            fs::read_to_string(host_dir.join("share/build.tar"))
+                .await
+                .unwrap(),
+            "build"
+        );
+        assert_eq!(
+            fs::read_to_string(host_dir.join("share/docs/readme.md"))
+                .await
+                .unwrap(),
+            "# Docs"
+        );
+
+        fs::remove_dir_all(save_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_redirected_collection() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("PROPFIND"))
+            .and(path("/old/"))
+            .respond_with(
+                ResponseTemplate::new(301)
+                    .insert_header("Location", format!("{}/share/", mock_server.uri())),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/share/"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw("<html>Share</html>", "text/html"),
+            )
+            .mount(&mock_server)
+            .await;
+
+        // Following the redirect turns the PROPFIND into a GET, which can't list anything
+        let old = Url::parse(&format!("{}/old/", mock_server.uri())).unwrap();
+        let error = list_collection(&Downloader::new(), &old).await.unwrap_err();
+        assert_eq!(error.kind(), "HttpRequest");
+    }
+}